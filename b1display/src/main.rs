@@ -266,6 +266,8 @@ fn main() -> ! {
 
     let mut logo_pos = Point::new(LOGO_OFFSET_X, LOGO_OFFSET_Y);
 
+    let mut frame_decoder = FrameDecoder::new();
+
     loop {
         // Go to sleep if the host is sleeping
         let host_sleeping = sleep.is_low().unwrap();
@@ -333,6 +335,9 @@ fn main() -> ! {
             }
         }
 
+        // Drop a frame that was cut off, the host gave up on it
+        frame_decoder.expire(timer.get_counter().ticks());
+
         // Check for new data
         if usb_dev.poll(&mut [&mut serial]) {
             let mut buf = [0u8; 64];
@@ -344,37 +349,59 @@ fn main() -> ! {
                     // Do nothing
                 }
                 Ok(count) => {
-                    match (parse_command(count, &buf), &state.sleeping) {
-                        (Some(Command::Sleep(go_sleeping)), _) => {
-                            handle_sleep(go_sleeping, &mut state, &mut delay, &mut disp);
-                        }
-                        (Some(c @ Command::BootloaderReset), _)
-                        | (Some(c @ Command::IsSleeping), _) => {
-                            if let Some(response) =
-                                handle_command(&c, &mut state, logo_rect, &mut disp, &mut delay)
-                            {
-                                let _ = serial.write(&response);
-                            };
-                        }
-                        (Some(command), SimpleSleepState::Awake) => {
-                            // While sleeping no command is handled, except waking up
-                            if let Some(response) = handle_command(
-                                &command, &mut state, logo_rect, &mut disp, &mut delay,
-                            ) {
-                                let _ = serial.write(&response);
-                            };
-                            // Must write AFTER writing response, otherwise the
-                            // client interprets this debug message as the response
-                            //let mut text: String<64> = String::new();
-                            //write!(
-                            //    &mut text,
-                            //    "Handled command {}:{}:{}:{}\r\n",
-                            //    buf[0], buf[1], buf[2], buf[3]
-                            //)
-                            //.unwrap();
-                            //let _ = serial.write(text.as_bytes());
+                    frame_decoder.push_at(&buf[..count], timer.get_counter().ticks());
+                    while let Some(received) = frame_decoder.next_received() {
+                        let (reply, command) = received.into_command();
+                        let mut reply_buf = [0u8; MAX_FRAME_SIZE];
+                        match (command, &state.sleeping) {
+                            (Ok(Command::Sleep(go_sleeping)), _) => {
+                                handle_sleep(go_sleeping, &mut state, &mut delay, &mut disp);
+                                let len = reply.ack(None, &mut reply_buf);
+                                let _ = serial.write(&reply_buf[..len]);
+                            }
+                            (Ok(c @ Command::BootloaderReset), _)
+                            | (Ok(c @ Command::IsSleeping), _) => {
+                                let mut handler = B1DisplayHandler {
+                                    state: &mut state,
+                                    logo_rect,
+                                    disp: &mut disp,
+                                    delay: &mut delay,
+                                };
+                                let result = handler.handle_command(&c);
+                                let len = reply.respond(&result, &mut reply_buf);
+                                let _ = serial.write(&reply_buf[..len]);
+                            }
+                            (Ok(command), SimpleSleepState::Awake) => {
+                                // While sleeping no command is handled, except waking up
+                                let mut handler = B1DisplayHandler {
+                                    state: &mut state,
+                                    logo_rect,
+                                    disp: &mut disp,
+                                    delay: &mut delay,
+                                };
+                                let result = handler.handle_command(&command);
+                                let len = reply.respond(&result, &mut reply_buf);
+                                let _ = serial.write(&reply_buf[..len]);
+                                // Must write AFTER writing response, otherwise the
+                                // client interprets this debug message as the response
+                                //let mut text: String<64> = String::new();
+                                //write!(
+                                //    &mut text,
+                                //    "Handled command {}:{}:{}:{}\r\n",
+                                //    buf[0], buf[1], buf[2], buf[3]
+                                //)
+                                //.unwrap();
+                                //let _ = serial.write(text.as_bytes());
+                            }
+                            (Ok(_), SimpleSleepState::Sleeping) => {
+                                let len = reply.nak(ErrorCode::Sleeping, &mut reply_buf);
+                                let _ = serial.write(&reply_buf[..len]);
+                            }
+                            (Err(code), _) => {
+                                let len = reply.nak(code, &mut reply_buf);
+                                let _ = serial.write(&reply_buf[..len]);
+                            }
                         }
-                        _ => {}
                     }
                 }
             }
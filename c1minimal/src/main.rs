@@ -152,6 +152,8 @@ fn main() -> ! {
         ))
         .unwrap();
 
+    let mut frame_decoder = FrameDecoder::new();
+
     loop {
         // Go to sleep if the host is sleeping
         let host_sleeping = sleep.is_low().unwrap();
@@ -163,6 +165,9 @@ fn main() -> ! {
             prev_timer = timer.get_counter().ticks();
         }
 
+        // Drop a frame that was cut off, the host gave up on it
+        frame_decoder.expire(timer.get_counter().ticks());
+
         // Check for new data
         if usb_dev.poll(&mut [&mut serial]) {
             let mut buf = [0u8; 64];
@@ -174,17 +179,28 @@ fn main() -> ! {
                     // Do nothing
                 }
                 Ok(count) => {
-                    if let Some(command) = parse_command(count, &buf) {
-                        if let Command::Sleep(go_sleeping) = command {
-                            handle_sleep(go_sleeping, &mut state, &mut delay, &mut ws2812);
-                        } else if let SimpleSleepState::Awake = state.sleeping {
+                    frame_decoder.push_at(&buf[..count], timer.get_counter().ticks());
+                    while let Some(received) = frame_decoder.next_received() {
+                        let (reply, command) = received.into_command();
+                        let mut reply_buf = [0u8; MAX_FRAME_SIZE];
+                        let len = match command {
+                            Ok(Command::Sleep(go_sleeping)) => {
+                                handle_sleep(go_sleeping, &mut state, &mut delay, &mut ws2812);
+                                reply.ack(None, &mut reply_buf)
+                            }
                             // While sleeping no command is handled, except waking up
-                            if let Some(response) =
-                                handle_command(&command, &mut state, &mut ws2812)
-                            {
-                                let _ = serial.write(&response);
-                            };
-                        }
+                            Ok(command) if matches!(state.sleeping, SimpleSleepState::Awake) => {
+                                let mut handler = C1MinimalHandler {
+                                    state: &mut state,
+                                    ws2812: &mut ws2812,
+                                };
+                                let result = handler.handle_command(&command);
+                                reply.respond(&result, &mut reply_buf)
+                            }
+                            Ok(_) => reply.nak(ErrorCode::Sleeping, &mut reply_buf),
+                            Err(code) => reply.nak(code, &mut reply_buf),
+                        };
+                        let _ = serial.write(&reply_buf[..len]);
                     }
                 }
             }
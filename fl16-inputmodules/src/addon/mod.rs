@@ -1,7 +1,7 @@
 use crate::addon::vector2::Vector2;
 use crate::matrix::{Grid, LedmatrixState, Side, HEIGHT, WIDTH};
-use core::f32::consts::PI;
-use num_traits::clamp;
+use core::f32::consts::{PI, TAU};
+pub use inputmodule_protocol::AddonAnimationVals;
 
 pub mod vector2;
 
@@ -19,12 +19,6 @@ pub enum AddonAnimation {
     Splashes,
     Helix,
 }
-#[derive(num_derive::FromPrimitive)]
-pub enum AddonAnimationVals {
-    Spiral = 0x00,
-    Splashes = 0x01,
-    Helix = 0x02,
-}
 
 #[derive(Copy, Clone)]
 pub struct CachedUV {
@@ -72,14 +66,14 @@ pub fn draw_addon_animation(state: &LedmatrixState, addon_animation: &AddonAnima
     grid
 }
 
-pub fn spiral(state: &LedmatrixState, uv: Vector2, uv_centered: Vector2, time: f32) -> f32 {
+pub fn spiral(_state: &LedmatrixState, _uv: Vector2, uv_centered: Vector2, time: f32) -> f32 {
     const RAD: f32 = 5.0;
     let len = uv_centered.length();
     let angle = libm::atan2f(uv_centered.y, uv_centered.x);
     sin_full(angle + len * RAD - time * 0.1)
 }
 
-pub fn splashes(state: &LedmatrixState, uv: Vector2, uv_centered: Vector2, time: f32) -> f32 {
+pub fn splashes(state: &LedmatrixState, _uv: Vector2, uv_centered: Vector2, time: f32) -> f32 {
     let mut ret: f32 = 0.0;
     for keypress in state.visual_keypresses.iter()
     {
@@ -104,31 +98,31 @@ pub fn splashes(state: &LedmatrixState, uv: Vector2, uv_centered: Vector2, time:
     ret
 }
 
-pub fn helix(state: &LedmatrixState, mut uv: Vector2, uv_centered: Vector2, time: f32) -> f32 {
+pub fn helix(_state: &LedmatrixState, mut uv: Vector2, _uv_centered: Vector2, time: f32) -> f32 {
     uv.x -= 0.5;
     uv.x *= 2.0;
 
-    const width: f32 = 1.5 / WIDTH as f32;
-    const padding: f32 = 0.0;
-    const freq: f32 = 4.0;
+    const LINE_WIDTH: f32 = 1.5 / WIDTH as f32;
+    const PADDING: f32 = 0.0;
+    const FREQ: f32 = 4.0;
 
     let time = time * 0.1;
-    let mut shade_coeff = f32::abs(uv.x) + width * 2.0;
+    let mut shade_coeff = f32::abs(uv.x) + LINE_WIDTH * 2.0;
     shade_coeff = shade_coeff * shade_coeff * shade_coeff;
-    let left_offset = sin_full((uv.y * freq + time) + PI / 2.0) * (1.0 - width - padding);
+    let left_offset = sin_full((uv.y * FREQ + time) + PI / 2.0) * (1.0 - LINE_WIDTH - PADDING);
     let left_shaded = left_offset > 0.0;
 
-    let mut left = uv.x + sin_full(uv.y * freq + time) * (1.0 - width - padding);
-    left = smoothstep(f32::abs(left), width * 1.5, width);
+    let mut left = uv.x + sin_full(uv.y * FREQ + time) * (1.0 - LINE_WIDTH - PADDING);
+    left = smoothstep(f32::abs(left), LINE_WIDTH * 1.5, LINE_WIDTH);
     left *= if left_shaded { shade_coeff } else { 1.0 };
 
-    let mut right = uv.x + sin_full(uv.y * freq + time + PI) * (1.0 - width - padding);
-    right = smoothstep(f32::abs(right), width * 1.5, width);
+    let mut right = uv.x + sin_full(uv.y * FREQ + time + PI) * (1.0 - LINE_WIDTH - PADDING);
+    right = smoothstep(f32::abs(right), LINE_WIDTH * 1.5, LINE_WIDTH);
     right *= if left_shaded { 1.0 } else { shade_coeff };
 
-    let mut bar = f32::abs(sin_full((uv.y * freq + time) * 8.0));
+    let mut bar = f32::abs(sin_full((uv.y * FREQ + time) * 8.0));
     let mut bar_mask = sin_full(uv.x + PI / 2.0) - 0.5;
-    let mut bar_mask_mul = f32::abs(sin_full(uv.y * freq + time)) * 0.8;
+    let mut bar_mask_mul = f32::abs(sin_full(uv.y * FREQ + time)) * 0.8;
     bar_mask_mul *= bar_mask_mul;
     bar_mask -= (1.0 - bar_mask_mul) * 0.5;
     bar = step(0.8, bar);
@@ -155,11 +149,11 @@ pub const fn smoothstep(x: f32, edge0: f32, edge1: f32) -> f32 {
 
 #[inline]
 pub const fn sin(x: f32) -> f32 {
-    const FOUROVERPI: f32 = 1.2732395447351627;
-    const FOUROVERPISQ: f32 = 0.40528473456935109;
-    const Q: f32 = 0.77633023248007499;
+    const FOUROVERPI: f32 = 1.273_239_5;
+    const FOUROVERPISQ: f32 = 0.405_284_73;
+    const Q: f32 = 0.776_330_23;
 
-    let mut p = 0.22308510060189463_f32.to_bits();
+    let mut p = 0.223_085_1_f32.to_bits();
     let mut v = x.to_bits();
 
     let sign: u32 = v & 0x80000000;
@@ -174,12 +168,11 @@ pub const fn sin(x: f32) -> f32 {
 
 #[inline]
 pub fn sin_full(x: f32) -> f32 {
-    const TWOPI: f32 = 6.2831853071795865;
-    const INVTWOPI: f32 = 0.15915494309189534;
+    const INVTWOPI: f32 = 0.159_154_94;
 
     let k: i32 = (x * INVTWOPI) as i32;
     let half = if x < 0.0_f32 { -0.5_f32 } else { 0.5_f32 };
-    sin((half + (k as f32)) * TWOPI - x)
+    sin((half + (k as f32)) * TAU - x)
 }
 
 #[inline(always)]
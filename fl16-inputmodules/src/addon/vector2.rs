@@ -1,4 +1,3 @@
-use core::fmt::Display;
 use core::ops::{Div, Mul, Sub};
 
 #[derive(Copy, Clone)]
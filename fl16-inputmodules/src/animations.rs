@@ -1,7 +1,11 @@
+use heapless::Vec;
+
 use crate::control::*;
 use crate::games::game_of_life::*;
 use crate::games::pong_animation::*;
 use crate::games::snake_animation::*;
+use crate::games::tetris_animation::*;
+use crate::games::Game;
 use crate::matrix::Grid;
 use crate::matrix::*;
 use crate::patterns::*;
@@ -19,6 +23,21 @@ pub enum Animation {
     Breathing(BreathingIterator),
     Snake(SnakeIterator),
     Pong(PongIterator),
+    Tetris(TetrisIterator),
+    Custom(CustomAnimationIterator),
+    ScrollText(ScrollTextIterator),
+}
+impl Animation {
+    /// Whether the animation keeps playing when the host sends a command
+    ///
+    /// Others are cancelled, so that the host can take over the display.
+    pub fn keeps_playing(&self) -> bool {
+        match self {
+            Animation::Custom(_) => true,
+            Animation::ScrollText(x) => !x.is_finished(),
+            _ => false,
+        }
+    }
 }
 impl Iterator for Animation {
     type Item = Grid;
@@ -31,6 +50,9 @@ impl Iterator for Animation {
             Animation::Breathing(x) => x.next(),
             Animation::Snake(x) => x.next(),
             Animation::Pong(x) => x.next(),
+            Animation::Tetris(x) => x.next(),
+            Animation::Custom(x) => x.next(),
+            Animation::ScrollText(x) => x.next(),
         }
     }
 }
@@ -106,7 +128,7 @@ impl GameOfLifeIterator {
     pub fn new(start_param: GameOfLifeStartParam, frames: usize) -> Self {
         Self {
             // Could start with a custom grid
-            state: GameOfLifeState::new(start_param, &Grid::default()),
+            state: GameOfLifeState::new(&start_param.into(), &Grid::default()),
             frames_remaining: frames,
         }
     }
@@ -120,9 +142,10 @@ impl Iterator for GameOfLifeIterator {
             self.frames_remaining -= 1;
             // Only update every 8th frame, otherwise the animation is too fast
             if self.frames_remaining.is_multiple_of(8) {
-                self.state.tick();
+                // Without reseeding, no randomness is needed
+                self.state.tick(0);
             }
-            Some(self.state.draw_matrix())
+            Some(self.state.draw())
         } else {
             None
         }
@@ -169,3 +192,142 @@ impl Iterator for BreathingIterator {
         }
     }
 }
+
+/// Frame of a custom animation, uploaded by the host
+#[derive(Clone)]
+pub struct CustomFrame {
+    pub grid: Grid,
+    /// How long to show the frame, in milliseconds
+    pub delay: u16,
+}
+
+pub type CustomFrames = Vec<CustomFrame, MAX_ANIMATION_FRAMES>;
+
+/// Loops through the uploaded frames forever
+///
+/// Called once every animation period. Each frame is shown until its delay
+/// has passed, so delays are rounded to multiples of the animation period.
+pub struct CustomAnimationIterator {
+    frames: CustomFrames,
+    /// Animation period in microseconds
+    period: u64,
+    current_frame: usize,
+    /// How long the current frame has been shown, in microseconds
+    elapsed: u64,
+}
+
+impl CustomAnimationIterator {
+    pub fn new(frames: CustomFrames, period: u64) -> Self {
+        Self {
+            frames,
+            period,
+            current_frame: 0,
+            elapsed: 0,
+        }
+    }
+
+    /// Update the animation period, when it's changed while playing
+    pub fn set_period(&mut self, period: u64) {
+        self.period = period;
+    }
+}
+
+impl Iterator for CustomAnimationIterator {
+    type Item = Grid;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.frames.get(self.current_frame)?;
+        let grid = frame.grid.clone();
+
+        self.elapsed += self.period;
+        if self.elapsed >= frame.delay as u64 * 1_000 {
+            self.elapsed = 0;
+            self.current_frame = (self.current_frame + 1) % self.frames.len();
+        }
+        Some(grid)
+    }
+}
+
+/// Text scrolling across the display, like a marquee
+///
+/// Called once every animation period. Each loop, the text scrolls in on one
+/// end and all the way out on the other end.
+pub struct ScrollTextIterator {
+    rows: TextRows,
+    /// Rows per second
+    speed: u8,
+    direction: ScrollDirection,
+    /// Loops until the text stops, `None` to scroll forever
+    loops_remaining: Option<u8>,
+    /// Animation period in microseconds
+    period: u64,
+    /// How far the text has scrolled in the current loop, in rows
+    offset: usize,
+    /// Movement that didn't add up to a full row yet, in rows per 1,000,000
+    progress: u64,
+}
+
+impl ScrollTextIterator {
+    /// Scroll the text `loops` times, or forever if 0
+    pub fn new(text: &str, speed: u8, direction: ScrollDirection, loops: u8, period: u64) -> Self {
+        Self {
+            rows: text_rows(text),
+            speed,
+            direction,
+            loops_remaining: if loops == 0 { None } else { Some(loops) },
+            period,
+            offset: 0,
+            progress: 0,
+        }
+    }
+
+    /// Update the animation period, when it's changed while scrolling
+    pub fn set_period(&mut self, period: u64) {
+        self.period = period;
+    }
+
+    /// Whether the text has scrolled as often as requested
+    pub fn is_finished(&self) -> bool {
+        self.loops_remaining == Some(0)
+    }
+
+    fn draw(&self) -> Grid {
+        let mut grid = Grid::default();
+        for y in 0..HEIGHT {
+            // Row of the text shown on row y of the grid, if any.
+            // The text starts outside of the grid, at offset 0 nothing is shown.
+            let pos = match self.direction {
+                ScrollDirection::Down => self.offset.checked_sub(y + 1),
+                ScrollDirection::Up => (self.rows.len() + HEIGHT).checked_sub(y + 1 + self.offset),
+            };
+            if let Some(&row) = pos.and_then(|pos| self.rows.get(pos)) {
+                draw_text_row(&mut grid, y, row);
+            }
+        }
+        grid
+    }
+}
+
+impl Iterator for ScrollTextIterator {
+    type Item = Grid;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_finished() {
+            return None;
+        }
+        let grid = self.draw();
+
+        self.progress += self.speed as u64 * self.period;
+        self.offset += (self.progress / 1_000_000) as usize;
+        self.progress %= 1_000_000;
+        // Once the text has scrolled out completely, the next loop starts
+        let loop_len = self.rows.len() + HEIGHT;
+        while self.offset >= loop_len && !self.is_finished() {
+            self.offset -= loop_len;
+            if let Some(loops) = self.loops_remaining.as_mut() {
+                *loops -= 1;
+            }
+        }
+        Some(grid)
+    }
+}
@@ -1,5 +1,5 @@
 //! Firmware API - Commands
-use num::FromPrimitive;
+#[cfg(feature = "rp2040")]
 use rp2040_hal::rom_data::reset_to_usb_boot;
 
 use crate::serialnum::{device_release, is_pre_release};
@@ -7,8 +7,6 @@ use crate::serialnum::{device_release, is_pre_release};
 #[cfg(feature = "b1display")]
 use crate::graphics::*;
 #[cfg(feature = "b1display")]
-use core::fmt::Write;
-#[cfg(feature = "b1display")]
 use embedded_graphics::Pixel;
 #[cfg(feature = "b1display")]
 use embedded_graphics::{
@@ -23,209 +21,63 @@ use embedded_hal::digital::OutputPin;
 #[cfg(feature = "b1display")]
 use embedded_hal::spi::SpiDevice;
 #[cfg(feature = "b1display")]
-use heapless::String;
-#[cfg(feature = "b1display")]
 use st7306::{FpsConfig, PowerMode, ST7306};
 
+#[cfg(feature = "ledmatrix")]
+use crate::animations::{Animation, CustomAnimationIterator, CustomFrame, ScrollTextIterator};
+#[cfg(feature = "ledmatrix")]
+use crate::games;
 #[cfg(feature = "ledmatrix")]
 use crate::games::pong;
 #[cfg(feature = "ledmatrix")]
 use crate::games::snake;
 #[cfg(feature = "ledmatrix")]
+use crate::games::tetris;
+#[cfg(feature = "ledmatrix")]
 use crate::matrix::*;
 #[cfg(feature = "ledmatrix")]
 use crate::patterns::*;
 #[cfg(feature = "ledmatrix")]
+use crate::settings::{Flash, SettingsStore};
+#[cfg(feature = "ledmatrix")]
 use is31fl3741::PwmFreq;
 
-#[cfg(feature = "c1minimal")]
-use smart_leds::{SmartLedsWrite, RGB8};
+#[cfg(feature = "ledmatrix")]
 use crate::addon;
-use crate::addon::{AddonAnimation, AddonAnimationVals, VisualKeypress};
-
-#[repr(u8)]
-#[derive(num_derive::FromPrimitive)]
-/// All available commands
-pub enum CommandVals {
-    // addon stuff
-    Keypress = 0x30,
-    SetAddonAnimation = 0x31,
-    StopAddonAnimation = 0x32,
-    SetSide = 0x33,
-
-    Brightness = 0x00,
-    Pattern = 0x01,
-    BootloaderReset = 0x02,
-    Sleep = 0x03,
-    Animate = 0x04,
-    Panic = 0x05,
-    Draw = 0x06,
-    StageGreyCol = 0x07,
-    DrawGreyColBuffer = 0x08,
-    SetText = 0x09,
-    StartGame = 0x10,
-    GameControl = 0x11,
-    GameStatus = 0x12,
-    SetColor = 0x13,
-    DisplayOn = 0x14,
-    InvertScreen = 0x15,
-    SetPixelColumn = 0x16,
-    FlushFramebuffer = 0x17,
-    ClearRam = 0x18,
-    ScreenSaver = 0x19,
-    SetFps = 0x1A,
-    SetPowerMode = 0x1B,
-    AnimationPeriod = 0x1C,
-    PwmFreq = 0x1E,
-    DebugMode = 0x1F,
-    Version = 0x20,
-}
-
-#[derive(num_derive::FromPrimitive)]
-pub enum PatternVals {
-    Percentage = 0x00,
-    Gradient = 0x01,
-    DoubleGradient = 0x02,
-    DisplayLotus = 0x03,
-    ZigZag = 0x04,
-    FullBrightness = 0x05,
-    DisplayPanic = 0x06,
-    DisplayLotus2 = 0x07,
-}
-
-pub enum Game {
-    Snake,
-    Pong,
-    Tetris,
-    GameOfLife(GameOfLifeStartParam),
-}
-
-#[derive(Copy, Clone, num_derive::FromPrimitive)]
-pub enum GameVal {
-    Snake = 0,
-    Pong = 1,
-    Tetris = 2,
-    GameOfLife = 3,
-}
-
-#[derive(Copy, Clone, num_derive::FromPrimitive)]
-pub enum GameControlArg {
-    Up = 0,
-    Down = 1,
-    Left = 2,
-    Right = 3,
-    Exit = 4,
-    SecondLeft = 5,
-    SecondRight = 6,
-}
+#[cfg(feature = "ledmatrix")]
+use crate::addon::{AddonAnimation, VisualKeypress};
 
-#[derive(Copy, Clone, num_derive::FromPrimitive)]
-pub enum GameOfLifeStartParam {
-    CurrentMatrix = 0x00,
-    Pattern1 = 0x01,
-    Blinker = 0x02,
-    Toad = 0x03,
-    Beacon = 0x04,
-    Glider = 0x05,
-    BeaconToadBlinker = 0x06,
-}
+#[cfg(feature = "c1minimal")]
+use smart_leds::{SmartLedsWrite, RGB8};
 
-#[derive(Copy, Clone, num_derive::FromPrimitive)]
-pub enum DisplayMode {
-    /// Low Power Mode
-    Lpm = 0x00,
-    /// High Power Mode
-    Hpm = 0x01,
-}
+pub use inputmodule_protocol::*;
 
 #[cfg(feature = "ledmatrix")]
-#[derive(Copy, Clone, num_derive::FromPrimitive)]
-pub enum PwmFreqArg {
-    /// 29kHz
-    P29k = 0x00,
-    /// 3.6kHz
-    P3k6 = 0x01,
-    /// 1.8kHz
-    P1k8 = 0x02,
-    /// 900Hz
-    P900 = 0x03,
-}
-#[cfg(feature = "ledmatrix")]
-impl From<PwmFreqArg> for PwmFreq {
-    fn from(val: PwmFreqArg) -> Self {
-        match val {
-            PwmFreqArg::P29k => PwmFreq::P29k,
-            PwmFreqArg::P3k6 => PwmFreq::P3k6,
-            PwmFreqArg::P1k8 => PwmFreq::P1k8,
-            PwmFreqArg::P900 => PwmFreq::P900,
-        }
+pub fn pwm_freq(val: PwmFreqArg) -> PwmFreq {
+    match val {
+        PwmFreqArg::P29k => PwmFreq::P29k,
+        PwmFreqArg::P3k6 => PwmFreq::P3k6,
+        PwmFreqArg::P1k8 => PwmFreq::P1k8,
+        PwmFreqArg::P900 => PwmFreq::P900,
     }
 }
 
-// TODO: Reduce size for modules that don't require other commands
-pub enum Command {
-    // addon stuff
-    Keypress { keycode: u32, pressed: bool },
-    SetAddonAnimation(AddonAnimationVals),
-    StopAddonAnimation,
-    SetSide(Side),
-
-    /// Get current brightness scaling
-    GetBrightness,
-    /// Set brightness scaling
-    SetBrightness(u8),
-    /// Display pre-programmed pattern
-    Pattern(PatternVals),
-    /// Reset into bootloader
-    BootloaderReset,
-    /// Light up a percentage of the screen
-    Percentage(u8),
-    /// Go to sleepe or wake up
-    Sleep(bool),
-    IsSleeping,
-    /// Start/stop animation (vertical scrolling)
-    SetAnimate(bool),
-    GetAnimate,
-    /// Panic. Just to test what happens
-    Panic,
-    /// Draw black/white on the grid
-    #[cfg(feature = "ledmatrix")]
-    Draw([u8; DRAW_BYTES]),
-    #[cfg(feature = "ledmatrix")]
-    StageGreyCol(u8, [u8; HEIGHT]),
-    DrawGreyColBuffer,
-    #[cfg(feature = "b1display")]
-    SetText(String<64>),
-    StartGame(Game),
-    GameControl(GameControlArg),
-    GameStatus,
-    Version,
-    GetColor,
-    #[cfg(feature = "c1minimal")]
-    SetColor(RGB8),
-    DisplayOn(bool),
-    GetDisplayOn,
-    InvertScreen(bool),
-    GetInvertScreen,
-    SetPixelColumn(usize, [u8; 50]),
-    FlushFramebuffer,
-    ClearRam,
-    ScreenSaver(bool),
-    GetScreenSaver,
-    SetFps(u8),
-    GetFps,
-    SetPowerMode(u8),
-    GetPowerMode,
-    /// Set the animation period in milliseconds
-    SetAnimationPeriod(u16),
-    /// Get the animation period in milliseconds
-    GetAnimationPeriod,
-    #[cfg(feature = "ledmatrix")]
-    SetPwmFreq(PwmFreqArg),
-    GetPwmFreq,
-    SetDebugMode(bool),
-    GetDebugMode,
-    _Unknown,
+/// Map a game control to the direction it has on the displayed grid
+///
+/// Games run on the untransformed grid, so with a mirrored display, pressing
+/// left has to move things to the right of the grid.
+#[cfg(feature = "ledmatrix")]
+fn oriented_game_control(arg: GameControlArg, orientation: Orientation) -> GameControlArg {
+    use GameControlArg::*;
+    match arg {
+        Left if orientation.mirrors_x() => Right,
+        Right if orientation.mirrors_x() => Left,
+        SecondLeft if orientation.mirrors_x() => SecondRight,
+        SecondRight if orientation.mirrors_x() => SecondLeft,
+        Up if orientation.mirrors_y() => Down,
+        Down if orientation.mirrors_y() => Up,
+        arg => arg,
+    }
 }
 
 #[cfg(any(feature = "c1minimal", feature = "b1display"))]
@@ -269,700 +121,663 @@ pub struct B1DIsplayState {
     pub animation_period: u64,
 }
 
-pub fn parse_command(count: usize, buf: &[u8]) -> Option<Command> {
-    if let Some(command) = parse_module_command(count, buf) {
-        return Some(command);
-    }
-
-    // Parse the generic commands common to all modules
-    if count >= 3 && buf[0] == 0x32 && buf[1] == 0xAC {
-        let command = buf[2];
-        let arg = if count <= 3 { None } else { Some(buf[3]) };
-
-        //let mut text: String<64> = String::new();
-        //writeln!(&mut text, "Command: {command}, arg: {arg}").unwrap();
-        //let _ = serial.write(text.as_bytes());
-        match FromPrimitive::from_u8(command) {
-            Some(CommandVals::Sleep) => Some(if let Some(go_to_sleep) = arg {
-                Command::Sleep(go_to_sleep == 1)
-            } else {
-                Command::IsSleeping
-            }),
-            Some(CommandVals::BootloaderReset) => Some(Command::BootloaderReset),
-            Some(CommandVals::Panic) => Some(Command::Panic),
-            Some(CommandVals::Version) => Some(Command::Version),
-            _ => None, //Some(Command::Unknown),
+/// Command handling of a module
+///
+/// Every module handles its own commands in [`ModuleHandler::handle_module_command`].
+/// Commands that all modules support are handled once, in [`ModuleHandler::handle_command`].
+pub trait ModuleHandler {
+    /// Features of this module, reported to the host
+    const CAPABILITIES: Capabilities;
+
+    fn is_sleeping(&self) -> bool;
+
+    /// Handle a command that's specific to this module
+    fn handle_module_command(&mut self, command: &Command) -> CommandResult;
+
+    /// Handle any command received from the host
+    fn handle_command(&mut self, command: &Command) -> CommandResult {
+        match command {
+            Command::BootloaderReset => {
+                //let _ = serial.write("Bootloader Reset".as_bytes());
+                // Without the hardware, there's no bootloader to go to
+                #[cfg(feature = "rp2040")]
+                reset_to_usb_boot(0, 0);
+                Ok(None)
+            }
+            Command::Panic => panic!("Ahhh"),
+            Command::Version => {
+                let version = Version::from_bcd(device_release(), is_pre_release());
+                Ok(Some(Response::Version(version).encode()))
+            }
+            Command::GetCapabilities => {
+                Ok(Some(Response::Capabilities(Self::CAPABILITIES).encode()))
+            }
+            // Going to sleep and waking up depends on the hardware, the main loop handles it
+            Command::Sleep(_) => Ok(None),
+            Command::IsSleeping => Ok(Some(Response::Sleeping(self.is_sleeping()).encode())),
+            _ if !Self::CAPABILITIES.supports(command) => Err(ErrorCode::Unsupported),
+            _ => self.handle_module_command(command),
         }
-    } else {
-        None
     }
 }
 
 #[cfg(feature = "ledmatrix")]
-pub fn parse_module_command(count: usize, buf: &[u8]) -> Option<Command> {
-    if count >= 3 && buf[0] == 0x32 && buf[1] == 0xAC {
-        let command = buf[2];
-        let arg = if count <= 3 { None } else { Some(buf[3]) };
+pub struct LedmatrixHandler<'a, L: LedDriver, F: Flash> {
+    pub state: &'a mut LedmatrixState,
+    pub matrix: &'a mut L,
+    pub settings: &'a mut SettingsStore<F>,
+    /// Random value to start games with
+    pub random: u8,
+}
 
-        match FromPrimitive::from_u8(command) {
+#[cfg(feature = "ledmatrix")]
+impl<L: LedDriver, F: Flash> ModuleHandler for LedmatrixHandler<'_, L, F> {
+    const CAPABILITIES: Capabilities = Capabilities {
+        module: ModuleType::LedMatrix,
+        protocol_version: FRAME_VERSION,
+        width: WIDTH as u16,
+        height: HEIGHT as u16,
+        commands: command_bits(&[
+            CommandVals::Keypress,
+            CommandVals::SetAddonAnimation,
+            CommandVals::StopAddonAnimation,
+            CommandVals::SetSide,
+            CommandVals::Brightness,
+            CommandVals::Pattern,
+            CommandVals::BootloaderReset,
+            CommandVals::Sleep,
+            CommandVals::Animate,
+            CommandVals::Panic,
+            CommandVals::Draw,
+            CommandVals::StageGreyCol,
+            CommandVals::DrawGreyColBuffer,
+            CommandVals::DrawGreyFrame,
+            CommandVals::Gamma,
+            CommandVals::GammaLut,
+            CommandVals::Orientation,
+            CommandVals::ScrollText,
+            CommandVals::StartGame,
+            CommandVals::GameControl,
+            CommandVals::GameStatus,
+            CommandVals::AnimationPeriod,
+            CommandVals::PwmFreq,
+            CommandVals::DebugMode,
+            CommandVals::Version,
+            CommandVals::GetCapabilities,
+            CommandVals::SaveSettings,
+            CommandVals::LoadSettings,
+            CommandVals::ResetSettings,
+            CommandVals::StoreAnimationFrame,
+            CommandVals::CustomAnimation,
+        ]),
+        games: game_bits(&[
+            GameVal::Snake,
+            GameVal::Pong,
+            GameVal::Tetris,
+            GameVal::GameOfLife,
+        ]),
+        patterns: pattern_bits(&[
+            PatternVals::Percentage,
+            PatternVals::Gradient,
+            PatternVals::DoubleGradient,
+            PatternVals::DisplayLotus,
+            PatternVals::ZigZag,
+            PatternVals::FullBrightness,
+            PatternVals::DisplayPanic,
+            PatternVals::DisplayLotus2,
+        ]),
+        addon_animations: addon_animation_bits(&[
+            AddonAnimationVals::Spiral,
+            AddonAnimationVals::Splashes,
+            AddonAnimationVals::Helix,
+        ]),
+    };
+
+    fn is_sleeping(&self) -> bool {
+        matches!(self.state.sleeping, SleepState::Sleeping(_))
+    }
+
+    fn handle_module_command(&mut self, command: &Command) -> CommandResult {
+        use crate::games::game_of_life;
+
+        let state = &mut *self.state;
+        let matrix = &mut *self.matrix;
+        let random = self.random;
+
+        // Drawing something else stops the custom animation and scrolling text
+        if matches!(
+            command,
+            Command::Pattern(_)
+                | Command::Percentage(_)
+                | Command::Draw(_)
+                | Command::DrawGreyColBuffer
+                | Command::DrawGreyFrame(_)
+                | Command::StartGame(_)
+        ) && matches!(
+            state.upcoming_frames,
+            Some(Animation::Custom(_) | Animation::ScrollText(_))
+        ) {
+            state.upcoming_frames = None;
+        }
+
+        match command {
             // addon stuff
-            Some(CommandVals::Keypress) => {
-                if buf.len() < 8 { return None; }
-                Some(Command::Keypress { keycode: u32::from_le_bytes([buf[3], buf[4], buf[5], buf[6]]), pressed: buf[7] == 1 })
-            },
-            Some(CommandVals::SetAddonAnimation) => match arg.and_then(FromPrimitive::from_u8) {
-                Some(val) => Some(Command::SetAddonAnimation(val)),
-                None => None,
-            },
-            Some(CommandVals::StopAddonAnimation) => Some(Command::StopAddonAnimation),
-            Some(CommandVals::SetSide) => match arg {
-                Some(arg) => Some(Command::SetSide(if arg == 0 { Side::Left } else { Side::Right })),
-                None => None,
-            }
-
-            Some(CommandVals::Brightness) => Some(if let Some(brightness) = arg {
-                Command::SetBrightness(brightness)
-            } else {
-                Command::GetBrightness
-            }),
-            Some(CommandVals::Pattern) => match arg.and_then(FromPrimitive::from_u8) {
-                // TODO: Convert arg to PatternVals
-                Some(PatternVals::Percentage) => {
-                    if count >= 5 {
-                        Some(Command::Percentage(buf[4]))
+            Command::Keypress { keycode, pressed } => {
+                let pos = state
+                    .visual_keypresses
+                    .iter()
+                    .position(|k| k.keycode == *keycode);
+                if *pressed {
+                    if let Some(pos) = pos {
+                        state.visual_keypresses[pos].life = state.visual_keypress_life;
+                        state.visual_keypresses[pos].alive = true;
                     } else {
-                        None
+                        let keycode = *keycode;
+                        state
+                            .visual_keypresses
+                            .push(VisualKeypress {
+                                life: state.visual_keypress_life,
+                                keycode,
+                                alive: true,
+                                side: if addon::rand(keycode.wrapping_add(100)) > 0.5 {
+                                    Side::Left
+                                } else {
+                                    Side::Right
+                                },
+                                rand0: addon::rand(keycode),
+                                rand1: addon::rand(keycode.wrapping_add(50)),
+                            })
+                            .ok();
                     }
-                }
-                Some(PatternVals::Gradient) => Some(Command::Pattern(PatternVals::Gradient)),
-                Some(PatternVals::DoubleGradient) => {
-                    Some(Command::Pattern(PatternVals::DoubleGradient))
-                }
-                Some(PatternVals::DisplayLotus) => {
-                    Some(Command::Pattern(PatternVals::DisplayLotus))
-                }
-                Some(PatternVals::ZigZag) => Some(Command::Pattern(PatternVals::ZigZag)),
-                Some(PatternVals::FullBrightness) => {
-                    Some(Command::Pattern(PatternVals::FullBrightness))
-                }
-                Some(PatternVals::DisplayPanic) => {
-                    Some(Command::Pattern(PatternVals::DisplayPanic))
-                }
-                Some(PatternVals::DisplayLotus2) => {
-                    Some(Command::Pattern(PatternVals::DisplayLotus2))
-                }
-                None => None,
-            },
-            Some(CommandVals::Animate) => Some(if let Some(run_animation) = arg {
-                Command::SetAnimate(run_animation == 1)
-            } else {
-                Command::GetAnimate
-            }),
-            Some(CommandVals::Draw) => {
-                if count >= 3 + DRAW_BYTES {
-                    let mut bytes = [0; DRAW_BYTES];
-                    bytes.clone_from_slice(&buf[3..3 + DRAW_BYTES]);
-                    Some(Command::Draw(bytes))
                 } else {
-                    None
+                    if let Some(pos) = pos {
+                        state.visual_keypresses[pos].alive = false;
+                    }
                 }
+                Ok(None)
             }
-            Some(CommandVals::StageGreyCol) => {
-                if count >= 3 + 1 + HEIGHT {
-                    let mut bytes = [0; HEIGHT];
-                    bytes.clone_from_slice(&buf[4..4 + HEIGHT]);
-                    Some(Command::StageGreyCol(buf[3], bytes))
-                } else {
-                    None
+            Command::SetAddonAnimation(val) => {
+                match val {
+                    AddonAnimationVals::Spiral => {
+                        state.addon_animation = Some(AddonAnimation::Spiral)
+                    }
+                    AddonAnimationVals::Splashes => {
+                        state.addon_animation = Some(AddonAnimation::Splashes)
+                    }
+                    AddonAnimationVals::Helix => {
+                        state.addon_animation = Some(AddonAnimation::Helix)
+                    }
                 }
+                Ok(None)
             }
-            Some(CommandVals::DrawGreyColBuffer) => Some(Command::DrawGreyColBuffer),
-            Some(CommandVals::StartGame) => match arg.and_then(FromPrimitive::from_u8) {
-                Some(GameVal::Snake) => Some(Command::StartGame(Game::Snake)),
-                Some(GameVal::Pong) => Some(Command::StartGame(Game::Pong)),
-                Some(GameVal::Tetris) => None,
-                Some(GameVal::GameOfLife) => {
-                    if count >= 5 {
-                        FromPrimitive::from_u8(buf[4])
-                            .map(|x| Command::StartGame(Game::GameOfLife(x)))
-                    } else {
-                        None
+            Command::StopAddonAnimation => {
+                state.addon_animation = None;
+                Ok(None)
+            }
+            Command::SetSide(side) => {
+                state.side = *side;
+                Ok(None)
+            }
+            Command::GetSide => Ok(Some(Response::Side(state.side).encode())),
+
+            Command::GetBrightness => Ok(Some(Response::Brightness(state.brightness).encode())),
+            Command::SetBrightness(br) => {
+                //let _ = serial.write("Brightness".as_bytes());
+                set_brightness(state, *br, matrix);
+                Ok(None)
+            }
+            Command::Percentage(p) => {
+                //let p = if count >= 5 { buf[4] } else { 100 };
+                state.grid = percentage(*p as u16);
+                Ok(None)
+            }
+            Command::Pattern(pattern) => {
+                //let _ = serial.write("Pattern".as_bytes());
+                state.addon_animation = None;
+                match pattern {
+                    PatternVals::Gradient => state.grid = gradient(),
+                    PatternVals::DoubleGradient => state.grid = double_gradient(),
+                    PatternVals::DisplayLotus => state.grid = display_lotus(),
+                    PatternVals::ZigZag => state.grid = zigzag(),
+                    PatternVals::FullBrightness => {
+                        state.grid = percentage(100);
+                        set_brightness(state, BRIGHTNESS_LEVELS, matrix);
                     }
+                    PatternVals::DisplayPanic => state.grid = display_panic(),
+                    PatternVals::DisplayLotus2 => state.grid = display_lotus2(),
+                    _ => {}
                 }
-                _ => None,
-            },
-            Some(CommandVals::GameControl) => match arg.and_then(FromPrimitive::from_u8) {
-                Some(GameControlArg::Up) => Some(Command::GameControl(GameControlArg::Up)),
-                Some(GameControlArg::Down) => Some(Command::GameControl(GameControlArg::Down)),
-                Some(GameControlArg::Left) => Some(Command::GameControl(GameControlArg::Left)),
-                Some(GameControlArg::Right) => Some(Command::GameControl(GameControlArg::Right)),
-                Some(GameControlArg::Exit) => Some(Command::GameControl(GameControlArg::Exit)),
-                Some(GameControlArg::SecondLeft) => {
-                    Some(Command::GameControl(GameControlArg::SecondLeft))
-                }
-                Some(GameControlArg::SecondRight) => {
-                    Some(Command::GameControl(GameControlArg::SecondRight))
-                }
-                _ => None,
-            },
-            Some(CommandVals::GameStatus) => Some(Command::GameStatus),
-            Some(CommandVals::AnimationPeriod) => {
-                if count == 3 + 2 {
-                    let period = u16::from_le_bytes([buf[3], buf[4]]);
-                    Some(Command::SetAnimationPeriod(period))
-                } else {
-                    Some(Command::GetAnimationPeriod)
+                Ok(None)
+            }
+            Command::SetAnimate(a) => {
+                state.animate = *a;
+                Ok(None)
+            }
+            Command::GetAnimate => Ok(Some(Response::Animate(state.animate).encode())),
+            Command::Draw(vals) => {
+                state.grid = draw(vals);
+                Ok(None)
+            }
+            Command::StageGreyCol(col, vals) => {
+                draw_grey_col(&mut state.col_buffer, *col, vals);
+                Ok(None)
+            }
+            Command::DrawGreyColBuffer => {
+                // Copy the staging buffer to the real grid and display it
+                state.grid = state.col_buffer.clone();
+                // Zero the old staging buffer, just for good measure.
+                state.col_buffer = percentage(0);
+                Ok(None)
+            }
+            Command::DrawGreyFrame(frame) => {
+                draw_grey_frame(&mut state.grid, frame)?;
+                Ok(None)
+            }
+            Command::SetGamma(curve) => {
+                set_gamma(state, *curve);
+                fill_grid_pixels(state, matrix);
+                Ok(None)
+            }
+            Command::GetGamma => Ok(Some(Response::Gamma(state.gamma).encode())),
+            Command::SetOrientation(orientation) => {
+                state.orientation = *orientation;
+                fill_grid_pixels(state, matrix);
+                Ok(None)
+            }
+            Command::GetOrientation => Ok(Some(Response::Orientation(state.orientation).encode())),
+            Command::SetGammaLut(lut) => {
+                state.custom_gamma_lut = *lut;
+                set_gamma(state, GammaCurve::Custom);
+                fill_grid_pixels(state, matrix);
+                Ok(None)
+            }
+            Command::StartGame(game) => {
+                match game {
+                    Game::Snake(param) => snake::start_game(state, random, *param),
+                    Game::Pong(param) => pong::start_game(state, random, *param),
+                    Game::Tetris => tetris::start_game(state, random),
+                    Game::GameOfLife(param) => game_of_life::start_game(state, random, param),
                 }
+                Ok(None)
             }
-            Some(CommandVals::PwmFreq) => {
-                if let Some(freq) = arg {
-                    FromPrimitive::from_u8(freq).map(Command::SetPwmFreq)
-                } else {
-                    Some(Command::GetPwmFreq)
+            Command::GameControl(arg) => {
+                let arg = &oriented_game_control(*arg, state.orientation);
+                games::handle_control(state, arg);
+                Ok(None)
+            }
+            Command::GameStatus => {
+                let status = match state.game {
+                    Some(ref game) => game.game().status(),
+                    None => GameStatus::default(),
+                };
+                Ok(Some(Response::GameStatus(status).encode()))
+            }
+            Command::SetAnimationPeriod(period) => {
+                state.animation_period = (*period as u64) * 1_000;
+                match state.upcoming_frames {
+                    Some(Animation::Custom(ref mut custom)) => {
+                        custom.set_period(state.animation_period)
+                    }
+                    Some(Animation::ScrollText(ref mut scroll)) => {
+                        scroll.set_period(state.animation_period)
+                    }
+                    _ => {}
                 }
+                Ok(None)
             }
-            Some(CommandVals::DebugMode) => Some(if let Some(debug_mode) = arg {
-                Command::SetDebugMode(debug_mode == 1)
-            } else {
-                Command::GetDebugMode
-            }),
-            _ => None,
-        }
-    } else {
-        None
-    }
-}
-
-/*
-#[cfg(feature = "b1display")]
-pub fn parse_module_command(count: usize, buf: &[u8]) -> Option<Command> {
-    if count >= 3 && buf[0] == 0x32 && buf[1] == 0xAC {
-        let command = buf[2];
-        let arg = if count <= 3 { None } else { Some(buf[3]) };
-
-        match FromPrimitive::from_u8(command) {
-            Some(CommandVals::SetText) => {
-                if let Some(arg) = arg {
-                    let available_len = count - 4;
-                    let str_len = arg as usize;
-                    assert!(str_len <= available_len);
-
-                    assert!(str_len < 32);
-                    let mut bytes = [0; 32];
-                    bytes[..str_len].copy_from_slice(&buf[4..4 + str_len]);
-
-                    let text_str = core::str::from_utf8(&bytes[..str_len]).unwrap();
-                    let mut text: String<64> = String::new();
-                    writeln!(&mut text, "{}", text_str).unwrap();
-
-                    Some(Command::SetText(text))
+            Command::GetAnimationPeriod => {
+                // TODO: Doesn't seem to work when the FPS is 16 or higher
+                let period_ms = state.animation_period / 1_000;
+                Ok(Some(Response::AnimationPeriod(period_ms as u16).encode()))
+            }
+            Command::SetPwmFreq(arg) => {
+                state.pwm_freq = *arg;
+                matrix.set_pwm_freq(state.pwm_freq);
+                Ok(None)
+            }
+            Command::GetPwmFreq => Ok(Some(Response::PwmFreq(state.pwm_freq).encode())),
+            Command::SetDebugMode(arg) => {
+                state.debug_mode = *arg;
+                Ok(None)
+            }
+            Command::GetDebugMode => Ok(Some(Response::DebugMode(state.debug_mode).encode())),
+            Command::StoreAnimationFrame { index, delay } => {
+                let index = *index as usize;
+                if index > state.custom_frames.len() || index >= MAX_ANIMATION_FRAMES {
+                    return Err(ErrorCode::BadArgument);
+                }
+                if index == 0 {
+                    state.custom_frames.clear();
+                }
+                let frame = CustomFrame {
+                    grid: state.col_buffer.clone(),
+                    delay: *delay,
+                };
+                if index < state.custom_frames.len() {
+                    state.custom_frames[index] = frame;
                 } else {
-                    None
+                    // Can't fail, the index was checked above
+                    let _ = state.custom_frames.push(frame);
                 }
+                state.col_buffer = percentage(0);
+                Ok(None)
             }
-            Some(CommandVals::DisplayOn) => Some(if let Some(on) = arg {
-                Command::DisplayOn(on == 1)
-            } else {
-                Command::GetDisplayOn
-            }),
-            Some(CommandVals::InvertScreen) => Some(if let Some(invert) = arg {
-                Command::InvertScreen(invert == 1)
-            } else {
-                Command::GetInvertScreen
-            }),
-            Some(CommandVals::SetPixelColumn) => {
-                //  3B for magic and command
-                //  2B for column (u16)
-                // 50B for 400 pixels (400/8=50)
-                if count == 3 + 2 + 50 {
-                    let column = u16::from_le_bytes([buf[3], buf[4]]);
-                    //panic!("SetPixelColumn. Col: {}", column);
-                    let mut pixels: [u8; 50] = [0; 50];
-                    pixels.clone_from_slice(&buf[5..55]);
-                    Some(Command::SetPixelColumn(column as usize, pixels))
-                } else {
-                    None
+            Command::PlayCustomAnimation(true) => {
+                if state.custom_frames.is_empty() {
+                    return Err(ErrorCode::BadArgument);
                 }
+                state.upcoming_frames = Some(Animation::Custom(CustomAnimationIterator::new(
+                    state.custom_frames.clone(),
+                    state.animation_period,
+                )));
+                Ok(None)
             }
-            Some(CommandVals::FlushFramebuffer) => Some(Command::FlushFramebuffer),
-            Some(CommandVals::ClearRam) => Some(Command::ClearRam),
-            Some(CommandVals::ScreenSaver) => Some(if let Some(on) = arg {
-                Command::ScreenSaver(on == 1)
-            } else {
-                Command::GetScreenSaver
-            }),
-            Some(CommandVals::SetFps) => Some(if let Some(fps) = arg {
-                Command::SetFps(fps)
-            } else {
-                Command::GetFps
-            }),
-            Some(CommandVals::SetPowerMode) => Some(if let Some(mode) = arg {
-                Command::SetPowerMode(mode)
-            } else {
-                Command::GetPowerMode
-            }),
-            Some(CommandVals::AnimationPeriod) => {
-                if count == 3 + 2 {
-                    let period = u16::from_le_bytes([buf[3], buf[4]]);
-                    Some(Command::SetAnimationPeriod(period))
-                } else {
-                    Some(Command::GetAnimationPeriod)
+            Command::PlayCustomAnimation(false) => {
+                if matches!(state.upcoming_frames, Some(Animation::Custom(_))) {
+                    state.upcoming_frames = None;
                 }
+                Ok(None)
             }
-            _ => None,
-        }
-    } else {
-        None
-    }
-}*/
-
-#[cfg(not(any(feature = "ledmatrix", feature = "b1display", feature = "c1minimal")))]
-pub fn parse_module_command(_count: usize, _buf: &[u8]) -> Option<Command> {
-    None
-}
-
-pub fn handle_generic_command(command: &Command) -> Option<[u8; 32]> {
-    match command {
-        Command::BootloaderReset => {
-            //let _ = serial.write("Bootloader Reset".as_bytes());
-            reset_to_usb_boot(0, 0);
-            None
-        }
-        Command::Panic => panic!("Ahhh"),
-        Command::Version => {
-            let mut response: [u8; 32] = [0; 32];
-            let bcd_device = device_release().to_be_bytes();
-            response[0] = bcd_device[0];
-            response[1] = bcd_device[1];
-            response[2] = is_pre_release() as u8;
-            Some(response)
-        }
-        _ => None,
-    }
-}
-
-#[cfg(feature = "ledmatrix")]
-pub fn handle_command(
-    command: &Command,
-    state: &mut LedmatrixState,
-    matrix: &mut Foo,
-    random: u8,
-) -> Option<[u8; 32]> {
-    use crate::games::game_of_life;
-
-    match command {
-        // addon stuff
-        Command::Keypress { keycode, pressed } => {
-            let pos = state.visual_keypresses.iter().position(|k| k.keycode == *keycode);
-            if *pressed {
-                if let Some(pos) = pos {
-                    state.visual_keypresses[pos].life = state.visual_keypress_life;
-                    state.visual_keypresses[pos].alive = true;
+            Command::IsPlayingCustomAnimation => {
+                let playing = matches!(state.upcoming_frames, Some(Animation::Custom(_)));
+                Ok(Some(Response::PlayingCustomAnimation(playing).encode()))
+            }
+            Command::ScrollText {
+                speed,
+                direction,
+                loops,
+                text,
+            } => {
+                if text.is_empty() {
+                    if matches!(state.upcoming_frames, Some(Animation::ScrollText(_))) {
+                        state.upcoming_frames = None;
+                        state.grid = percentage(0);
+                    }
+                    return Ok(None);
                 }
-                else {
-                    let keycode = *keycode;
-                    state.visual_keypresses.push(VisualKeypress {
-                        life: state.visual_keypress_life,
-                        keycode,
-                        alive: true,
-                        side: if addon::rand(keycode.wrapping_add(100)) > 0.5 { Side::Left } else { Side::Right },
-                        rand0: addon::rand(keycode),
-                        rand1: addon::rand(keycode.wrapping_add(50))
-                    }).ok();
+                if *speed == 0 {
+                    return Err(ErrorCode::BadArgument);
                 }
+                state.upcoming_frames = Some(Animation::ScrollText(ScrollTextIterator::new(
+                    text,
+                    *speed,
+                    *direction,
+                    *loops,
+                    state.animation_period,
+                )));
+                Ok(None)
             }
-            else {
-                if let Some(pos) = pos {
-                    state.visual_keypresses[pos].alive = false;
-                }
+            Command::IsScrollingText => {
+                let scrolling = matches!(
+                    state.upcoming_frames,
+                    Some(Animation::ScrollText(ref scroll)) if !scroll.is_finished()
+                );
+                Ok(Some(Response::ScrollingText(scrolling).encode()))
             }
-            None
-        }
-        Command::SetAddonAnimation(val) => {
-            match val {
-                AddonAnimationVals::Spiral => state.addon_animation = Some(AddonAnimation::Spiral),
-                AddonAnimationVals::Splashes => state.addon_animation = Some(AddonAnimation::Splashes),
-                AddonAnimationVals::Helix => state.addon_animation = Some(AddonAnimation::Helix),
+            Command::SaveSettings => {
+                state.save_settings(self.settings);
+                Ok(None)
             }
-            None
-        }
-        Command::StopAddonAnimation => {
-            state.addon_animation = None;
-            None
-        }
-        Command::SetSide(side) => {
-            state.side = *side;
-            None
-        }
-
-        Command::GetBrightness => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.brightness;
-            Some(response)
-        }
-        Command::SetBrightness(br) => {
-            //let _ = serial.write("Brightness".as_bytes());
-            set_brightness(state, *br, matrix);
-            None
-        }
-        Command::Percentage(p) => {
-            //let p = if count >= 5 { buf[4] } else { 100 };
-            state.grid = percentage(*p as u16);
-            None
-        }
-        Command::Pattern(pattern) => {
-            //let _ = serial.write("Pattern".as_bytes());
-            state.addon_animation = None;
-            match pattern {
-                PatternVals::Gradient => state.grid = gradient(),
-                PatternVals::DoubleGradient => state.grid = double_gradient(),
-                PatternVals::DisplayLotus => state.grid = display_lotus(),
-                PatternVals::ZigZag => state.grid = zigzag(),
-                PatternVals::FullBrightness => {
-                    state.grid = percentage(100);
-                    set_brightness(state, BRIGHTNESS_LEVELS, matrix);
-                }
-                PatternVals::DisplayPanic => state.grid = display_panic(),
-                PatternVals::DisplayLotus2 => state.grid = display_lotus2(),
-                _ => {}
+            Command::LoadSettings => {
+                state.load_settings(self.settings);
+                matrix.set_pwm_freq(state.pwm_freq);
+                fill_grid_pixels(state, matrix);
+                Ok(None)
             }
-            None
-        }
-        Command::SetAnimate(a) => {
-            state.animate = *a;
-            None
-        }
-        Command::GetAnimate => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.animate as u8;
-            Some(response)
-        }
-        Command::Draw(vals) => {
-            state.grid = draw(vals);
-            None
-        }
-        Command::StageGreyCol(col, vals) => {
-            draw_grey_col(&mut state.col_buffer, *col, vals);
-            None
-        }
-        Command::DrawGreyColBuffer => {
-            // Copy the staging buffer to the real grid and display it
-            state.grid = state.col_buffer.clone();
-            // Zero the old staging buffer, just for good measure.
-            state.col_buffer = percentage(0);
-            None
-        }
-        // TODO: Move to handle_generic_command
-        Command::IsSleeping => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = match state.sleeping {
-                SleepState::Sleeping(_) => 1,
-                SleepState::Awake => 0,
-            };
-            Some(response)
-        }
-        Command::StartGame(game) => {
-            match game {
-                Game::Snake => snake::start_game(state, random),
-                Game::Pong => pong::start_game(state, random),
-                Game::Tetris => {}
-                Game::GameOfLife(param) => game_of_life::start_game(state, random, *param),
-            }
-            None
-        }
-        Command::GameControl(arg) => {
-            match state.game {
-                Some(GameState::Snake(_)) => snake::handle_control(state, arg),
-                Some(GameState::Pong(_)) => pong::handle_control(state, arg),
-                Some(GameState::GameOfLife(_)) => game_of_life::handle_control(state, arg),
-                _ => {}
-            }
-            None
-        }
-        Command::GameStatus => None,
-        Command::SetAnimationPeriod(period) => {
-            state.animation_period = (*period as u64) * 1_000;
-            None
-        }
-        Command::GetAnimationPeriod => {
-            // TODO: Doesn't seem to work when the FPS is 16 or higher
-            let mut response: [u8; 32] = [0; 32];
-            let period_ms = state.animation_period / 1_000;
-            response[0..2].copy_from_slice(&(period_ms as u16).to_le_bytes());
-            Some(response)
-        }
-        Command::SetPwmFreq(arg) => {
-            state.pwm_freq = *arg;
-            matrix.device.set_pwm_freq(state.pwm_freq.into()).unwrap();
-            None
-        }
-        Command::GetPwmFreq => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.pwm_freq as u8;
-            Some(response)
-        }
-        Command::SetDebugMode(arg) => {
-            state.debug_mode = *arg;
-            None
-        }
-        Command::GetDebugMode => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.debug_mode as u8;
-            Some(response)
+            Command::ResetSettings => {
+                self.settings.reset();
+                state.reset_settings();
+                matrix.set_pwm_freq(state.pwm_freq);
+                fill_grid_pixels(state, matrix);
+                Ok(None)
+            }
+            _ => Err(ErrorCode::Unsupported),
         }
-        _ => handle_generic_command(command),
     }
 }
 
-/*
+/// Features of the B1 display
+///
+/// Not tied to the `b1display` feature, so that the module can be emulated on the host.
+pub const B1DISPLAY_CAPABILITIES: Capabilities = Capabilities {
+    module: ModuleType::B1Display,
+    protocol_version: FRAME_VERSION,
+    width: 300,
+    height: 400,
+    commands: command_bits(&[
+        CommandVals::BootloaderReset,
+        CommandVals::Sleep,
+        CommandVals::Panic,
+        CommandVals::SetText,
+        CommandVals::DisplayOn,
+        CommandVals::InvertScreen,
+        CommandVals::SetPixelColumn,
+        CommandVals::FlushFramebuffer,
+        CommandVals::ClearRam,
+        CommandVals::ScreenSaver,
+        CommandVals::SetFps,
+        CommandVals::SetPowerMode,
+        CommandVals::AnimationPeriod,
+        CommandVals::Version,
+        CommandVals::GetCapabilities,
+    ]),
+    games: 0,
+    patterns: 0,
+    addon_animations: 0,
+};
+
+#[cfg(feature = "b1display")]
+pub struct B1DisplayHandler<'a, SPI, DC, RST, DELAY, const COLS: usize, const ROWS: usize> {
+    pub state: &'a mut B1DIsplayState,
+    pub logo_rect: Rectangle,
+    pub disp: &'a mut ST7306<SPI, DC, RST, COLS, ROWS>,
+    pub delay: &'a mut DELAY,
+}
+
 #[cfg(feature = "b1display")]
-pub fn handle_command<SPI, DC, RST, DELAY, const COLS: usize, const ROWS: usize>(
-    command: &Command,
-    state: &mut B1DIsplayState,
-    logo_rect: Rectangle,
-    disp: &mut ST7306<SPI, DC, RST, COLS, ROWS>,
-    delay: &mut DELAY,
-) -> Option<[u8; 32]>
+impl<SPI, DC, RST, DELAY, const COLS: usize, const ROWS: usize> ModuleHandler
+    for B1DisplayHandler<'_, SPI, DC, RST, DELAY, COLS, ROWS>
 where
     SPI: SpiDevice,
     DC: OutputPin,
     RST: OutputPin,
     DELAY: DelayNs,
 {
-    match command {
-        // TODO: Move to handle_generic_command
-        Command::IsSleeping => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = match state.sleeping {
-                SimpleSleepState::Sleeping => 1,
-                SimpleSleepState::Awake => 0,
-            };
-            Some(response)
-        }
-        Command::Panic => panic!("Ahhh"),
-        Command::SetText(text) => {
-            // Turn screensaver off, when drawing something
-            state.screensaver = None;
-
-            clear_text(
-                disp,
-                Point::new(LOGO_OFFSET_X, LOGO_OFFSET_Y + logo_rect.size.height as i32),
-                Rgb565::WHITE,
-            )
-            .unwrap();
-
-            draw_text(
-                disp,
-                text,
-                Point::new(LOGO_OFFSET_X, LOGO_OFFSET_Y + logo_rect.size.height as i32),
-            )
-            .unwrap();
-            disp.flush().unwrap();
-            None
-        }
-        Command::DisplayOn(on) => {
-            state.screen_on = *on;
-            disp.on_off(*on).unwrap();
-            None
-        }
-        Command::GetDisplayOn => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.screen_on as u8;
-            Some(response)
-        }
-        Command::InvertScreen(invert) => {
-            state.screen_inverted = *invert;
-            disp.invert_screen(state.screen_inverted).unwrap();
-            None
-        }
-        Command::GetInvertScreen => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.screen_inverted as u8;
-            Some(response)
-        }
-        Command::SetPixelColumn(column, pixel_bytes) => {
-            // Turn screensaver off, when drawing something
-            state.screensaver = None;
-
-            let mut pixels: [bool; 400] = [false; 400];
-            for (i, byte) in pixel_bytes.iter().enumerate() {
-                pixels[8 * i] = byte & 0b00000001 != 0;
-                pixels[8 * i + 1] = byte & 0b00000010 != 0;
-                pixels[8 * i + 2] = byte & 0b00000100 != 0;
-                pixels[8 * i + 3] = byte & 0b00001000 != 0;
-                pixels[8 * i + 4] = byte & 0b00010000 != 0;
-                pixels[8 * i + 5] = byte & 0b00100000 != 0;
-                pixels[8 * i + 6] = byte & 0b01000000 != 0;
-                pixels[8 * i + 7] = byte & 0b10000000 != 0;
-            }
-            disp.draw_pixels(
-                pixels.iter().enumerate().map(|(y, black)| {
-                    Pixel(
-                        Point::new(*column as i32, y as i32),
-                        if *black { Rgb565::BLACK } else { Rgb565::WHITE },
-                    )
-                }),
-                false,
-            )
-            .unwrap();
-            None
-        }
-        Command::FlushFramebuffer => {
-            disp.flush().unwrap();
-            None
-        }
-        Command::ClearRam => {
-            // Turn screensaver off, when drawing something
-            state.screensaver = None;
+    const CAPABILITIES: Capabilities = B1DISPLAY_CAPABILITIES;
 
-            disp.clear_ram().unwrap();
-            None
-        }
-        Command::ScreenSaver(on) => {
-            state.screensaver = match (*on, state.screensaver) {
-                (true, Some(x)) => Some(x),
-                (true, None) => Some(ScreenSaverState::default()),
-                (false, Some(_)) => None,
-                (false, None) => None,
-            };
-            None
-        }
-        Command::GetScreenSaver => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.screensaver.is_some() as u8;
-            Some(response)
-        }
-        Command::SetFps(fps) => {
-            if let Some(fps_config) = FpsConfig::from_u8(*fps) {
+    fn is_sleeping(&self) -> bool {
+        matches!(self.state.sleeping, SimpleSleepState::Sleeping)
+    }
+
+    fn handle_module_command(&mut self, command: &Command) -> CommandResult {
+        let state = &mut *self.state;
+        let disp = &mut *self.disp;
+        let delay = &mut *self.delay;
+        let text_pos = Point::new(
+            LOGO_OFFSET_X,
+            LOGO_OFFSET_Y + self.logo_rect.size.height as i32,
+        );
+
+        match command {
+            Command::SetText(text) => {
+                // Turn screensaver off, when drawing something
+                state.screensaver = None;
+
+                clear_text(disp, text_pos, Rgb565::WHITE).unwrap();
+                draw_text(disp, text, text_pos).unwrap();
+                disp.flush().unwrap();
+                Ok(None)
+            }
+            Command::DisplayOn(on) => {
+                state.screen_on = *on;
+                disp.on_off(*on).unwrap();
+                Ok(None)
+            }
+            Command::GetDisplayOn => Ok(Some(Response::DisplayOn(state.screen_on).encode())),
+            Command::InvertScreen(invert) => {
+                state.screen_inverted = *invert;
+                disp.invert_screen(state.screen_inverted).unwrap();
+                Ok(None)
+            }
+            Command::GetInvertScreen => {
+                Ok(Some(Response::InvertScreen(state.screen_inverted).encode()))
+            }
+            Command::SetPixelColumn(column, pixel_bytes) => {
+                // Turn screensaver off, when drawing something
+                state.screensaver = None;
+
+                let mut pixels: [bool; 400] = [false; 400];
+                for (i, byte) in pixel_bytes.iter().enumerate() {
+                    pixels[8 * i] = byte & 0b00000001 != 0;
+                    pixels[8 * i + 1] = byte & 0b00000010 != 0;
+                    pixels[8 * i + 2] = byte & 0b00000100 != 0;
+                    pixels[8 * i + 3] = byte & 0b00001000 != 0;
+                    pixels[8 * i + 4] = byte & 0b00010000 != 0;
+                    pixels[8 * i + 5] = byte & 0b00100000 != 0;
+                    pixels[8 * i + 6] = byte & 0b01000000 != 0;
+                    pixels[8 * i + 7] = byte & 0b10000000 != 0;
+                }
+                disp.draw_pixels(
+                    pixels.iter().enumerate().map(|(y, black)| {
+                        Pixel(
+                            Point::new(*column as i32, y as i32),
+                            if *black { Rgb565::BLACK } else { Rgb565::WHITE },
+                        )
+                    }),
+                    false,
+                )
+                .unwrap();
+                Ok(None)
+            }
+            Command::FlushFramebuffer => {
+                disp.flush().unwrap();
+                Ok(None)
+            }
+            Command::ClearRam => {
+                // Turn screensaver off, when drawing something
+                state.screensaver = None;
+
+                disp.clear_ram().unwrap();
+                Ok(None)
+            }
+            Command::ScreenSaver(on) => {
+                state.screensaver = match (*on, state.screensaver) {
+                    (true, Some(x)) => Some(x),
+                    (true, None) => Some(ScreenSaverState::default()),
+                    (false, Some(_)) => None,
+                    (false, None) => None,
+                };
+                Ok(None)
+            }
+            Command::GetScreenSaver => Ok(Some(
+                Response::ScreenSaver(state.screensaver.is_some()).encode(),
+            )),
+            Command::SetFps(fps) => {
+                let fps_config = FpsConfig::from_u8(*fps).ok_or(ErrorCode::BadArgument)?;
                 state.fps_config = fps_config;
                 disp.set_fps(state.fps_config).unwrap();
                 // TODO: Need to reinit the display
+                Ok(None)
             }
-            None
-        }
-        Command::GetFps => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.fps_config.as_u8();
-            Some(response)
-        }
-        Command::SetPowerMode(mode) => {
-            match mode {
-                0 => {
-                    state.power_mode = PowerMode::Lpm;
-                    disp.switch_mode(delay, state.power_mode).unwrap();
-                }
-                1 => {
-                    state.power_mode = PowerMode::Hpm;
-                    disp.switch_mode(delay, state.power_mode).unwrap();
-                }
-                _ => {}
+            Command::GetFps => Ok(Some(Response::Fps(state.fps_config.as_u8()).encode())),
+            Command::SetPowerMode(mode) => {
+                state.power_mode = match mode {
+                    DisplayMode::Lpm => PowerMode::Lpm,
+                    DisplayMode::Hpm => PowerMode::Hpm,
+                };
+                disp.switch_mode(delay, state.power_mode).unwrap();
+                Ok(None)
             }
-            None
-        }
-        Command::GetPowerMode => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = match state.power_mode {
-                PowerMode::Lpm => 0,
-                PowerMode::Hpm => 1,
-            };
-            Some(response)
-        }
-        Command::SetAnimationPeriod(period) => {
-            state.animation_period = (*period as u64) * 1_000;
-            None
-        }
-        Command::GetAnimationPeriod => {
-            // TODO: Doesn't seem to work when the FPS is 16 or higher
-            let mut response: [u8; 32] = [0; 32];
-            let period_ms = state.animation_period / 1_000;
-            response[0..2].copy_from_slice(&(period_ms as u16).to_le_bytes());
-            Some(response)
+            Command::GetPowerMode => {
+                let mode = match state.power_mode {
+                    PowerMode::Lpm => DisplayMode::Lpm,
+                    PowerMode::Hpm => DisplayMode::Hpm,
+                };
+                Ok(Some(Response::PowerMode(mode).encode()))
+            }
+            Command::SetAnimationPeriod(period) => {
+                state.animation_period = (*period as u64) * 1_000;
+                Ok(None)
+            }
+            Command::GetAnimationPeriod => {
+                // TODO: Doesn't seem to work when the FPS is 16 or higher
+                let period_ms = state.animation_period / 1_000;
+                Ok(Some(Response::AnimationPeriod(period_ms as u16).encode()))
+            }
+            _ => Err(ErrorCode::Unsupported),
         }
-        _ => handle_generic_command(command),
     }
-}*/
+}
+
+/// Features of the C1 minimal module
+///
+/// Not tied to the `c1minimal` feature, so that the module can be emulated on the host.
+pub const C1MINIMAL_CAPABILITIES: Capabilities = Capabilities {
+    module: ModuleType::C1Minimal,
+    protocol_version: FRAME_VERSION,
+    width: 1,
+    height: 1,
+    commands: command_bits(&[
+        CommandVals::Brightness,
+        CommandVals::BootloaderReset,
+        CommandVals::Sleep,
+        CommandVals::Panic,
+        CommandVals::SetColor,
+        CommandVals::Version,
+        CommandVals::GetCapabilities,
+    ]),
+    games: 0,
+    patterns: 0,
+    addon_animations: 0,
+};
 
-/*
 #[cfg(feature = "c1minimal")]
-pub fn handle_command(
-    command: &Command,
-    state: &mut C1MinimalState,
-    ws2812: &mut impl SmartLedsWrite<Color = RGB8, Error = ()>,
-) -> Option<[u8; 32]> {
-    match command {
-        // TODO: Move to handle_generic_command
-        Command::IsSleeping => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = match state.sleeping {
-                SimpleSleepState::Sleeping => 1,
-                SimpleSleepState::Awake => 0,
-            };
-            Some(response)
-        }
-        Command::GetBrightness => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.brightness;
-            Some(response)
-        }
-        Command::SetBrightness(br) => {
-            //let _ = serial.write("Brightness".as_bytes());
-            state.brightness = *br;
-            ws2812
-                .write(smart_leds::brightness(
-                    [state.color].iter().cloned(),
-                    state.brightness,
-                ))
-                .unwrap();
-            None
-        }
-        Command::GetColor => {
-            let mut response: [u8; 32] = [0; 32];
-            response[0] = state.color.r;
-            response[1] = state.color.g;
-            response[2] = state.color.b;
-            Some(response)
-        }
-        Command::SetColor(color) => {
-            state.color = *color;
-            ws2812
-                .write(smart_leds::brightness(
-                    [*color].iter().cloned(),
-                    state.brightness,
-                ))
-                .unwrap();
-            None
-        }
-        // TODO: Make it return something
-        _ => handle_generic_command(command),
-    }
-}*/
+pub struct C1MinimalHandler<'a, WS2812> {
+    pub state: &'a mut C1MinimalState,
+    pub ws2812: &'a mut WS2812,
+}
 
-/*
 #[cfg(feature = "c1minimal")]
-pub fn parse_module_command(count: usize, buf: &[u8]) -> Option<Command> {
-    if count >= 3 && buf[0] == 0x32 && buf[1] == 0xAC {
-        let command = buf[2];
-        let arg = if count <= 3 { None } else { Some(buf[3]) };
-
-        match FromPrimitive::from_u8(command) {
-            Some(CommandVals::Brightness) => Some(if let Some(brightness) = arg {
-                Command::SetBrightness(brightness)
-            } else {
-                Command::GetBrightness
-            }),
-            Some(CommandVals::SetColor) => {
-                if count >= 6 {
-                    let (red, green, blue) = (buf[3], buf[4], buf[5]);
-                    Some(Command::SetColor(RGB8::new(red, green, blue)))
-                } else if arg.is_none() {
-                    Some(Command::GetColor)
-                } else {
-                    None
-                }
+impl<WS2812> ModuleHandler for C1MinimalHandler<'_, WS2812>
+where
+    WS2812: SmartLedsWrite<Color = RGB8, Error = ()>,
+{
+    const CAPABILITIES: Capabilities = C1MINIMAL_CAPABILITIES;
+
+    fn is_sleeping(&self) -> bool {
+        matches!(self.state.sleeping, SimpleSleepState::Sleeping)
+    }
+
+    fn handle_module_command(&mut self, command: &Command) -> CommandResult {
+        let state = &mut *self.state;
+        match command {
+            Command::GetBrightness => Ok(Some(Response::Brightness(state.brightness).encode())),
+            Command::SetBrightness(br) => {
+                //let _ = serial.write("Brightness".as_bytes());
+                state.brightness = *br;
+                self.ws2812
+                    .write(smart_leds::brightness(
+                        [state.color].iter().cloned(),
+                        state.brightness,
+                    ))
+                    .unwrap();
+                Ok(None)
+            }
+            Command::GetColor => {
+                let color = state.color;
+                Ok(Some(Response::Color([color.r, color.g, color.b]).encode()))
             }
-            _ => None,
+            Command::SetColor([r, g, b]) => {
+                state.color = RGB8::new(*r, *g, *b);
+                self.ws2812
+                    .write(smart_leds::brightness(
+                        [state.color].iter().cloned(),
+                        state.brightness,
+                    ))
+                    .unwrap();
+                Ok(None)
+            }
+            _ => Err(ErrorCode::Unsupported),
         }
-    } else {
-        None
     }
-}*/
+}
@@ -1,5 +1,16 @@
-use crate::control::{GameControlArg, GameOfLifeStartParam};
+use crate::control::{
+    GameControlArg, GameOfLifeParam, GameOfLifeStartParam, GameStatus, GameVal, LifeEdges, LifeRule,
+};
+use crate::games::Game;
 use crate::matrix::{GameState, Grid, LedmatrixState, HEIGHT, WIDTH};
+use crate::patterns::draw;
+
+/// Number of past generations to compare with, to notice when the board repeats
+const HISTORY: usize = 8;
+/// Brightness lost per generation that a cell is alive, with age brightness
+const AGE_DIMMING: u8 = 0x30;
+/// Generations after which cells don't get any dimmer
+const MAX_AGE: u8 = 4;
 
 #[derive(Clone, Copy, num_derive::FromPrimitive, PartialEq, Eq)]
 pub enum Cell {
@@ -10,9 +21,31 @@ pub enum Cell {
 #[derive(Clone)]
 pub struct GameOfLifeState {
     cells: [[Cell; WIDTH]; HEIGHT],
+    /// Generations that each cell has been alive for
+    ages: [[u8; WIDTH]; HEIGHT],
+    edges: LifeEdges,
+    rule: LifeRule,
+    age_brightness: bool,
+    reseed: bool,
+    /// Fingerprints of the last generations
+    history: [Option<u32>; HISTORY],
+    pub generation: u16,
 }
 
 impl GameOfLifeState {
+    fn from_cells(cells: [[Cell; WIDTH]; HEIGHT]) -> Self {
+        GameOfLifeState {
+            cells,
+            ages: [[0; WIDTH]; HEIGHT],
+            edges: LifeEdges::default(),
+            rule: LifeRule::default(),
+            age_brightness: false,
+            reseed: false,
+            history: [None; HISTORY],
+            generation: 0,
+        }
+    }
+
     pub fn combine(&self, other: &Self) -> Self {
         let mut state = self.clone();
         for x in 0..WIDTH {
@@ -26,48 +59,19 @@ impl GameOfLifeState {
     }
 }
 
-pub fn start_game(state: &mut LedmatrixState, _random: u8, param: GameOfLifeStartParam) {
+pub fn start_game(state: &mut LedmatrixState, _random: u8, param: &GameOfLifeParam) {
     let gol = GameOfLifeState::new(param, &state.grid);
-    state.grid = gol.draw_matrix();
+    state.grid = gol.draw();
     state.game = Some(GameState::GameOfLife(gol));
 }
-pub fn handle_control(state: &mut LedmatrixState, arg: &GameControlArg) {
-    if let Some(GameState::GameOfLife(ref mut _gol_state)) = state.game {
-        if let GameControlArg::Exit = arg {
-            state.game = None
-        }
-    }
-}
-pub fn game_step(state: &mut LedmatrixState, _random: u8) {
-    if let Some(GameState::GameOfLife(ref mut gol_state)) = state.game {
-        gol_state.tick();
-        state.grid = gol_state.draw_matrix();
-    } else {
-        panic!("Game of Life not started!")
-    }
-}
 
 impl GameOfLifeState {
     // TODO: Integrate Grid into GameOfLifeStartParam because it's only used in one of the enum variants
-    pub fn new(param: GameOfLifeStartParam, grid: &Grid) -> Self {
-        match param {
+    pub fn new(param: &GameOfLifeParam, grid: &Grid) -> Self {
+        let state = match param.start {
             GameOfLifeStartParam::Beacon => Self::beacon(),
-            GameOfLifeStartParam::CurrentMatrix => {
-                let mut cells = [[Cell::Dead; WIDTH]; HEIGHT];
-                for row in 0..HEIGHT {
-                    for col in 0..WIDTH {
-                        cells[row][col] = if grid.0[col][row] == 0 {
-                            Cell::Dead
-                        } else {
-                            Cell::Alive
-                        };
-                    }
-                }
-                //cells: grid
-                //    .0
-                //    .map(|col| col.map(|val| if val == 0 { Cell::Dead } else { Cell::Alive })),
-                GameOfLifeState { cells }
-            }
+            GameOfLifeStartParam::CurrentMatrix => Self::from_grid(grid),
+            GameOfLifeStartParam::Upload => Self::from_grid(&draw(&param.seed)),
             GameOfLifeStartParam::Pattern1 => Self::pattern1(),
             GameOfLifeStartParam::Blinker => Self::blinker(),
             GameOfLifeStartParam::Toad => Self::toad(),
@@ -75,7 +79,41 @@ impl GameOfLifeState {
             GameOfLifeStartParam::BeaconToadBlinker => Self::beacon()
                 .combine(&Self::toad())
                 .combine(&Self::blinker()),
+        };
+        GameOfLifeState {
+            edges: param.edges,
+            rule: param.rule,
+            age_brightness: param.age_brightness,
+            reseed: param.reseed,
+            ..state
+        }
+    }
+    /// Every pixel that's lit up is a living cell
+    fn from_grid(grid: &Grid) -> Self {
+        let mut cells = [[Cell::Dead; WIDTH]; HEIGHT];
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                cells[row][col] = if grid.0[col][row] == 0 {
+                    Cell::Dead
+                } else {
+                    Cell::Alive
+                };
+            }
+        }
+        Self::from_cells(cells)
+    }
+    /// Random cells, about a third of them alive
+    fn random(random: u8) -> Self {
+        let mut rng = u16::from_le_bytes([random, 0x5A]);
+        let mut cells = [[Cell::Dead; WIDTH]; HEIGHT];
+        for cell in cells.iter_mut().flatten() {
+            // Linear congruential generator, seeded from the ring oscillator
+            rng = rng.wrapping_mul(25173).wrapping_add(13849);
+            if rng >> 8 < 0x55 {
+                *cell = Cell::Alive;
+            }
         }
+        Self::from_cells(cells)
     }
     fn pattern1() -> Self {
         // Starts off with lots of alive cells, quickly reduced.
@@ -89,7 +127,7 @@ impl GameOfLifeState {
                 }
             }
         }
-        GameOfLifeState { cells }
+        Self::from_cells(cells)
     }
     fn blinker() -> Self {
         // Oscillates between:
@@ -105,7 +143,7 @@ impl GameOfLifeState {
         cells[8][5] = Cell::Alive;
         cells[8][6] = Cell::Alive;
         cells[8][7] = Cell::Alive;
-        GameOfLifeState { cells }
+        Self::from_cells(cells)
     }
     fn toad() -> Self {
         // Oscillates between
@@ -123,7 +161,7 @@ impl GameOfLifeState {
         cells[18][5] = Cell::Alive;
         cells[18][6] = Cell::Alive;
         cells[18][7] = Cell::Alive;
-        GameOfLifeState { cells }
+        Self::from_cells(cells)
     }
     fn beacon() -> Self {
         // Oscillates between
@@ -146,7 +184,7 @@ impl GameOfLifeState {
         cells[28][7] = Cell::Alive;
         cells[29][6] = Cell::Alive;
         cells[29][7] = Cell::Alive;
-        GameOfLifeState { cells }
+        Self::from_cells(cells)
     }
 
     fn glider() -> Self {
@@ -165,29 +203,62 @@ impl GameOfLifeState {
         cells[22][4] = Cell::Alive;
         cells[22][5] = Cell::Alive;
         cells[22][6] = Cell::Alive;
-        GameOfLifeState { cells }
+        Self::from_cells(cells)
     }
 
     /// Count live neighbor cells
     pub fn live_neighbor_count(&self, row: usize, col: usize) -> u8 {
         let mut count = 0;
-        // Use HEIGHT-1 instead of -1 because usize can't go below 0
-        for delta_row in [HEIGHT - 1, 0, 1] {
-            for delta_col in [WIDTH - 1, 0, 1] {
+        for delta_row in [-1, 0, 1] {
+            for delta_col in [-1, 0, 1] {
                 if delta_row == 0 && delta_col == 0 {
                     // The cell itself
                     continue;
                 }
 
-                let neighbor_row = (row + delta_row) % HEIGHT;
-                let neighbor_col = (col + delta_col) % WIDTH;
+                let neighbor_row = row as isize + delta_row;
+                let neighbor_col = col as isize + delta_col;
+                let (neighbor_row, neighbor_col) = match self.edges {
+                    LifeEdges::Wrap => (
+                        neighbor_row.rem_euclid(HEIGHT as isize),
+                        neighbor_col.rem_euclid(WIDTH as isize),
+                    ),
+                    LifeEdges::Bounded => {
+                        if !(0..HEIGHT as isize).contains(&neighbor_row)
+                            || !(0..WIDTH as isize).contains(&neighbor_col)
+                        {
+                            // Beyond the edge, everything is dead
+                            continue;
+                        }
+                        (neighbor_row, neighbor_col)
+                    }
+                };
 
-                count += self.cells[neighbor_row][neighbor_col] as u8;
+                count += self.cells[neighbor_row as usize][neighbor_col as usize] as u8;
             }
         }
         count
     }
-    pub fn tick(&mut self) {
+    /// Keep the options of another state
+    fn with_options(self, other: &Self) -> Self {
+        GameOfLifeState {
+            edges: other.edges,
+            rule: other.rule,
+            age_brightness: other.age_brightness,
+            reseed: other.reseed,
+            ..self
+        }
+    }
+
+    /// Hash of the living cells (FNV-1a)
+    fn fingerprint(&self) -> u32 {
+        self.cells.iter().flatten().fold(0x811C_9DC5, |hash, cell| {
+            (hash ^ *cell as u32).wrapping_mul(0x0100_0193)
+        })
+    }
+}
+impl Game for GameOfLifeState {
+    fn tick(&mut self, random: u8) {
         let mut next_generation = self.cells;
 
         for row in 0..HEIGHT {
@@ -195,35 +266,77 @@ impl GameOfLifeState {
                 let cell = self.cells[row][col];
                 let live_neighbors = self.live_neighbor_count(row, col);
 
-                let child_cell = match (cell, live_neighbors) {
-                    // Fewer than 2 neighbors causes it to die
-                    (Cell::Alive, x) if x < 2 => Cell::Dead,
-                    // 2 or three neighbors are good and it stays alive
-                    (Cell::Alive, 2) | (Cell::Alive, 3) => Cell::Alive,
-                    // More than 3 is too many and the cell dies
-                    (Cell::Alive, x) if x > 3 => Cell::Dead,
-                    // 3 neighbors when the cell is dead, revives it
-                    (Cell::Dead, 3) => Cell::Alive,
-                    // No change by default
-                    (c, _) => c,
+                // Which neighbor counts keep the cell alive or revive it
+                let rule = match cell {
+                    Cell::Alive => self.rule.survive,
+                    Cell::Dead => self.rule.birth,
+                };
+                let child_cell = if rule & (1 << live_neighbors) > 0 {
+                    Cell::Alive
+                } else {
+                    Cell::Dead
                 };
 
+                self.ages[row][col] = match (cell, child_cell) {
+                    (Cell::Alive, Cell::Alive) => self.ages[row][col].saturating_add(1),
+                    _ => 0,
+                };
                 next_generation[row][col] = child_cell;
             }
         }
 
         self.cells = next_generation;
+        self.generation = self.generation.saturating_add(1);
+
+        if self.reseed {
+            // Static and oscillating boards look the same as a few generations ago
+            let fingerprint = self.fingerprint();
+            if self.history.contains(&Some(fingerprint)) {
+                let generation = self.generation;
+                *self = Self::random(random).with_options(self);
+                self.generation = generation;
+            } else {
+                self.history[self.generation as usize % HISTORY] = Some(fingerprint);
+            }
+        }
     }
 
-    pub fn draw_matrix(&self) -> Grid {
+    fn draw(&self) -> Grid {
         let mut grid = Grid::default();
 
         for row in 0..HEIGHT {
             for col in 0..WIDTH {
-                grid.0[col][row] = (self.cells[row][col] as u8) * 0xFF;
+                let brightness = if self.age_brightness {
+                    // Young cells are the brightest
+                    0xFF - AGE_DIMMING * self.ages[row][col].min(MAX_AGE)
+                } else {
+                    0xFF
+                };
+                grid.0[col][row] = (self.cells[row][col] as u8) * brightness;
             }
         }
 
         grid
     }
+
+    /// No controls, it plays itself
+    fn control(&mut self, _arg: &GameControlArg) {}
+
+    fn tick_period(&self) -> u64 {
+        500_000
+    }
+
+    /// The score is the number of living cells. Once they're all dead, the game is over.
+    /// The level is the number of generations.
+    fn status(&self) -> GameStatus {
+        let cells = self.cells.iter().flatten();
+        let alive = cells.filter(|cell| **cell == Cell::Alive).count() as u16;
+        GameStatus {
+            game: Some(GameVal::GameOfLife),
+            game_over: alive == 0,
+            score: alive,
+            level: self.generation,
+            ..Default::default()
+        }
+    }
 }
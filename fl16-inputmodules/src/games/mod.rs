@@ -1,5 +1,43 @@
+use crate::control::{GameControlArg, GameStatus};
+use crate::matrix::{Grid, LedmatrixState};
+
 pub mod game_of_life;
 pub mod pong;
 pub mod pong_animation;
 pub mod snake;
 pub mod snake_animation;
+pub mod tetris;
+pub mod tetris_animation;
+
+/// An embedded game
+///
+/// The main loop calls `tick` every `tick_period` and shows what `draw` returns.
+pub trait Game {
+    /// Advance the game by one step
+    fn tick(&mut self, random: u8);
+    fn draw(&self) -> Grid;
+    /// Handle a button press, except for `Exit`, which ends every game
+    fn control(&mut self, arg: &GameControlArg);
+    /// Time between two steps in microseconds
+    fn tick_period(&self) -> u64;
+    fn status(&self) -> GameStatus;
+}
+
+pub fn handle_control(state: &mut LedmatrixState, arg: &GameControlArg) {
+    if let GameControlArg::Exit = arg {
+        state.game = None;
+    } else if let Some(ref mut game) = state.game {
+        let game = game.game_mut();
+        game.control(arg);
+        // Show moves right away, not just on the next step
+        state.grid = game.draw();
+    }
+}
+
+pub fn game_step(state: &mut LedmatrixState, random: u8) {
+    if let Some(ref mut game) = state.game {
+        let game = game.game_mut();
+        game.tick(random);
+        state.grid = game.draw();
+    }
+}
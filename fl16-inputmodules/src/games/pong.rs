@@ -1,12 +1,21 @@
-use crate::control::GameControlArg;
+use crate::control::{GameControlArg, GameStatus, GameVal, PongDifficulty, PongPaddle, PongParam};
+use crate::games::Game;
+use crate::mapping::glyph;
 use crate::matrix::{GameState, Grid, LedmatrixState, HEIGHT, WIDTH};
+use crate::patterns::display_letter;
 
 const PADDLE_WIDTH: usize = 5;
+/// Points needed to win. Fits into a single digit, to show the score.
+const WINNING_SCORE: u8 = 9;
+/// Game steps to show the score for, before the ball is served again
+const SCORE_STEPS: u8 = 15;
+/// Fastest the ball gets sideways, so that paddles can still catch it
+const MAX_SIDEWAYS: i8 = 2;
 
 #[derive(Clone)]
 struct Score {
-    _upper: u8,
-    _lower: u8,
+    upper: u8,
+    lower: u8,
 }
 
 type Position = (usize, usize);
@@ -21,56 +30,119 @@ struct Ball {
 
 #[derive(Clone)]
 pub struct PongState {
-    // TODO: Properly calculate score and display it
-    _score: Score,
+    score: Score,
     ball: Ball,
     paddles: (usize, usize),
-    pub speed: u64,
+    speed: u64,
+    /// Paddle that's played by the module
+    cpu: Option<PongPaddle>,
+    difficulty: PongDifficulty,
+    /// Steps left to show the score for, after a point
+    showing_score: u8,
+    /// Counts the steps, to slow down the CPU paddle
+    steps: u8,
+    game_over: bool,
 }
 
 impl Default for PongState {
     fn default() -> Self {
         PongState {
-            _score: Score {
-                _upper: 0,
-                _lower: 0,
-            },
+            score: Score { upper: 0, lower: 0 },
             ball: Ball {
                 pos: (4, 20),
                 direction: (0, 1),
             },
             paddles: (PADDLE_WIDTH / 2, PADDLE_WIDTH / 2),
             speed: 0,
+            cpu: None,
+            difficulty: PongDifficulty::default(),
+            showing_score: 0,
+            steps: 0,
+            game_over: false,
         }
     }
 }
 
 impl PongState {
-    pub fn draw_matrix(&self) -> Grid {
+    pub fn new(param: PongParam) -> Self {
+        PongState {
+            cpu: param.cpu,
+            difficulty: param.difficulty,
+            ..Default::default()
+        }
+    }
+
+    /// Upper score at the top, lower score at the bottom, with a line between them
+    fn draw_score(&self) -> Grid {
         let mut grid = Grid::default();
+        let digit = |score: u8| glyph(char::from(b'0' + score)).unwrap_or_default();
 
-        for x in self.paddles.0..self.paddles.0 + PADDLE_WIDTH {
-            grid.0[x][0] = 0xFF;
+        display_letter(6, &mut grid, digit(self.score.upper));
+        for x in (0..WIDTH).step_by(2) {
+            grid.0[x][HEIGHT / 2] = 0x40;
         }
-        for x in self.paddles.1..self.paddles.1 + PADDLE_WIDTH {
-            grid.0[x][HEIGHT - 1] = 0xFF;
-        }
-        grid.0[self.ball.pos.0][self.ball.pos.1] = 0xFF;
+        display_letter(20, &mut grid, digit(self.score.lower));
 
         grid
     }
 
-    pub fn tick(&mut self) {
+    /// Follow the ball. The lower the difficulty, the slower the paddle.
+    fn move_cpu_paddle(&mut self) {
+        let Some(cpu) = self.cpu else {
+            return;
+        };
+        let (steps_per_move, only_incoming) = match self.difficulty {
+            PongDifficulty::Easy => (3, true),
+            PongDifficulty::Medium => (2, false),
+            PongDifficulty::Hard => (1, false),
+        };
+        let (_, vy) = self.ball.direction;
+        let incoming = match cpu {
+            PongPaddle::Upper => vy < 0,
+            PongPaddle::Lower => vy > 0,
+        };
+        if !self.steps.is_multiple_of(steps_per_move) || (only_incoming && !incoming) {
+            return;
+        }
+
+        let target = self
+            .ball
+            .pos
+            .0
+            .saturating_sub(PADDLE_WIDTH / 2)
+            .min(WIDTH - PADDLE_WIDTH);
+        let paddle = match cpu {
+            PongPaddle::Upper => &mut self.paddles.0,
+            PongPaddle::Lower => &mut self.paddles.1,
+        };
+        if *paddle < target {
+            *paddle += 1;
+        } else if *paddle > target {
+            *paddle -= 1;
+        }
+    }
+}
+
+impl Game for PongState {
+    fn tick(&mut self, random: u8) {
+        if self.game_over {
+            return;
+        }
+        if self.showing_score > 0 {
+            self.showing_score -= 1;
+            return;
+        }
+        self.steps = self.steps.wrapping_add(1);
+        self.move_cpu_paddle();
+
         self.ball.pos = {
             let (vx, vy) = self.ball.direction;
             let (x, y) = add_velocity(self.ball.pos, self.ball.direction);
-            let x = if x > WIDTH - 1 { WIDTH - 1 } else { x };
             if x == 0 || x == WIDTH - 1 {
                 // Hit wall, bounce back
                 self.ball.direction = (-vx, vy);
             }
 
-            let y = if y > HEIGHT - 1 { HEIGHT - 1 } else { y };
             let (x, y) = if let Some(paddle_hit) = hit_paddle((x, y), self.paddles) {
                 // Hit paddle, bounce back
                 // TODO: Change vy direction slightly depending on where the paddle was hit
@@ -84,12 +156,25 @@ impl PongState {
                     // Shouldn't occur
                     _ => (vx, -vy),
                 };
+                let (vx, vy) = self.ball.direction;
+                self.ball.direction = (vx.clamp(-MAX_SIDEWAYS, MAX_SIDEWAYS), vy);
                 // TODO: Not sure if I want the speed to change. Speed by angle change is already high enough
                 //self.speed += 1;
                 (x, y)
             } else if y == 0 || y == HEIGHT - 1 {
+                // Ball got past a paddle, the other player gets the point
+                if y == 0 {
+                    self.score.lower += 1;
+                } else {
+                    self.score.upper += 1;
+                }
+                self.game_over = self.score.upper.max(self.score.lower) >= WINNING_SCORE;
+                self.showing_score = SCORE_STEPS;
                 self.speed = 0;
-                self.ball.direction = (1, 1); //random_v(random);
+                // Serve to the player that lost the point, to a random side
+                let vx = if random & 1 == 0 { 1 } else { -1 };
+                let vy = if y == 0 { -1 } else { 1 };
+                self.ball.direction = (vx, vy);
                 (WIDTH / 2, HEIGHT / 2)
             } else {
                 (x, y)
@@ -97,57 +182,81 @@ impl PongState {
             (x, y)
         };
     }
-    pub fn handle_control(&mut self, arg: &GameControlArg) {
-        match arg {
-            GameControlArg::Left => {
-                if self.paddles.0 + PADDLE_WIDTH < WIDTH {
-                    self.paddles.0 += 1;
-                }
-            }
-            GameControlArg::Right => {
-                if self.paddles.0 >= 1 {
-                    self.paddles.0 -= 1;
-                }
-            }
-            GameControlArg::SecondLeft => {
-                if self.paddles.1 + PADDLE_WIDTH < WIDTH {
-                    self.paddles.1 += 1;
+
+    fn draw(&self) -> Grid {
+        if self.showing_score > 0 || self.game_over {
+            return self.draw_score();
+        }
+        let mut grid = Grid::default();
+
+        for x in self.paddles.0..self.paddles.0 + PADDLE_WIDTH {
+            grid.0[x][0] = 0xFF;
+        }
+        for x in self.paddles.1..self.paddles.1 + PADDLE_WIDTH {
+            grid.0[x][HEIGHT - 1] = 0xFF;
+        }
+        grid.0[self.ball.pos.0][self.ball.pos.1] = 0xFF;
+
+        grid
+    }
+
+    fn control(&mut self, arg: &GameControlArg) {
+        if self.game_over {
+            return;
+        }
+        // With a single player, Left/Right control the paddle that's not played by the CPU
+        let (first, second) = match self.cpu {
+            None => (Some(&mut self.paddles.0), Some(&mut self.paddles.1)),
+            Some(PongPaddle::Upper) => (Some(&mut self.paddles.1), None),
+            Some(PongPaddle::Lower) => (Some(&mut self.paddles.0), None),
+        };
+        let paddle = match arg {
+            GameControlArg::Left | GameControlArg::Right => first,
+            GameControlArg::SecondLeft | GameControlArg::SecondRight => second,
+            _ => None,
+        };
+        if let Some(paddle) = paddle {
+            match arg {
+                GameControlArg::Left | GameControlArg::SecondLeft => {
+                    if *paddle + PADDLE_WIDTH < WIDTH {
+                        *paddle += 1;
+                    }
                 }
-            }
-            GameControlArg::SecondRight => {
-                if self.paddles.1 >= 1 {
-                    self.paddles.1 -= 1;
+                _ => {
+                    if *paddle >= 1 {
+                        *paddle -= 1;
+                    }
                 }
             }
-            _ => {}
         }
     }
-}
 
-pub fn start_game(state: &mut LedmatrixState, _random: u8) {
-    state.game = Some(GameState::Pong(PongState::default()))
-}
-pub fn handle_control(state: &mut LedmatrixState, arg: &GameControlArg) {
-    if let Some(GameState::Pong(ref mut pong_state)) = state.game {
-        match arg {
-            GameControlArg::Exit => state.game = None,
-            _ => pong_state.handle_control(arg),
+    fn tick_period(&self) -> u64 {
+        100_000 - 5_000 * self.speed
+    }
+
+    fn status(&self) -> GameStatus {
+        GameStatus {
+            game: Some(GameVal::Pong),
+            game_over: self.game_over,
+            level: self.speed as u16,
+            pong_score: [self.score.upper, self.score.lower],
+            ..Default::default()
         }
     }
 }
 
-// TODO: Randomize the velocity vector upon respawning
-fn _random_v(random: u8) -> Velocity {
-    // TODO: while food == head:
-    let x = ((random & 0xF0) >> 4) % WIDTH as u8;
-    let y = (random & 0x0F) % HEIGHT as u8;
-    (x as i8, y as i8)
+pub fn start_game(state: &mut LedmatrixState, _random: u8, param: PongParam) {
+    state.game = Some(GameState::Pong(PongState::new(param)))
 }
 
+/// Move by the velocity, but stay on the grid
 fn add_velocity(pos: Position, v: Velocity) -> Position {
     let (vx, vy) = v;
     let (x, y) = pos;
-    (((x as i8) + vx) as usize, ((y as i8) + vy) as usize)
+    let x = (x as i8 + vx).clamp(0, WIDTH as i8 - 1);
+    let y = (y as i8 + vy).clamp(0, HEIGHT as i8 - 1);
+    (x as usize, y as usize)
 }
 
 fn hit_paddle(ball: Position, paddles: (usize, usize)) -> Option<usize> {
@@ -160,10 +269,3 @@ fn hit_paddle(ball: Position, paddles: (usize, usize)) -> Option<usize> {
         None
     }
 }
-
-pub fn game_step(state: &mut LedmatrixState, _random: u8) {
-    if let Some(GameState::Pong(ref mut pong_state)) = state.game {
-        pong_state.tick();
-        state.grid = pong_state.draw_matrix();
-    }
-}
@@ -1,5 +1,6 @@
 use crate::control::GameControlArg;
 use crate::games::pong::PongState;
+use crate::games::Game;
 use crate::matrix::Grid;
 
 pub struct PongIterator {
@@ -27,16 +28,18 @@ impl Iterator for PongIterator {
         }
 
         if let Some(command) = self.commands[self.current_command] {
-            self.state.handle_control(&command);
+            self.state.control(&command);
         }
         self.current_command += 1;
 
-        self.state.tick();
-        Some(self.state.draw_matrix())
+        // Always serve the same way, the recorded commands depend on it
+        self.state.tick(0);
+        Some(self.state.draw())
     }
 }
 
-const SAMPLE_GAME: [Option<GameControlArg>; 136] = [
+/// Moves of a rally where both players keep hitting the ball, nobody scores
+pub const SAMPLE_GAME: [Option<GameControlArg>; 136] = [
     Some(GameControlArg::Left), // Middle
     None,
     Some(GameControlArg::Left),
@@ -137,9 +140,9 @@ const SAMPLE_GAME: [Option<GameControlArg>; 136] = [
     None,
     None,
     None,
+    Some(GameControlArg::Left), // Catch the ball at the wall
     None,
-    None,
-    None,
+    Some(GameControlArg::Left),
     None,
     None,
     None,
@@ -1,10 +1,15 @@
-use crate::control::GameControlArg;
+use crate::control::{GameControlArg, GameStatus, GameVal, SnakeParam};
+use crate::games::Game;
 use crate::matrix::{GameState, Grid, LedmatrixState, HEIGHT, LEDS, WIDTH};
+use crate::patterns::display_number;
 
 use heapless::Vec;
 
-// Wrap around the edges
-const WRAP_ENABLE: bool = false;
+/// Time between two steps in microseconds, at the start
+const START_PERIOD: u64 = 500_000;
+/// How much faster every step gets per eaten food, if speeding up
+const PERIOD_DECREASE: u64 = 25_000;
+const MIN_PERIOD: u64 = 150_000;
 
 #[derive(Clone, Debug, Copy)]
 pub enum HeadDirection {
@@ -24,19 +29,27 @@ pub struct SnakeState {
     pub body: Vec<Position, LEDS>,
     pub game_over: bool,
     food: Position,
+    /// Wrap around the edges
+    wrap: bool,
+    speed_up: bool,
 }
 
 impl SnakeState {
-    pub fn new(random: u8) -> Self {
+    pub fn new(random: u8, param: SnakeParam) -> Self {
         SnakeState {
             head: (4, 0),
             direction: HeadDirection::Down,
             body: Vec::new(),
             game_over: false,
             food: place_food(random),
+            wrap: param.wrap,
+            speed_up: param.speed_up,
         }
     }
-    pub fn tick(&mut self, random: u8) {
+}
+
+impl Game for SnakeState {
+    fn tick(&mut self, random: u8) {
         if self.game_over {
             return;
         }
@@ -53,27 +66,18 @@ impl SnakeState {
         let (x, y) = self.head;
         let width = WIDTH as i8;
         let height = HEIGHT as i8;
+        let off_grid = x >= width || x < 0 || y >= height || y < 0;
+        if off_grid && self.wrap {
+            // Come back on the other side
+            self.head = (x.rem_euclid(width), y.rem_euclid(height));
+        }
 
-        if self.body.contains(&self.head) {
+        if off_grid && !self.wrap {
+            // Hit an edge
+            self.game_over = true
+        } else if self.body.contains(&self.head) {
             // Ran into itself
             self.game_over = true
-        } else if x >= width || x < 0 || y >= height || y < 0 {
-            // Hit an edge
-            if WRAP_ENABLE {
-                self.head = if x >= width {
-                    (0, y)
-                } else if x < 0 {
-                    (width - 1, y)
-                } else if y >= height {
-                    (x, 0)
-                } else if y < 0 {
-                    (x, height - 1)
-                } else {
-                    (x, y)
-                };
-            } else {
-                self.game_over = true
-            }
         } else if self.head == self.food {
             // Eating food and growing
             self.body.insert(0, oldhead).unwrap();
@@ -85,16 +89,10 @@ impl SnakeState {
         }
     }
 
-    pub fn handle_control(&mut self, arg: &GameControlArg) {
-        match arg {
-            GameControlArg::Up => self.direction = HeadDirection::Up,
-            GameControlArg::Down => self.direction = HeadDirection::Down,
-            GameControlArg::Left => self.direction = HeadDirection::Left,
-            GameControlArg::Right => self.direction = HeadDirection::Right,
-            _ => {}
+    fn draw(&self) -> Grid {
+        if self.game_over {
+            return display_number(self.body.len() as u16);
         }
-    }
-    pub fn draw_matrix(&self) -> Grid {
         let (x, y) = self.head;
         let mut grid = Grid::default();
 
@@ -107,6 +105,36 @@ impl SnakeState {
 
         grid
     }
+
+    fn control(&mut self, arg: &GameControlArg) {
+        match arg {
+            GameControlArg::Up => self.direction = HeadDirection::Up,
+            GameControlArg::Down => self.direction = HeadDirection::Down,
+            GameControlArg::Left => self.direction = HeadDirection::Left,
+            GameControlArg::Right => self.direction = HeadDirection::Right,
+            _ => {}
+        }
+    }
+
+    fn tick_period(&self) -> u64 {
+        if self.speed_up {
+            START_PERIOD
+                .saturating_sub(PERIOD_DECREASE * self.body.len() as u64)
+                .max(MIN_PERIOD)
+        } else {
+            START_PERIOD
+        }
+    }
+
+    fn status(&self) -> GameStatus {
+        GameStatus {
+            game: Some(GameVal::Snake),
+            game_over: self.game_over,
+            score: self.body.len() as u16,
+            snake_length: self.body.len() as u16 + 1,
+            ..Default::default()
+        }
+    }
 }
 
 fn place_food(random: u8) -> Position {
@@ -116,33 +144,6 @@ fn place_food(random: u8) -> Position {
     (x as i8, y as i8)
 }
 
-pub fn start_game(state: &mut LedmatrixState, random: u8) {
-    state.game = Some(GameState::Snake(SnakeState::new(random)));
-}
-
-pub fn handle_control(state: &mut LedmatrixState, arg: &GameControlArg) {
-    if let Some(GameState::Snake(ref mut snake_state)) = state.game {
-        match arg {
-            GameControlArg::Exit => state.game = None,
-            _ => snake_state.handle_control(arg),
-        }
-    }
-}
-
-pub fn game_step(state: &mut LedmatrixState, random: u8) -> (HeadDirection, bool, usize, Position) {
-    if let Some(GameState::Snake(ref mut snake_state)) = state.game {
-        snake_state.tick(random);
-
-        if !snake_state.game_over {
-            state.grid = snake_state.draw_matrix();
-        }
-        (
-            snake_state.direction,
-            snake_state.game_over,
-            snake_state.body.len(),
-            snake_state.head,
-        )
-    } else {
-        (HeadDirection::Down, true, 0, (0, 0))
-    }
+pub fn start_game(state: &mut LedmatrixState, random: u8, param: SnakeParam) {
+    state.game = Some(GameState::Snake(SnakeState::new(random, param)));
 }
@@ -1,5 +1,6 @@
-use crate::control::GameControlArg;
+use crate::control::{GameControlArg, SnakeParam};
 use crate::games::snake::SnakeState;
+use crate::games::Game;
 use crate::matrix::Grid;
 
 pub struct SnakeIterator {
@@ -11,7 +12,7 @@ pub struct SnakeIterator {
 impl SnakeIterator {
     pub fn new(random: u8) -> Self {
         Self {
-            state: SnakeState::new(random),
+            state: SnakeState::new(random, SnakeParam::default()),
             commands: SAMPLE_GAME,
             current_tick: 0,
         }
@@ -35,7 +36,7 @@ impl Iterator for SnakeIterator {
         if self.current_tick.is_multiple_of(4) {
             let (maybe_cmd, random) = self.commands[self.current_tick / 4];
             if let Some(command) = maybe_cmd {
-                self.state.handle_control(&command);
+                self.state.control(&command);
             }
             self.state.tick(random);
         }
@@ -44,7 +45,7 @@ impl Iterator for SnakeIterator {
         if self.state.game_over {
             None
         } else {
-            Some(self.state.draw_matrix())
+            Some(self.state.draw())
         }
     }
 }
@@ -0,0 +1,290 @@
+use crate::control::{GameControlArg, GameStatus, GameVal};
+use crate::games::Game;
+use crate::matrix::{GameState, Grid, LedmatrixState, HEIGHT, WIDTH};
+use crate::patterns::display_number;
+
+/// Brightness of the blocks that have landed
+const LANDED_BRIGHTNESS: u8 = 0x60;
+/// Lines to clear to get to the next level
+const LINES_PER_LEVEL: u16 = 5;
+/// Points for clearing 0-4 lines at once, multiplied by the level
+const LINE_POINTS: [u16; 5] = [0, 1, 3, 5, 8];
+
+/// Blocks of a piece, relative to its position
+pub type Blocks = [(i8, i8); 4];
+
+/// The seven pieces, in the rotation they spawn in
+///
+/// (0, 0) is at the top right corner, like with snake. So x grows to the left.
+const SHAPES: [Blocks; 7] = [
+    // I
+    [(-1, 0), (0, 0), (1, 0), (2, 0)],
+    // O
+    [(0, 0), (1, 0), (0, 1), (1, 1)],
+    // T
+    [(-1, 0), (0, 0), (1, 0), (0, 1)],
+    // S
+    [(0, 0), (-1, 0), (1, 1), (0, 1)],
+    // Z
+    [(1, 0), (0, 0), (0, 1), (-1, 1)],
+    // J
+    [(1, 0), (1, 1), (0, 1), (-1, 1)],
+    // L
+    [(-1, 0), (1, 1), (0, 1), (-1, 1)],
+];
+const O_SHAPE: u8 = 1;
+
+/// Horizontal shifts to try when a rotated piece doesn't fit
+const WALL_KICKS: [i8; 5] = [0, 1, -1, 2, -2];
+
+pub type Board = [[bool; HEIGHT]; WIDTH];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Piece {
+    shape: u8,
+    pub blocks: Blocks,
+    pub x: i8,
+    pub y: i8,
+}
+
+impl Piece {
+    fn new(shape: u8) -> Self {
+        Piece {
+            shape,
+            blocks: SHAPES[shape as usize],
+            x: WIDTH as i8 / 2,
+            y: 0,
+        }
+    }
+
+    /// Positions of the blocks on the board
+    pub fn positions(&self) -> impl Iterator<Item = (i8, i8)> + '_ {
+        self.blocks.iter().map(|(x, y)| (self.x + x, self.y + y))
+    }
+
+    pub fn moved(&self, dx: i8, dy: i8) -> Self {
+        Piece {
+            x: self.x + dx,
+            y: self.y + dy,
+            ..*self
+        }
+    }
+
+    /// Rotated clockwise, as seen on the display
+    ///
+    /// The top stays in the same row, so that pieces can be rotated right
+    /// after they appear at the top.
+    pub fn rotated(&self) -> Self {
+        if self.shape == O_SHAPE {
+            return *self;
+        }
+        let top = |blocks: &Blocks| blocks.iter().map(|(_, y)| *y).min().unwrap_or(0);
+        let mut blocks = self.blocks.map(|(x, y)| (y, -x));
+        let shift = top(&self.blocks) - top(&blocks);
+        for block in blocks.iter_mut() {
+            block.1 += shift;
+        }
+        Piece { blocks, ..*self }
+    }
+}
+
+#[derive(Clone)]
+pub struct TetrisState {
+    /// Blocks that have landed
+    pub board: Board,
+    pub piece: Piece,
+    /// Shuffled set of all pieces, handed out one after another
+    bag: [u8; 7],
+    bag_index: usize,
+    rng: u16,
+    pub lines: u16,
+    pub score: u16,
+    pub game_over: bool,
+}
+
+impl TetrisState {
+    pub fn new(random: u8) -> Self {
+        let mut state = TetrisState {
+            board: [[false; HEIGHT]; WIDTH],
+            piece: Piece::new(0),
+            bag: [0, 1, 2, 3, 4, 5, 6],
+            bag_index: 0,
+            rng: u16::from_le_bytes([random, 0xA5]),
+            lines: 0,
+            score: 0,
+            game_over: false,
+        };
+        state.shuffle_bag();
+        state.piece = state.next_piece();
+        state
+    }
+
+    pub fn level(&self) -> u16 {
+        self.lines / LINES_PER_LEVEL
+    }
+
+    /// Whether the piece is inside the board and doesn't overlap landed blocks
+    pub fn fits(&self, piece: &Piece) -> bool {
+        piece.positions().all(|(x, y)| {
+            (0..WIDTH as i8).contains(&x)
+                && (0..HEIGHT as i8).contains(&y)
+                && !self.board[x as usize][y as usize]
+        })
+    }
+
+    /// Where the piece ends up when it's dropped
+    pub fn dropped(&self, piece: &Piece) -> Piece {
+        let mut piece = *piece;
+        while self.fits(&piece.moved(0, 1)) {
+            piece = piece.moved(0, 1);
+        }
+        piece
+    }
+
+    fn try_move(&mut self, piece: Piece) {
+        if self.fits(&piece) {
+            self.piece = piece;
+        }
+    }
+
+    /// Add the piece to the board, clear full lines and spawn the next piece
+    fn land(&mut self) {
+        for (x, y) in self.piece.positions() {
+            self.board[x as usize][y as usize] = true;
+        }
+
+        let cleared = self.clear_lines();
+        let points = LINE_POINTS[cleared].saturating_mul(self.level() + 1);
+        self.score = self.score.saturating_add(points);
+        self.lines = self.lines.saturating_add(cleared as u16);
+
+        self.piece = self.next_piece();
+        if !self.fits(&self.piece) {
+            self.game_over = true;
+        }
+    }
+
+    /// Remove full lines and move everything above down. Returns the number of lines.
+    fn clear_lines(&mut self) -> usize {
+        let mut cleared = 0;
+        let mut y = HEIGHT;
+        while y > 0 {
+            y -= 1;
+            if (0..WIDTH).all(|x| self.board[x][y]) {
+                for col in self.board.iter_mut() {
+                    col.copy_within(0..y, 1);
+                    col[0] = false;
+                }
+                cleared += 1;
+                // Check the same line again, the one above moved into it
+                y += 1;
+            }
+        }
+        cleared
+    }
+
+    fn next_piece(&mut self) -> Piece {
+        if self.bag_index == self.bag.len() {
+            self.shuffle_bag();
+        }
+        let shape = self.bag[self.bag_index];
+        self.bag_index += 1;
+        Piece::new(shape)
+    }
+
+    /// Refill the bag, so that every piece comes once in random order
+    fn shuffle_bag(&mut self) {
+        for i in (1..self.bag.len()).rev() {
+            let j = self.random() as usize % (i + 1);
+            self.bag.swap(i, j);
+        }
+        self.bag_index = 0;
+    }
+
+    fn random(&mut self) -> u8 {
+        // Linear congruential generator, seeded from the ring oscillator
+        self.rng = self.rng.wrapping_mul(25173).wrapping_add(13849);
+        (self.rng >> 8) as u8
+    }
+}
+
+impl Game for TetrisState {
+    /// Let the piece fall by one row
+    fn tick(&mut self, _random: u8) {
+        if self.game_over {
+            return;
+        }
+        let fallen = self.piece.moved(0, 1);
+        if self.fits(&fallen) {
+            self.piece = fallen;
+        } else {
+            self.land();
+        }
+    }
+
+    fn draw(&self) -> Grid {
+        if self.game_over {
+            return display_number(self.score);
+        }
+        let mut grid = Grid::default();
+        for x in 0..WIDTH {
+            for y in 0..HEIGHT {
+                if self.board[x][y] {
+                    grid.0[x][y] = LANDED_BRIGHTNESS;
+                }
+            }
+        }
+        for (x, y) in self.piece.positions() {
+            grid.0[x as usize][y as usize] = 0xFF;
+        }
+        grid
+    }
+
+    fn control(&mut self, arg: &GameControlArg) {
+        if self.game_over {
+            return;
+        }
+        match arg {
+            GameControlArg::Left => self.try_move(self.piece.moved(1, 0)),
+            GameControlArg::Right => self.try_move(self.piece.moved(-1, 0)),
+            GameControlArg::Up => {
+                let rotated = self.piece.rotated();
+                if let Some(kicked) = WALL_KICKS
+                    .iter()
+                    .map(|dx| rotated.moved(*dx, 0))
+                    .find(|piece| self.fits(piece))
+                {
+                    self.piece = kicked;
+                }
+            }
+            GameControlArg::Down => {
+                self.piece = self.dropped(&self.piece);
+                self.land();
+            }
+            _ => {}
+        }
+    }
+
+    /// Gets shorter with every level
+    fn tick_period(&self) -> u64 {
+        500_000u64
+            .saturating_sub(40_000 * self.level() as u64)
+            .max(100_000)
+    }
+
+    fn status(&self) -> GameStatus {
+        GameStatus {
+            game: Some(GameVal::Tetris),
+            game_over: self.game_over,
+            score: self.score,
+            level: self.level(),
+            ..Default::default()
+        }
+    }
+}
+
+pub fn start_game(state: &mut LedmatrixState, random: u8) {
+    let tetris_state = TetrisState::new(random);
+    state.grid = tetris_state.draw();
+    state.game = Some(GameState::Tetris(tetris_state));
+}
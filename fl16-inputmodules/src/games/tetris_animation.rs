@@ -0,0 +1,112 @@
+use crate::control::GameControlArg;
+use crate::games::tetris::{Piece, TetrisState};
+use crate::games::Game;
+use crate::matrix::{Grid, HEIGHT, WIDTH};
+
+/// Tetris playing itself
+///
+/// For every new piece, it picks the spot that leaves the fewest holes and
+/// then steers the piece there, one move per frame.
+pub struct TetrisIterator {
+    state: TetrisState,
+    /// Where the current piece should go, before dropping it
+    target: Option<Piece>,
+    frames_remaining: usize,
+}
+
+impl TetrisIterator {
+    pub fn new(random: u8, frames: usize) -> Self {
+        Self {
+            state: TetrisState::new(random),
+            target: None,
+            frames_remaining: frames,
+        }
+    }
+}
+
+impl Default for TetrisIterator {
+    fn default() -> Self {
+        Self::new(31, 200)
+    }
+}
+
+impl Iterator for TetrisIterator {
+    type Item = Grid;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frames_remaining == 0 || self.state.game_over {
+            return None;
+        }
+        self.frames_remaining -= 1;
+
+        let target = *self.target.get_or_insert_with(|| best_spot(&self.state));
+        let piece = self.state.piece;
+        let command = if piece.blocks != target.blocks {
+            GameControlArg::Up
+        } else if piece.x < target.x {
+            GameControlArg::Left
+        } else if piece.x > target.x {
+            GameControlArg::Right
+        } else {
+            GameControlArg::Down
+        };
+        self.state.control(&command);
+        // Drop the piece where it is, if it's stuck on the way
+        let stuck = command != GameControlArg::Down && self.state.piece == piece;
+        if stuck {
+            self.state.control(&GameControlArg::Down);
+        }
+        if command == GameControlArg::Down || stuck {
+            // The next piece needs a new target
+            self.target = None;
+        }
+
+        Some(self.state.draw())
+    }
+}
+
+/// Find the rotation and position that lands the piece in the best spot
+fn best_spot(state: &TetrisState) -> Piece {
+    let mut best = state.piece;
+    let mut best_score = i32::MIN;
+    let mut rotated = state.piece;
+    for _ in 0..4 {
+        for shift in -(WIDTH as i8)..WIDTH as i8 {
+            let piece = rotated.moved(shift, 0);
+            if !state.fits(&piece) {
+                continue;
+            }
+            let score = rate(state, &state.dropped(&piece));
+            if score > best_score {
+                best = piece;
+                best_score = score;
+            }
+        }
+        rotated = rotated.rotated();
+    }
+    best
+}
+
+/// How good it is to land the piece here. Higher is better.
+fn rate(state: &TetrisState, piece: &Piece) -> i32 {
+    let mut board = state.board;
+    for (x, y) in piece.positions() {
+        board[x as usize][y as usize] = true;
+    }
+    let full_lines = (0..HEIGHT)
+        .filter(|&y| (0..WIDTH).all(|x| board[x][y]))
+        .count() as i32;
+
+    let mut heights = [0; WIDTH];
+    let mut holes = 0;
+    for (x, col) in board.iter().enumerate() {
+        if let Some(top) = col.iter().position(|block| *block) {
+            heights[x] = (HEIGHT - top) as i32;
+            holes += col[top..].iter().filter(|block| !**block).count() as i32;
+        }
+    }
+    let total_height: i32 = heights.iter().sum();
+    let bumpiness: i32 = heights.windows(2).map(|w| (w[0] - w[1]).abs()).sum();
+
+    76 * full_lines - 51 * total_height - 36 * holes - 18 * bumpiness
+}
@@ -12,7 +12,7 @@ compile_error!("Features \"ledmatrix\", \"b1display\", and \"c1minimal\" are mut
 pub mod fl16;
 #[cfg(feature = "ledmatrix")]
 pub mod games;
-#[cfg(feature = "ledmatrix")]
+#[cfg(all(feature = "ledmatrix", feature = "rp2040"))]
 pub mod led_hal;
 #[cfg(feature = "ledmatrix")]
 #[rustfmt::skip]
@@ -37,4 +37,6 @@ pub mod qtpy_hal;
 
 pub mod control;
 pub mod serialnum;
+pub mod settings;
+#[cfg(feature = "ledmatrix")]
 pub mod addon;
@@ -313,6 +313,94 @@ pub const ONE: SingleDisplayData = [
     0b00000100,
     0b00000100,
 ];
+/// Number 2
+pub const TWO: SingleDisplayData = [
+    0b00111000,
+    0b01000100,
+    0b00000100,
+    0b00001000,
+    0b00010000,
+    0b00100000,
+    0b01000000,
+    0b01111100,
+];
+/// Number 3
+pub const THREE: SingleDisplayData = [
+    0b00111000,
+    0b01000100,
+    0b00000100,
+    0b00011000,
+    0b00000100,
+    0b00000100,
+    0b01000100,
+    0b00111000,
+];
+/// Number 4
+pub const FOUR: SingleDisplayData = [
+    0b00001000,
+    0b00011000,
+    0b00101000,
+    0b01001000,
+    0b01111100,
+    0b00001000,
+    0b00001000,
+    0b00001000,
+];
+/// Number 5
+pub const FIVE: SingleDisplayData = [
+    0b01111100,
+    0b01000000,
+    0b01000000,
+    0b01111000,
+    0b00000100,
+    0b00000100,
+    0b01000100,
+    0b00111000,
+];
+/// Number 6
+pub const SIX: SingleDisplayData = [
+    0b00111000,
+    0b01000000,
+    0b01000000,
+    0b01111000,
+    0b01000100,
+    0b01000100,
+    0b01000100,
+    0b00111000,
+];
+/// Number 7
+pub const SEVEN: SingleDisplayData = [
+    0b01111100,
+    0b00000100,
+    0b00000100,
+    0b00001000,
+    0b00010000,
+    0b00010000,
+    0b00010000,
+    0b00010000,
+];
+/// Number 8
+pub const EIGHT: SingleDisplayData = [
+    0b00111000,
+    0b01000100,
+    0b01000100,
+    0b00111000,
+    0b01000100,
+    0b01000100,
+    0b01000100,
+    0b00111000,
+];
+/// Number 9
+pub const NINE: SingleDisplayData = [
+    0b00111000,
+    0b01000100,
+    0b01000100,
+    0b01000100,
+    0b00111100,
+    0b00000100,
+    0b00000100,
+    0b00111000,
+];
 /// " " character
 pub const SPACE: SingleDisplayData = [0; 8];
 /// "." character
@@ -338,3 +426,50 @@ pub const HASH: SingleDisplayData = [
     0b11100100,
     0b00100100,
 ];
+
+/// Glyph of a character, if the font has one. Lowercase letters are shown as uppercase.
+pub fn glyph(c: char) -> Option<SingleDisplayData> {
+    Some(match c.to_ascii_uppercase() {
+        'A' => CAP_A,
+        'B' => CAP_B,
+        'C' => CAP_C,
+        'D' => CAP_D,
+        'E' => CAP_E,
+        'F' => CAP_F,
+        'G' => CAP_G,
+        'H' => CAP_H,
+        'I' => CAP_I,
+        'J' => CAP_J,
+        'K' => CAP_K,
+        'L' => CAP_L,
+        'M' => CAP_M,
+        'N' => CAP_N,
+        'O' => CAP_O,
+        'P' => CAP_P,
+        'Q' => CAP_Q,
+        'R' => CAP_R,
+        'S' => CAP_S,
+        'T' => CAP_T,
+        'U' => CAP_U,
+        'V' => CAP_V,
+        'W' => CAP_W,
+        'X' => CAP_X,
+        'Y' => CAP_Y,
+        'Z' => CAP_Z,
+        '0' => ZERO,
+        '1' => ONE,
+        '2' => TWO,
+        '3' => THREE,
+        '4' => FOUR,
+        '5' => FIVE,
+        '6' => SIX,
+        '7' => SEVEN,
+        '8' => EIGHT,
+        '9' => NINE,
+        ' ' => SPACE,
+        '.' => DOT,
+        '!' => EXCLAMATION_MARK,
+        '#' => HASH,
+        _ => return None,
+    })
+}
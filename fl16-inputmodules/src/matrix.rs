@@ -1,14 +1,15 @@
-use heapless::Vec;
-use crate::addon::{AddonAnimation, VisualKeypress};
+use crate::addon::{self, AddonAnimation, VisualKeypress};
 use crate::animations::*;
-use crate::control::PwmFreqArg;
+use crate::control::{Command, GammaCurve, Orientation, PwmFreqArg, GAMMA_LUT_SIZE};
 use crate::games::game_of_life::GameOfLifeState;
 use crate::games::pong::PongState;
 use crate::games::snake::SnakeState;
+use crate::games::tetris::TetrisState;
+use crate::games::Game;
+use crate::patterns::{fill_grid_pixels, LedDriver};
+use heapless::Vec;
 
-pub const WIDTH: usize = 9;
-pub const HEIGHT: usize = 34;
-pub const LEDS: usize = WIDTH * HEIGHT;
+pub use inputmodule_protocol::{Side, HEIGHT, LEDS, WIDTH};
 
 #[derive(Clone)]
 pub struct Grid(pub [[u8; HEIGHT]; WIDTH]);
@@ -26,20 +27,6 @@ impl Grid {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
-pub enum Side {
-    Left,
-    Right,
-}
-impl Side {
-    pub fn is_left(&self) -> bool {
-        matches!(self, Side::Left)
-    }
-    pub fn is_right(&self) -> bool {
-        matches!(self, Side::Right)
-    }
-}
-
 pub struct LedmatrixState {
     // addon stuff
     /// list of keypresses for use in keyboard-reactive patterns. tries to clear elements when their life is zero.
@@ -57,6 +44,14 @@ pub struct LedmatrixState {
     pub animate: bool,
     /// LED brightness out of 255
     pub brightness: u8,
+    /// Curve that maps pixel values to LED brightness
+    pub gamma: GammaCurve,
+    /// Lookup table of the current gamma curve, see `patterns::set_gamma`
+    pub gamma_lut: [u8; GAMMA_LUT_SIZE],
+    /// Lookup table uploaded by the host, for the custom gamma curve
+    pub custom_gamma_lut: [u8; GAMMA_LUT_SIZE],
+    /// How the grid is transformed when it's displayed
+    pub orientation: Orientation,
     /// Current sleep state
     pub sleeping: SleepState,
     /// State of the current game, if any
@@ -73,6 +68,89 @@ pub struct LedmatrixState {
     /// - No automatic sleeping
     pub debug_mode: bool,
     pub upcoming_frames: Option<Animation>,
+    /// Frames of the custom animation, uploaded by the host
+    pub custom_frames: CustomFrames,
+}
+
+/// Time between game steps in microseconds, if no game is running
+const IDLE_GAME_PERIOD: u64 = 500_000;
+
+/// Hardware independent steps of the main loop
+///
+/// The firmware and the simulator call them, and take care of timing, sleeping
+/// and talking to the host themselves.
+impl LedmatrixState {
+    /// Periodic display update, only while awake
+    ///
+    /// Shows the current grid and advances animations by one frame.
+    pub fn render<L: LedDriver>(&mut self, matrix: &mut L) {
+        if let Some(ref mut upcoming) = self.upcoming_frames {
+            if let Some(next_frame) = upcoming.next() {
+                self.grid = next_frame;
+            } else {
+                // Animation is over. Clear screen
+                self.grid = Grid::default();
+            }
+        }
+
+        fill_grid_pixels(self, matrix);
+        if self.animate {
+            for x in 0..WIDTH {
+                self.grid.0[x].rotate_right(1);
+            }
+        }
+
+        // manage visual keypresses
+        for keypress in self.visual_keypresses.iter_mut() {
+            if keypress.alive {
+                continue;
+            }
+            keypress.life -= 1;
+        }
+        self.visual_keypresses.retain(|kp| kp.life > 0);
+
+        if let Some(addon_animation) = &self.addon_animation {
+            self.grid = addon::draw_addon_animation(self, addon_animation);
+        }
+
+        self.timer += 1;
+    }
+
+    /// Time until the next game step in microseconds
+    pub fn game_period(&self) -> u64 {
+        match self.game {
+            Some(ref game) => game.game().tick_period(),
+            None => IDLE_GAME_PERIOD,
+        }
+    }
+
+    /// Prepare for handling a command from the host
+    ///
+    /// Returns whether the module should sleep. Every command except going to
+    /// sleep wakes it up, much more convenient than having to send the wakeup
+    /// command.
+    pub fn receive_command(&mut self, command: &Command) -> bool {
+        // Make sure sleep animation only goes up to newly set brightness,
+        // if setting the brightness causes wakeup
+        if let SleepState::Sleeping((ref grid, _)) = self.sleeping {
+            if let Command::SetBrightness(new_brightness) = command {
+                self.sleeping = SleepState::Sleeping((grid.clone(), *new_brightness));
+            }
+        }
+
+        // If there's a very early command, cancel the startup animation.
+        // Custom animations and scrolling text keep playing until
+        // something else is drawn.
+        if !self
+            .upcoming_frames
+            .as_ref()
+            .is_some_and(Animation::keeps_playing)
+        {
+            self.upcoming_frames = None;
+        }
+
+        matches!(command, Command::Sleep(true))
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -98,4 +176,25 @@ pub enum GameState {
     Snake(SnakeState),
     Pong(PongState),
     GameOfLife(GameOfLifeState),
+    Tetris(TetrisState),
+}
+
+impl GameState {
+    pub fn game(&self) -> &dyn Game {
+        match self {
+            GameState::Snake(snake) => snake,
+            GameState::Pong(pong) => pong,
+            GameState::GameOfLife(gol) => gol,
+            GameState::Tetris(tetris) => tetris,
+        }
+    }
+
+    pub fn game_mut(&mut self) -> &mut dyn Game {
+        match self {
+            GameState::Snake(snake) => snake,
+            GameState::Pong(pong) => pong,
+            GameState::GameOfLife(gol) => gol,
+            GameState::Tetris(tetris) => tetris,
+        }
+    }
 }
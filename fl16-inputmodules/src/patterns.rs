@@ -1,3 +1,7 @@
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+#[cfg(feature = "rp2040")]
 use rp2040_hal::{
     gpio::{
         bank0::{Gpio26, Gpio27},
@@ -6,18 +10,33 @@ use rp2040_hal::{
     pac::I2C1,
 };
 
+#[cfg(feature = "rp2040")]
+use crate::control::pwm_freq;
+#[cfg(feature = "rp2040")]
 use crate::led_hal as bsp;
 use crate::mapping::*;
 use crate::matrix::*;
+#[cfg(feature = "rp2040")]
 use is31fl3741::devices::LedMatrix;
 
-/// Bytes needed to represent all LEDs with a single bit
-/// math.ceil(WIDTH * HEIGHT / 8)
-pub const DRAW_BYTES: usize = 39;
+pub use inputmodule_protocol::DRAW_BYTES;
+use inputmodule_protocol::{
+    ErrorCode, GammaCurve, GreyFrame, PwmFreqArg, GAMMA_LUT_SIZE, MAX_SCROLL_TEXT_LEN,
+};
 
 /// Maximum number of brightneses levels
 pub const BRIGHTNESS_LEVELS: u8 = 255;
 
+/// The LEDs that the grid is shown on
+///
+/// On the module that's the LED controller, on the host it can be a simulation.
+pub trait LedDriver {
+    /// Set the brightness of every LED, indexed by x and y like the grid
+    fn fill_pixels(&mut self, brightnesses: &Grid);
+    fn set_pwm_freq(&mut self, freq: PwmFreqArg);
+}
+
+#[cfg(feature = "rp2040")]
 pub type Foo = LedMatrix<
     bsp::hal::I2C<
         I2C1,
@@ -53,6 +72,18 @@ pub fn draw_grey_col(grid: &mut Grid, col: u8, levels: &[u8; HEIGHT]) {
     grid.0[8 - col as usize][..HEIGHT].copy_from_slice(&levels[..HEIGHT]);
 }
 
+/// Draw a whole greyscale frame. Columns are mirrored, just like in draw_grey_col
+///
+/// The grid is only changed if the frame is valid.
+pub fn draw_grey_frame(grid: &mut Grid, frame: &GreyFrame) -> Result<(), ErrorCode> {
+    let mut cols = grid.0;
+    cols.reverse();
+    frame.decode(&mut cols)?;
+    cols.reverse();
+    grid.0 = cols;
+    Ok(())
+}
+
 pub fn display_sleep_reason(sleep_reason: SleepReason) -> Grid {
     let mut grid = Grid::default();
 
@@ -256,6 +287,73 @@ pub fn display_letter(pos: usize, grid: &mut Grid, letter: SingleDisplayData) {
     }
 }
 
+/// Rows of a text laid out with [`text_rows`]
+///
+/// Every character takes at most 9 rows, its glyph and the gap after it.
+pub type TextRows = Vec<u8, { MAX_SCROLL_TEXT_LEN * 9 }>;
+
+/// Width of a space between words, in rows
+const SPACE_ROWS: usize = 3;
+
+/// Lay out text with sideways letters, like `display_letter` does
+///
+/// Returns the rows of the text in reading order, from the first letter to the
+/// last. Bit `n` of a row lights up column `8 - n`. Letters are only as wide as
+/// their glyph, with an empty row in between. Characters that the font doesn't
+/// have are shown as space.
+pub fn text_rows(text: &str) -> TextRows {
+    let mut rows = TextRows::new();
+    for c in text.chars() {
+        let letter = glyph(c).unwrap_or(SPACE);
+        // Columns of the glyph that light up any pixel, bit 7 is the leftmost
+        let used = letter.iter().fold(0, |used, line| used | line);
+        if used == 0 {
+            for _ in 0..SPACE_ROWS {
+                let _ = rows.push(0);
+            }
+        } else {
+            let first = 7 - used.leading_zeros();
+            let last = used.trailing_zeros();
+            for bit in (last..=first).rev() {
+                let row = letter
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, line)| *line & (1 << bit) > 0)
+                    .fold(0, |row, (y, _)| row | (1 << y));
+                let _ = rows.push(row);
+            }
+        }
+        let _ = rows.push(0);
+    }
+    rows
+}
+
+/// Draw a row of [`text_rows`] on row `y` of the grid
+pub fn draw_text_row(grid: &mut Grid, y: usize, row: u8) {
+    for bit in 0..8 {
+        if row & (1 << bit) > 0 {
+            grid.0[8 - bit][y] = 0xFF;
+        }
+    }
+}
+
+/// Show a number in the middle of the display, like a game's score
+pub fn display_number(number: u16) -> Grid {
+    let mut text: String<5> = String::new();
+    write!(&mut text, "{}", number).unwrap();
+    let rows = text_rows(&text);
+
+    let mut grid = Grid::default();
+    // Letters are read from bottom to top
+    let bottom = (HEIGHT + rows.len()) / 2;
+    for (i, row) in rows.iter().enumerate() {
+        if let Some(y) = bottom.checked_sub(i + 1) {
+            draw_text_row(&mut grid, y, *row);
+        }
+    }
+    grid
+}
+
 /// Gradient getting brighter from top to bottom
 pub fn gradient() -> Grid {
     let gradient_drop = 1; // Brightness drop between rows
@@ -308,8 +406,29 @@ pub fn double_gradient() -> Grid {
     grid
 }
 
+#[cfg(feature = "rp2040")]
+impl LedDriver for Foo {
+    /// Just sends two I2C commands for the entire grid
+    fn fill_pixels(&mut self, brightnesses: &Grid) {
+        // 0xB4 LEDs on the first page, 0xAB on the second page
+        let mut registers = [0x00; 0xB4 + 0xAB];
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let (register, page) = (self.device.calc_pixel)(x as u8, y as u8);
+                registers[(page as usize) * 0xB4 + (register as usize)] = brightnesses.0[x][y];
+            }
+        }
+        self.device.fill_matrix(&registers).unwrap();
+    }
+
+    fn set_pwm_freq(&mut self, freq: PwmFreqArg) {
+        self.device.set_pwm_freq(pwm_freq(freq)).unwrap();
+    }
+}
+
 /// Same as fill_grid_pixels but does each pixel individually
 /// So it's much slower because it has to send 306 I2C commands
+#[cfg(feature = "rp2040")]
 pub fn _fill_grid(grid: &Grid, matrix: &mut Foo) {
     for y in 0..HEIGHT {
         for x in 0..WIDTH {
@@ -318,26 +437,70 @@ pub fn _fill_grid(grid: &Grid, matrix: &mut Foo) {
     }
 }
 
-pub fn set_brightness(state: &mut LedmatrixState, brightness: u8, matrix: &mut Foo) {
+/// Gamma lookup table that doesn't change the brightness
+pub const LINEAR_GAMMA_LUT: [u8; GAMMA_LUT_SIZE] = {
+    let mut lut = [0; GAMMA_LUT_SIZE];
+    let mut i = 0;
+    while i < GAMMA_LUT_SIZE {
+        lut[i] = i as u8;
+        i += 1;
+    }
+    lut
+};
+
+/// Calculate the lookup table of the gamma curve
+pub fn gamma_lut(curve: GammaCurve, custom: &[u8; GAMMA_LUT_SIZE]) -> [u8; GAMMA_LUT_SIZE] {
+    let mut lut = LINEAR_GAMMA_LUT;
+    for val in lut.iter_mut() {
+        let x = *val as f32 / 255.0;
+        let y = match curve {
+            GammaCurve::Linear => return LINEAR_GAMMA_LUT,
+            GammaCurve::Custom => return *custom,
+            GammaCurve::Gamma22 => libm::powf(x, 2.2),
+            GammaCurve::Cie => {
+                let lightness = x * 100.0;
+                if lightness <= 8.0 {
+                    lightness / 903.3
+                } else {
+                    libm::powf((lightness + 16.0) / 116.0, 3.0)
+                }
+            }
+        };
+        *val = libm::roundf(y * 255.0) as u8;
+    }
+    lut
+}
+
+pub fn set_gamma(state: &mut LedmatrixState, curve: GammaCurve) {
+    state.gamma = curve;
+    // Calculating the curve is slow, so only do it once
+    state.gamma_lut = gamma_lut(curve, &state.custom_gamma_lut);
+}
+
+pub fn set_brightness<L: LedDriver>(state: &mut LedmatrixState, brightness: u8, matrix: &mut L) {
     state.brightness = brightness;
     fill_grid_pixels(state, matrix);
 }
 
-/// Just sends two I2C commands for the entire grid
-pub fn fill_grid_pixels(state: &LedmatrixState, matrix: &mut Foo) {
-    // 0xB4 LEDs on the first page, 0xAB on the second page
-    let mut brightnesses = [0x00; 0xB4 + 0xAB];
+/// Show the grid on the LEDs, with orientation, gamma curve and brightness applied
+pub fn fill_grid_pixels<L: LedDriver>(state: &LedmatrixState, matrix: &mut L) {
+    let mirror_x = state.orientation.mirrors_x();
+    let mirror_y = state.orientation.mirrors_y();
+    let mut brightnesses = Grid::default();
     for y in 0..HEIGHT {
         for x in 0..WIDTH {
-            let (register, page) = (matrix.device.calc_pixel)(x as u8, y as u8);
-            brightnesses[(page as usize) * 0xB4 + (register as usize)] =
-                ((state.grid.0[x][y] as u64) * (state.brightness as u64)
-                    / (BRIGHTNESS_LEVELS as u64)) as u8;
+            // Pixel of the grid that's displayed on this LED
+            let grid_x = if mirror_x { WIDTH - 1 - x } else { x };
+            let grid_y = if mirror_y { HEIGHT - 1 - y } else { y };
+            let val = state.gamma_lut[state.grid.0[grid_x][grid_y] as usize];
+            brightnesses.0[x][y] =
+                ((val as u64) * (state.brightness as u64) / (BRIGHTNESS_LEVELS as u64)) as u8;
         }
     }
-    matrix.device.fill_matrix(&brightnesses).unwrap();
+    matrix.fill_pixels(&brightnesses);
 }
 
+#[cfg(feature = "rp2040")]
 pub fn full_brightness(matrix: &mut Foo) {
     // Fills every pixel individually
     //matrix.fill_brightness(0xFF).unwrap();
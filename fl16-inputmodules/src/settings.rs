@@ -0,0 +1,397 @@
+//! Persistent settings, stored in the flash
+//!
+//! Settings are stored as a log of key/value records in two 4K sectors of the
+//! "Persistent Storage" region (see `flash_layout.md`). Changing a setting
+//! appends a record, the newest record of a key wins. Only when a sector is
+//! full, the newest records are copied to the other sector and the full one
+//! is erased. That way every sector is erased as rarely as possible.
+//!
+//! Record layout (16 bytes):
+//!
+//! | Bytes | Content                                |
+//! |-------|----------------------------------------|
+//! | 1     | Key, 0xFF if the record is unused      |
+//! | 1     | Length of the value                    |
+//! | 10    | Value, padded with 0xFF                |
+//! | 4     | CRC32 of key, length and value         |
+//!
+//! The first record of a sector is a header with a sequence number.
+//! Only a sector with a valid header is used. If both are valid, the one with
+//! the higher sequence number is the current one.
+#[cfg(feature = "rp2040")]
+use rp2040_hal::rom_data;
+
+/// Offset of the persistent storage from the start of the flash
+pub const STORAGE_OFFSET: u32 = 0xFD000;
+/// Size of a flash sector, the smallest unit that can be erased
+pub const SECTOR_SIZE: usize = 4096;
+/// Size of a flash page, the unit that is programmed at once
+pub const PAGE_SIZE: usize = 256;
+/// Maximum size of a single value
+pub const MAX_VALUE_SIZE: usize = 10;
+
+const RECORD_SIZE: usize = 16;
+const RECORDS_PER_SECTOR: usize = SECTOR_SIZE / RECORD_SIZE;
+const RECORDS_PER_PAGE: usize = PAGE_SIZE / RECORD_SIZE;
+/// Key of the sector header record
+const HEADER_KEY: u8 = 0x00;
+/// Key of an unused (erased) record
+const UNUSED_KEY: u8 = 0xFF;
+
+/// Flash is mapped into memory at this address
+#[cfg(feature = "rp2040")]
+const XIP_BASE: usize = 0x1000_0000;
+
+const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Access to the persistent storage region of the flash
+///
+/// Offsets are relative to the start of the region.
+pub trait Flash {
+    fn read(&self, offset: usize, buf: &mut [u8]);
+    fn erase_sector(&mut self, offset: usize);
+    /// Program a page. Bits can only be cleared, bytes that are 0xFF stay unchanged.
+    fn program_page(&mut self, offset: usize, data: &[u8; PAGE_SIZE]);
+}
+
+/// Internal flash of the RP2040, written through the bootrom functions
+#[cfg(feature = "rp2040")]
+pub struct RomFlash;
+
+#[cfg(feature = "rp2040")]
+impl Flash for RomFlash {
+    fn read(&self, offset: usize, buf: &mut [u8]) {
+        // Flash is mapped into memory, just read it from there
+        let ptr = (XIP_BASE + STORAGE_OFFSET as usize + offset) as *const u8;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile(ptr.add(i)) };
+        }
+    }
+
+    fn erase_sector(&mut self, offset: usize) {
+        let addr = STORAGE_OFFSET + offset as u32;
+        cortex_m::interrupt::free(|_| unsafe { write_flash(addr, None) });
+    }
+
+    fn program_page(&mut self, offset: usize, data: &[u8; PAGE_SIZE]) {
+        let addr = STORAGE_OFFSET + offset as u32;
+        cortex_m::interrupt::free(|_| unsafe { write_flash(addr, Some(data)) });
+    }
+}
+
+/// Erase a sector or program a page
+///
+/// While writing, the flash can't be read. So this function runs from RAM and
+/// must not call any code in flash, after leaving XIP mode.
+/// Interrupts must be disabled.
+#[cfg(feature = "rp2040")]
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn write_flash(addr: u32, data: Option<&[u8; PAGE_SIZE]>) {
+    // Look up everything while the flash can still be read
+    let connect_internal_flash = rom_data::connect_internal_flash::ptr();
+    let flash_exit_xip = rom_data::flash_exit_xip::ptr();
+    let flash_range_erase = rom_data::flash_range_erase::ptr();
+    let flash_range_program = rom_data::flash_range_program::ptr();
+    let flash_flush_cache = rom_data::flash_flush_cache::ptr();
+
+    // boot2 sets up the fast XIP mode again afterwards. It's position independent
+    // and must run from RAM, just like this function.
+    let mut boot2 = [0u32; PAGE_SIZE / 4];
+    core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), PAGE_SIZE / 4);
+    // +1 for thumb mode
+    let enter_xip: unsafe extern "C" fn() =
+        core::mem::transmute(boot2.as_ptr() as *const u8 as usize + 1);
+
+    connect_internal_flash();
+    flash_exit_xip();
+    match data {
+        // Erase 64K blocks with 0xD8, if the range allows it, otherwise 4K sectors
+        None => flash_range_erase(addr, SECTOR_SIZE, 1 << 16, 0xD8),
+        Some(data) => flash_range_program(addr, data.as_ptr(), PAGE_SIZE),
+    }
+    flash_flush_cache();
+    enter_xip();
+}
+
+/// Key/value store for settings that survive a reboot
+pub struct SettingsStore<F: Flash> {
+    flash: F,
+    /// Sector that's currently used
+    sector: usize,
+    /// Sequence number of the current sector
+    seq: u32,
+    /// Index of the next unused record in the current sector
+    next: usize,
+}
+
+impl<F: Flash> SettingsStore<F> {
+    pub fn new(flash: F) -> Self {
+        let mut store = Self {
+            flash,
+            sector: 0,
+            seq: 0,
+            next: 1,
+        };
+
+        let headers = [store.header_seq(0), store.header_seq(1)];
+        match headers {
+            [Some(a), Some(b)] if b > a => store.open(1, b),
+            [Some(a), _] => store.open(0, a),
+            [None, Some(b)] => store.open(1, b),
+            // Nothing stored yet or everything corrupted
+            [None, None] => store.format(0, 1),
+        }
+        store
+    }
+
+    /// Read the newest value of the key into `buf`
+    ///
+    /// Returns the length of the value.
+    pub fn get(&self, key: u8, buf: &mut [u8]) -> Option<usize> {
+        let mut record = [0; RECORD_SIZE];
+        let mut found = None;
+        for index in 1..self.next {
+            self.read_record(self.sector, index, &mut record);
+            if record[0] == key && record_valid(&record) {
+                found = Some(record);
+            }
+        }
+        let record = found?;
+        let len = (record[1] as usize).min(buf.len());
+        buf[..len].copy_from_slice(&record[2..2 + len]);
+        Some(len)
+    }
+
+    /// Store a new value of the key
+    ///
+    /// Doesn't write anything if the value didn't change.
+    pub fn set(&mut self, key: u8, value: &[u8]) {
+        assert!(key != HEADER_KEY && key != UNUSED_KEY);
+        assert!(value.len() <= MAX_VALUE_SIZE);
+
+        let mut current = [0; MAX_VALUE_SIZE];
+        if let Some(len) = self.get(key, &mut current) {
+            if &current[..len] == value {
+                return;
+            }
+        }
+
+        if self.next >= RECORDS_PER_SECTOR {
+            self.compact();
+        }
+        let record = encode_record(key, value);
+        self.write_record(self.sector, self.next, &record);
+        self.next += 1;
+    }
+
+    /// Remove all settings
+    pub fn reset(&mut self) {
+        let sector = self.sector;
+        // Erase the current sector last, so the other one never has an older valid header
+        self.format(1 - sector, self.seq.wrapping_add(1));
+        self.flash.erase_sector(sector * SECTOR_SIZE);
+    }
+
+    fn open(&mut self, sector: usize, seq: u32) {
+        self.sector = sector;
+        self.seq = seq;
+        self.next = RECORDS_PER_SECTOR;
+        let mut record = [0; RECORD_SIZE];
+        for index in 1..RECORDS_PER_SECTOR {
+            self.read_record(sector, index, &mut record);
+            if record[0] == UNUSED_KEY {
+                self.next = index;
+                break;
+            }
+        }
+    }
+
+    /// Erase the sector and start using it
+    fn format(&mut self, sector: usize, seq: u32) {
+        self.flash.erase_sector(sector * SECTOR_SIZE);
+        self.write_record(sector, 0, &encode_record(HEADER_KEY, &seq.to_le_bytes()));
+        self.sector = sector;
+        self.seq = seq;
+        self.next = 1;
+    }
+
+    /// Copy the newest value of every key to the other sector and erase the full one
+    fn compact(&mut self) {
+        let old = self.sector;
+        let new = 1 - old;
+        self.flash.erase_sector(new * SECTOR_SIZE);
+
+        let mut next = 1;
+        let mut value = [0; MAX_VALUE_SIZE];
+        for key in (HEADER_KEY + 1)..UNUSED_KEY {
+            if let Some(len) = self.get(key, &mut value) {
+                self.write_record(new, next, &encode_record(key, &value[..len]));
+                next += 1;
+            }
+        }
+        // Header is written last, so the sector is only used once it's complete
+        let seq = self.seq.wrapping_add(1);
+        self.write_record(new, 0, &encode_record(HEADER_KEY, &seq.to_le_bytes()));
+        self.flash.erase_sector(old * SECTOR_SIZE);
+
+        self.sector = new;
+        self.seq = seq;
+        self.next = next;
+    }
+
+    fn header_seq(&self, sector: usize) -> Option<u32> {
+        let mut record = [0; RECORD_SIZE];
+        self.read_record(sector, 0, &mut record);
+        if record[0] != HEADER_KEY || record[1] != 4 || !record_valid(&record) {
+            return None;
+        }
+        Some(u32::from_le_bytes([
+            record[2], record[3], record[4], record[5],
+        ]))
+    }
+
+    fn read_record(&self, sector: usize, index: usize, record: &mut [u8; RECORD_SIZE]) {
+        self.flash
+            .read(sector * SECTOR_SIZE + index * RECORD_SIZE, record);
+    }
+
+    fn write_record(&mut self, sector: usize, index: usize, record: &[u8; RECORD_SIZE]) {
+        // Leave the rest of the page erased, programming 0xFF doesn't change anything
+        let mut page = [UNUSED_KEY; PAGE_SIZE];
+        let offset = (index % RECORDS_PER_PAGE) * RECORD_SIZE;
+        page[offset..offset + RECORD_SIZE].copy_from_slice(record);
+        let page_offset = sector * SECTOR_SIZE + (index / RECORDS_PER_PAGE) * PAGE_SIZE;
+        self.flash.program_page(page_offset, &page);
+    }
+}
+
+fn encode_record(key: u8, value: &[u8]) -> [u8; RECORD_SIZE] {
+    let mut record = [0xFF; RECORD_SIZE];
+    record[0] = key;
+    record[1] = value.len() as u8;
+    record[2..2 + value.len()].copy_from_slice(value);
+    let crc = CRC.checksum(&record[..RECORD_SIZE - 4]);
+    record[RECORD_SIZE - 4..].copy_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// Check that the record was completely written
+fn record_valid(record: &[u8; RECORD_SIZE]) -> bool {
+    let crc = u32::from_le_bytes([record[12], record[13], record[14], record[15]]);
+    record[1] as usize <= MAX_VALUE_SIZE && CRC.checksum(&record[..RECORD_SIZE - 4]) == crc
+}
+
+#[cfg(feature = "ledmatrix")]
+mod ledmatrix {
+    use num_traits::FromPrimitive;
+
+    use super::{Flash, SettingsStore};
+    use crate::addon::AddonAnimation;
+    use crate::control::{AddonAnimationVals, GammaCurve, Orientation, PwmFreqArg};
+    use crate::matrix::{LedmatrixState, Side};
+    use crate::patterns::set_gamma;
+
+    /// Default LED brightness, 51/255 = 20%
+    pub const DEFAULT_BRIGHTNESS: u8 = 51;
+    /// Default animation period, 31,250 us = 32 FPS
+    pub const DEFAULT_ANIMATION_PERIOD: u64 = 31_250;
+    pub const DEFAULT_PWM_FREQ: PwmFreqArg = PwmFreqArg::P29k;
+    pub const DEFAULT_SIDE: Side = Side::Left;
+    pub const DEFAULT_GAMMA: GammaCurve = GammaCurve::Linear;
+    pub const DEFAULT_ORIENTATION: Orientation = Orientation::Normal;
+
+    #[repr(u8)]
+    enum Key {
+        Brightness = 0x01,
+        PwmFreq = 0x02,
+        AddonAnimation = 0x03,
+        Side = 0x04,
+        AnimationPeriod = 0x05,
+        DebugMode = 0x06,
+        Gamma = 0x07,
+        Orientation = 0x08,
+    }
+
+    /// Stored if no addon animation is running
+    const NO_ADDON_ANIMATION: u8 = 0xFF;
+
+    impl LedmatrixState {
+        /// Apply the settings from the store. Settings that were never saved are kept.
+        pub fn load_settings<F: Flash>(&mut self, store: &SettingsStore<F>) {
+            let mut buf = [0; 8];
+            if store.get(Key::Brightness as u8, &mut buf) == Some(1) {
+                self.brightness = buf[0];
+            }
+            if store.get(Key::PwmFreq as u8, &mut buf) == Some(1) {
+                if let Some(freq) = FromPrimitive::from_u8(buf[0]) {
+                    self.pwm_freq = freq;
+                }
+            }
+            if store.get(Key::AddonAnimation as u8, &mut buf) == Some(1) {
+                self.addon_animation = match FromPrimitive::from_u8(buf[0]) {
+                    Some(AddonAnimationVals::Spiral) => Some(AddonAnimation::Spiral),
+                    Some(AddonAnimationVals::Splashes) => Some(AddonAnimation::Splashes),
+                    Some(AddonAnimationVals::Helix) => Some(AddonAnimation::Helix),
+                    None => None,
+                };
+            }
+            if store.get(Key::Side as u8, &mut buf) == Some(1) {
+                self.side = if buf[0] == 0 { Side::Left } else { Side::Right };
+            }
+            if store.get(Key::AnimationPeriod as u8, &mut buf) == Some(8) {
+                self.animation_period = u64::from_le_bytes(buf);
+            }
+            if store.get(Key::DebugMode as u8, &mut buf) == Some(1) {
+                self.debug_mode = buf[0] == 1;
+            }
+            if store.get(Key::Gamma as u8, &mut buf) == Some(1) {
+                // The custom lookup table is too large to be stored
+                match FromPrimitive::from_u8(buf[0]) {
+                    Some(GammaCurve::Custom) | None => {}
+                    Some(curve) => set_gamma(self, curve),
+                }
+            }
+            if store.get(Key::Orientation as u8, &mut buf) == Some(1) {
+                if let Some(orientation) = FromPrimitive::from_u8(buf[0]) {
+                    self.orientation = orientation;
+                }
+            }
+        }
+
+        /// Store the current settings, so that they're restored after a reboot
+        pub fn save_settings<F: Flash>(&self, store: &mut SettingsStore<F>) {
+            let addon_animation = match self.addon_animation {
+                Some(AddonAnimation::Spiral) => AddonAnimationVals::Spiral as u8,
+                Some(AddonAnimation::Splashes) => AddonAnimationVals::Splashes as u8,
+                Some(AddonAnimation::Helix) => AddonAnimationVals::Helix as u8,
+                None => NO_ADDON_ANIMATION,
+            };
+            store.set(Key::Brightness as u8, &[self.brightness]);
+            store.set(Key::PwmFreq as u8, &[self.pwm_freq as u8]);
+            store.set(Key::AddonAnimation as u8, &[addon_animation]);
+            store.set(Key::Side as u8, &[self.side.is_right() as u8]);
+            store.set(
+                Key::AnimationPeriod as u8,
+                &self.animation_period.to_le_bytes(),
+            );
+            store.set(Key::DebugMode as u8, &[self.debug_mode as u8]);
+            store.set(Key::Gamma as u8, &[self.gamma as u8]);
+            store.set(Key::Orientation as u8, &[self.orientation as u8]);
+        }
+
+        /// Go back to the default settings
+        pub fn reset_settings(&mut self) {
+            self.brightness = DEFAULT_BRIGHTNESS;
+            self.pwm_freq = DEFAULT_PWM_FREQ;
+            self.addon_animation = None;
+            self.side = DEFAULT_SIDE;
+            self.animation_period = DEFAULT_ANIMATION_PERIOD;
+            self.debug_mode = false;
+            set_gamma(self, DEFAULT_GAMMA);
+            self.orientation = DEFAULT_ORIENTATION;
+        }
+    }
+}
+#[cfg(feature = "ledmatrix")]
+pub use ledmatrix::*;
@@ -0,0 +1,20 @@
+#![cfg(feature = "ledmatrix")]
+
+use fl16_inputmodules::games::pong::PongState;
+use fl16_inputmodules::games::pong_animation::{PongIterator, SAMPLE_GAME};
+use fl16_inputmodules::games::Game;
+
+#[test]
+fn demo_without_score() {
+    // Played like the animation does it
+    let mut state = PongState::default();
+    for (step, command) in SAMPLE_GAME.iter().enumerate() {
+        if let Some(command) = command {
+            state.control(command);
+        }
+        state.tick(0);
+        assert_eq!(state.status().pong_score, [0, 0], "Scored in step {}", step);
+    }
+
+    assert_eq!(PongIterator::default().count(), SAMPLE_GAME.len());
+}
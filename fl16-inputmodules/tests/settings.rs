@@ -0,0 +1,158 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use fl16_inputmodules::settings::*;
+
+const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Flash in RAM. Clones share the same memory, to open it again after a reboot.
+#[derive(Clone)]
+struct RamFlash(Rc<RefCell<[u8; 2 * SECTOR_SIZE]>>);
+
+impl Default for RamFlash {
+    fn default() -> Self {
+        // Erased flash
+        Self(Rc::new(RefCell::new([0xFF; 2 * SECTOR_SIZE])))
+    }
+}
+
+impl RamFlash {
+    /// Program a 16 byte record, as laid out in `flash_layout.md`
+    fn write_record(&self, sector: usize, index: usize, key: u8, value: &[u8]) {
+        let mut record = [0xFF; 16];
+        record[0] = key;
+        record[1] = value.len() as u8;
+        record[2..2 + value.len()].copy_from_slice(value);
+        let crc = CRC.checksum(&record[..12]);
+        record[12..].copy_from_slice(&crc.to_le_bytes());
+
+        let offset = sector * SECTOR_SIZE + index * 16;
+        self.0.borrow_mut()[offset..offset + 16].copy_from_slice(&record);
+    }
+
+    fn write_header(&self, sector: usize, seq: u32) {
+        self.write_record(sector, 0, 0x00, &seq.to_le_bytes());
+    }
+}
+
+impl Flash for RamFlash {
+    fn read(&self, offset: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0.borrow()[offset..offset + buf.len()]);
+    }
+
+    fn erase_sector(&mut self, offset: usize) {
+        self.0.borrow_mut()[offset..offset + SECTOR_SIZE].fill(0xFF);
+    }
+
+    fn program_page(&mut self, offset: usize, data: &[u8; PAGE_SIZE]) {
+        let mut flash = self.0.borrow_mut();
+        for (byte, new) in flash[offset..offset + PAGE_SIZE].iter_mut().zip(data) {
+            *byte &= new;
+        }
+    }
+}
+
+fn get(store: &SettingsStore<RamFlash>, key: u8) -> Option<Vec<u8>> {
+    let mut buf = [0; MAX_VALUE_SIZE];
+    let len = store.get(key, &mut buf)?;
+    Some(buf[..len].to_vec())
+}
+
+#[test]
+fn values_survive_reboot() {
+    let flash = RamFlash::default();
+    let mut store = SettingsStore::new(flash.clone());
+    assert_eq!(get(&store, 1), None);
+    store.set(1, &[10]);
+    store.set(2, b"0123456789");
+    store.set(1, &[20]);
+
+    let store = SettingsStore::new(flash);
+    assert_eq!(get(&store, 1), Some(vec![20]));
+    assert_eq!(get(&store, 2), Some(b"0123456789".to_vec()));
+}
+
+#[test]
+fn compaction() {
+    let flash = RamFlash::default();
+    let mut store = SettingsStore::new(flash.clone());
+    store.set(1, &[1, 2, 3]);
+    // Fills up the first sector several times
+    for i in 0..1000u16 {
+        store.set(2, &i.to_le_bytes());
+    }
+
+    let store = SettingsStore::new(flash);
+    assert_eq!(get(&store, 1), Some(vec![1, 2, 3]));
+    assert_eq!(get(&store, 2), Some(999u16.to_le_bytes().to_vec()));
+}
+
+#[test]
+fn newest_sector_wins() {
+    let flash = RamFlash::default();
+    flash.write_header(0, 5);
+    flash.write_record(0, 1, 1, &[1]);
+    flash.write_header(1, 6);
+    flash.write_record(1, 1, 1, &[2]);
+    assert_eq!(get(&SettingsStore::new(flash.clone()), 1), Some(vec![2]));
+
+    flash.0.borrow_mut()[..SECTOR_SIZE].fill(0xFF);
+    flash.write_header(0, 7);
+    flash.write_record(0, 1, 1, &[3]);
+    assert_eq!(get(&SettingsStore::new(flash), 1), Some(vec![3]));
+}
+
+#[test]
+fn torn_header() {
+    let flash = RamFlash::default();
+    flash.write_header(0, 1);
+    flash.write_record(0, 1, 1, &[1]);
+    // Power was lost while compacting into the other sector, the header's CRC is missing
+    flash.write_header(1, 2);
+    flash.write_record(1, 1, 1, &[2]);
+    flash.0.borrow_mut()[SECTOR_SIZE + 12..SECTOR_SIZE + 16].fill(0xFF);
+
+    let mut store = SettingsStore::new(flash.clone());
+    assert_eq!(get(&store, 1), Some(vec![1]));
+    store.set(1, &[3]);
+    assert_eq!(get(&SettingsStore::new(flash.clone()), 1), Some(vec![3]));
+
+    // Nothing valid at all, starts out empty
+    flash.0.borrow_mut()[1] = 0x00;
+    let mut store = SettingsStore::new(flash.clone());
+    assert_eq!(get(&store, 1), None);
+    store.set(1, &[4]);
+    assert_eq!(get(&SettingsStore::new(flash), 1), Some(vec![4]));
+}
+
+#[test]
+fn bad_record_skipped() {
+    let flash = RamFlash::default();
+    flash.write_header(0, 0);
+    flash.write_record(0, 1, 1, &[1]);
+    flash.write_record(0, 2, 1, &[2]);
+    // Corrupt the value of the newer record
+    flash.0.borrow_mut()[2 * 16 + 2] = 0x00;
+
+    let mut store = SettingsStore::new(flash.clone());
+    assert_eq!(get(&store, 1), Some(vec![1]));
+    // New records go after the corrupted one
+    store.set(1, &[3]);
+    assert_eq!(get(&SettingsStore::new(flash), 1), Some(vec![3]));
+}
+
+#[test]
+fn reset() {
+    let flash = RamFlash::default();
+    let mut store = SettingsStore::new(flash.clone());
+    store.set(1, &[1]);
+    store.set(2, &[2]);
+    store.reset();
+    assert_eq!(get(&store, 1), None);
+    assert_eq!(get(&store, 2), None);
+
+    store.set(2, &[3]);
+    let store = SettingsStore::new(flash);
+    assert_eq!(get(&store, 1), None);
+    assert_eq!(get(&store, 2), Some(vec![3]));
+}
@@ -0,0 +1,226 @@
+//! Modules that only answer commands, without simulating what they show
+use fl16_inputmodules::control::*;
+
+use crate::module::{resets, Module, Reset};
+
+/// A module whose command handling is emulated
+pub trait Emulated: ModuleHandler {
+    fn set_sleeping(&mut self, sleeping: bool);
+
+    /// Whether the module handles the command while it's sleeping
+    fn handled_while_sleeping(_command: &Command) -> bool {
+        false
+    }
+}
+
+/// Main loop of an emulated module
+///
+/// Does the same as the main loop of the firmware: While sleeping, commands
+/// other than waking up are rejected.
+pub struct Emulator<E> {
+    pub module: E,
+    decoder: FrameDecoder,
+    time: u64,
+}
+
+impl<E: Emulated> Emulator<E> {
+    pub fn new(module: E) -> Self {
+        Self {
+            module,
+            decoder: FrameDecoder::new(),
+            time: 0,
+        }
+    }
+}
+
+impl<E: Emulated> Module for Emulator<E> {
+    fn write(&mut self, data: &[u8]) -> Result<Vec<u8>, Reset> {
+        let mut written = Vec::new();
+        self.decoder.push(data);
+        while let Some(received) = self.decoder.next_received() {
+            let (reply, command) = received.into_command();
+            let mut reply_buf = [0u8; MAX_FRAME_SIZE];
+            let len = match command {
+                Ok(Command::Sleep(go_sleeping)) => {
+                    self.module.set_sleeping(go_sleeping);
+                    reply.ack(None, &mut reply_buf)
+                }
+                Ok(command)
+                    if !self.module.is_sleeping() || E::handled_while_sleeping(&command) =>
+                {
+                    if resets(&command) {
+                        return Err(Reset);
+                    }
+                    let result = self.module.handle_command(&command);
+                    reply.respond(&result, &mut reply_buf)
+                }
+                Ok(_) => reply.nak(ErrorCode::Sleeping, &mut reply_buf),
+                Err(code) => reply.nak(code, &mut reply_buf),
+            };
+            written.extend_from_slice(&reply_buf[..len]);
+        }
+        Ok(written)
+    }
+
+    /// Nothing happens on its own, except for the time passing
+    fn step(&mut self, until: u64) -> bool {
+        self.time = until;
+        false
+    }
+
+    fn time(&self) -> u64 {
+        self.time
+    }
+}
+
+const HIGH_FPS_MASK: u8 = 0b00010000;
+const LOW_FPS_MASK: u8 = 0b00000111;
+
+/// B1 display, remembers the settings of the display
+pub struct B1Display {
+    sleeping: bool,
+    screen_on: bool,
+    screen_inverted: bool,
+    screensaver: bool,
+    power_mode: DisplayMode,
+    /// High power mode FPS in bit 4, low power mode FPS in bits 0-2
+    fps: u8,
+    /// Animation period in microseconds
+    animation_period: u64,
+}
+
+impl Default for B1Display {
+    fn default() -> Self {
+        Self {
+            sleeping: false,
+            screen_on: true,
+            screen_inverted: false,
+            screensaver: true,
+            power_mode: DisplayMode::Lpm,
+            // 32 FPS in high power mode, 2 FPS in low power mode
+            fps: HIGH_FPS_MASK | 0b011,
+            animation_period: 1_000_000,
+        }
+    }
+}
+
+impl ModuleHandler for B1Display {
+    const CAPABILITIES: Capabilities = B1DISPLAY_CAPABILITIES;
+
+    fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    fn handle_module_command(&mut self, command: &Command) -> CommandResult {
+        match command {
+            // Turn screensaver off, when drawing something
+            Command::SetText(_) | Command::SetPixelColumn(_, _) | Command::ClearRam => {
+                self.screensaver = false;
+                Ok(None)
+            }
+            Command::FlushFramebuffer => Ok(None),
+            Command::DisplayOn(on) => {
+                self.screen_on = *on;
+                Ok(None)
+            }
+            Command::GetDisplayOn => Ok(Some(Response::DisplayOn(self.screen_on).encode())),
+            Command::InvertScreen(invert) => {
+                self.screen_inverted = *invert;
+                Ok(None)
+            }
+            Command::GetInvertScreen => {
+                Ok(Some(Response::InvertScreen(self.screen_inverted).encode()))
+            }
+            Command::ScreenSaver(on) => {
+                self.screensaver = *on;
+                Ok(None)
+            }
+            Command::GetScreenSaver => Ok(Some(Response::ScreenSaver(self.screensaver).encode())),
+            Command::SetFps(fps) => {
+                // Only the frame rates that the display supports
+                if fps & !(HIGH_FPS_MASK | LOW_FPS_MASK) != 0 || fps & LOW_FPS_MASK > 5 {
+                    return Err(ErrorCode::BadArgument);
+                }
+                self.fps = *fps;
+                Ok(None)
+            }
+            Command::GetFps => Ok(Some(Response::Fps(self.fps).encode())),
+            Command::SetPowerMode(mode) => {
+                self.power_mode = *mode;
+                Ok(None)
+            }
+            Command::GetPowerMode => Ok(Some(Response::PowerMode(self.power_mode).encode())),
+            Command::SetAnimationPeriod(period) => {
+                self.animation_period = (*period as u64) * 1_000;
+                Ok(None)
+            }
+            Command::GetAnimationPeriod => {
+                let period_ms = self.animation_period / 1_000;
+                Ok(Some(Response::AnimationPeriod(period_ms as u16).encode()))
+            }
+            _ => Err(ErrorCode::Unsupported),
+        }
+    }
+}
+
+impl Emulated for B1Display {
+    fn set_sleeping(&mut self, sleeping: bool) {
+        if self.sleeping && !sleeping {
+            // Turn screensaver on when resuming from sleep
+            self.screensaver = true;
+        }
+        self.sleeping = sleeping;
+    }
+
+    fn handled_while_sleeping(command: &Command) -> bool {
+        matches!(command, Command::BootloaderReset | Command::IsSleeping)
+    }
+}
+
+/// C1 minimal module, remembers the color of its LED
+pub struct C1Minimal {
+    sleeping: bool,
+    color: [u8; 3],
+    brightness: u8,
+}
+
+impl Default for C1Minimal {
+    fn default() -> Self {
+        Self {
+            sleeping: false,
+            // Green
+            color: [0, 128, 0],
+            brightness: 10,
+        }
+    }
+}
+
+impl ModuleHandler for C1Minimal {
+    const CAPABILITIES: Capabilities = C1MINIMAL_CAPABILITIES;
+
+    fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    fn handle_module_command(&mut self, command: &Command) -> CommandResult {
+        match command {
+            Command::GetBrightness => Ok(Some(Response::Brightness(self.brightness).encode())),
+            Command::SetBrightness(br) => {
+                self.brightness = *br;
+                Ok(None)
+            }
+            Command::GetColor => Ok(Some(Response::Color(self.color).encode())),
+            Command::SetColor(color) => {
+                self.color = *color;
+                Ok(None)
+            }
+            _ => Err(ErrorCode::Unsupported),
+        }
+    }
+}
+
+impl Emulated for C1Minimal {
+    fn set_sleeping(&mut self, sleeping: bool) {
+        self.sleeping = sleeping;
+    }
+}
@@ -0,0 +1,243 @@
+mod emulated;
+mod module;
+#[cfg(unix)]
+mod pty;
+mod render;
+mod simulator;
+
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::{Parser, ValueEnum};
+use fl16_inputmodules::matrix::{Grid, HEIGHT};
+
+use crate::emulated::{B1Display, C1Minimal, Emulator};
+use crate::module::Module;
+use crate::simulator::Simulator;
+
+#[derive(Copy, Clone, Debug, PartialEq, ValueEnum)]
+enum ModuleType {
+    /// LED matrix, with everything it shows
+    LedMatrix,
+    /// B1 display, only its replies
+    B1Display,
+    /// C1 minimal, only its replies
+    C1Minimal,
+}
+
+/// Simulate the input modules, without hardware
+///
+/// Reads a script of commands to send to the module. Every line is one write
+/// to the serial port, with the bytes in hex, for example `32 ac 01 01` to show
+/// a gradient. `wait <ms>` lets the given time pass. `#` starts a comment.
+///
+/// With `--pty` the module acts as serial device instead, for the host software.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct ClapCli {
+    /// Script to run, reads from stdin if not given
+    script: Option<PathBuf>,
+
+    /// Module to simulate
+    #[arg(long, value_enum, default_value_t = ModuleType::LedMatrix)]
+    module: ModuleType,
+
+    /// Act as serial device on a pseudo-terminal, until the module resets
+    ///
+    /// Pass the device to `inputmodule-control --serial-dev`.
+    #[arg(long, conflicts_with = "script")]
+    pty: bool,
+
+    /// Create a symlink to the serial device, with a name that doesn't change
+    #[arg(long, requires = "pty")]
+    link: Option<PathBuf>,
+
+    /// Save the commands that the serial device receives as script
+    #[arg(long, requires = "pty")]
+    record: Option<PathBuf>,
+
+    /// Time to keep running after the end of the script, in milliseconds
+    #[arg(long, default_value_t = 0)]
+    duration: u64,
+
+    /// Run as fast as the real module, instead of as fast as possible
+    #[arg(long)]
+    realtime: bool,
+
+    /// Seed of the random numbers, for example for games
+    #[arg(long, default_value_t = 1)]
+    seed: u32,
+
+    /// Save every frame as PNG into this directory, instead of printing it
+    #[arg(long)]
+    png: Option<PathBuf>,
+
+    /// Size of a single LED in the PNG frames, in pixels
+    #[arg(long, default_value_t = 10)]
+    scale: u32,
+}
+
+/// Where the frames and messages go
+struct Output {
+    args: ClapCli,
+    /// Whether to draw in color and replace the previous frame
+    terminal: bool,
+    /// Last frame that was shown
+    last: Option<Grid>,
+    frames: usize,
+    /// Whether the last frame is right above the cursor and can be drawn over
+    drawn: bool,
+    start: Instant,
+}
+
+impl Output {
+    /// Show the LEDs, if they changed
+    fn show(&mut self, module: &mut dyn Module) -> Result<(), String> {
+        let time = module.time();
+        if self.args.realtime {
+            let due = self.start + Duration::from_micros(time);
+            std::thread::sleep(due.saturating_duration_since(Instant::now()));
+        }
+        let Some(leds) = module.leds() else {
+            return Ok(());
+        };
+        let updated = leds.take_updated();
+        let pixels = &leds.pixels;
+        if !updated || self.last.as_ref().is_some_and(|last| last.0 == pixels.0) {
+            return Ok(());
+        }
+
+        if let Some(dir) = &self.args.png {
+            let path = dir.join(format!("frame-{:05}.png", self.frames));
+            render::png(pixels, self.args.scale, &path)
+                .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        } else {
+            let mut stdout = std::io::stdout().lock();
+            // Draw over the previous frame
+            if self.terminal && self.drawn {
+                write!(stdout, "\x1b[{}A", HEIGHT + 1).unwrap();
+            }
+            let time = time as f64 / 1_000_000.0;
+            writeln!(stdout, "Time: {:.3}s", time).unwrap();
+            if self.terminal {
+                write!(stdout, "{}", render::terminal(pixels)).unwrap();
+            } else {
+                write!(stdout, "{}", render::text(pixels)).unwrap();
+            }
+            stdout.flush().unwrap();
+            self.drawn = true;
+        }
+        self.last = Some(pixels.clone());
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Print a message, the next frame goes below it
+    fn log(&mut self, message: &str) {
+        eprintln!("{}", message);
+        self.drawn = false;
+    }
+}
+
+/// Let the simulated time pass until `until`
+fn run_until(module: &mut dyn Module, output: &mut Output, until: u64) -> Result<(), String> {
+    while module.step(until) {
+        output.show(module)?;
+    }
+    output.show(module)
+}
+
+fn parse_hex(line: &str) -> Result<Vec<u8>, String> {
+    let digits: String = line.split_whitespace().collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err("Odd number of hex digits".to_string());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| format!("Invalid hex byte {}", &digits[i..i + 2]))
+        })
+        .collect()
+}
+
+fn run(args: ClapCli) -> Result<(), String> {
+    let mut script = String::new();
+    if let Some(path) = &args.script {
+        script = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    } else if !args.pty {
+        std::io::stdin()
+            .read_to_string(&mut script)
+            .map_err(|err| format!("Failed to read stdin: {}", err))?;
+    }
+    if let Some(dir) = &args.png {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    }
+
+    let mut module: Box<dyn Module> = match args.module {
+        ModuleType::LedMatrix => Box::new(Simulator::new(args.seed)),
+        ModuleType::B1Display => Box::new(Emulator::new(B1Display::default())),
+        ModuleType::C1Minimal => Box::new(Emulator::new(C1Minimal::default())),
+    };
+    let module = module.as_mut();
+    let duration = args.duration;
+    let pty = args.pty;
+    let mut output = Output {
+        terminal: args.png.is_none() && std::io::stdout().is_terminal(),
+        args,
+        last: None,
+        frames: 0,
+        drawn: false,
+        start: Instant::now(),
+    };
+    output.show(module)?;
+
+    if pty {
+        #[cfg(unix)]
+        return pty::serve(module, &mut output);
+        #[cfg(not(unix))]
+        return Err("Pseudo-terminals are only supported on Unix".to_string());
+    }
+
+    for (i, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |err| format!("Line {}: {}", i + 1, err);
+
+        if let Some(ms) = line.strip_prefix("wait") {
+            let ms: u64 = ms
+                .trim()
+                .parse()
+                .map_err(|_| error(format!("Invalid time {}", ms.trim())))?;
+            let until = module.time() + ms * 1_000;
+            run_until(module, &mut output, until)?;
+        } else {
+            let data = parse_hex(line).map_err(error)?;
+            let Ok(reply) = module.write(&data) else {
+                // Nothing left to talk to
+                output.log(&error("Module reset".to_string()));
+                return Ok(());
+            };
+            if !reply.is_empty() {
+                let hex: Vec<String> = reply.iter().map(|b| format!("{:02x}", b)).collect();
+                output.log(&format!("Reply: {}", hex.join(" ")));
+            }
+            output.show(module)?;
+        }
+    }
+    let until = module.time() + duration * 1_000;
+    run_until(module, &mut output, until)
+}
+
+fn main() {
+    let args = ClapCli::parse();
+    if let Err(err) = run(args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
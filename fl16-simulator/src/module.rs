@@ -0,0 +1,38 @@
+//! Common interface of the simulated and emulated modules
+use fl16_inputmodules::control::Command;
+
+use crate::simulator::SimulatedLeds;
+
+/// The module went away, like it does after resetting into the bootloader
+#[derive(Debug)]
+pub struct Reset;
+
+/// A module that the host can talk to
+pub trait Module {
+    /// Handle the data of a single write to the serial port
+    ///
+    /// Returns what the module writes back, or [`Reset`] if the module reset
+    /// instead of replying.
+    fn write(&mut self, data: &[u8]) -> Result<Vec<u8>, Reset>;
+
+    /// Let the time pass up to the next thing that happens on the module
+    ///
+    /// Stops at `until` if nothing happens before. Returns whether something happened.
+    fn step(&mut self, until: u64) -> bool;
+
+    /// Time since startup in microseconds
+    fn time(&self) -> u64;
+
+    /// LEDs of the module, if it has any that can be shown
+    fn leds(&mut self) -> Option<&mut SimulatedLeds> {
+        None
+    }
+}
+
+/// Whether the module resets after the command
+///
+/// Besides resetting into the bootloader, panicking does the same, because of
+/// the panic handler.
+pub fn resets(command: &Command) -> bool {
+    matches!(command, Command::BootloaderReset | Command::Panic)
+}
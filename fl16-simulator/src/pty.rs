@@ -0,0 +1,161 @@
+//! Serial device on a pseudo-terminal, to test the host software without hardware
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use fl16_inputmodules::control::{Command, FrameDecoder};
+use nix::fcntl::OFlag;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::pty::{grantpt, posix_openpt, ptsname_r, unlockpt};
+use nix::sys::termios::{cfmakeraw, tcgetattr, tcsetattr, SetArg};
+
+use crate::module::Module;
+use crate::{run_until, Output};
+
+/// How long to wait for data before letting the time pass, in milliseconds
+const POLL_TIMEOUT: i32 = 5;
+
+/// Short description of a command, without the large payloads of drawing commands
+fn describe(command: &Command) -> String {
+    let text = format!("{:?}", command);
+    if text.len() <= 60 {
+        return text;
+    }
+    match text.find(['(', ' ']) {
+        Some(end) => format!("{}(..)", &text[..end]),
+        None => text,
+    }
+}
+
+/// Commands received so far, saved as script that the simulator can run again
+struct Recording {
+    file: File,
+    /// When the previous command was received, in milliseconds
+    last: u64,
+}
+
+impl Recording {
+    fn record(&mut self, time: u64, data: &[u8], commands: &[String]) -> std::io::Result<()> {
+        let ms = time / 1_000;
+        if ms > self.last {
+            writeln!(self.file, "wait {}", ms - self.last)?;
+            self.last = ms;
+        }
+        for command in commands {
+            writeln!(self.file, "# {}", command)?;
+        }
+        let hex: Vec<String> = data.iter().map(|b| format!("{:02x}", b)).collect();
+        writeln!(self.file, "{}", hex.join(" "))?;
+        self.file.flush()
+    }
+}
+
+/// Point `link` at `target`, replacing the symlink of a previous run
+fn create_link(target: &str, link: &Path) -> Result<(), String> {
+    if link.is_symlink() {
+        std::fs::remove_file(link)
+            .map_err(|err| format!("Failed to remove {}: {}", link.display(), err))?;
+    }
+    std::os::unix::fs::symlink(target, link)
+        .map_err(|err| format!("Failed to create {}: {}", link.display(), err))
+}
+
+/// Act as serial device of the module, until it resets
+///
+/// The time of the module follows the wall clock.
+pub fn serve(module: &mut dyn Module, output: &mut Output) -> Result<(), String> {
+    let error = |err| format!("Failed to create pseudo-terminal: {}", err);
+    let mut master = posix_openpt(OFlag::O_RDWR | OFlag::O_NOCTTY).map_err(error)?;
+    grantpt(&master).map_err(error)?;
+    unlockpt(&master).map_err(error)?;
+    let name = ptsname_r(&master).map_err(error)?;
+
+    // Keep the device open, otherwise reading fails whenever the host closes it
+    let device = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(nix::libc::O_NOCTTY)
+        .open(&name)
+        .map_err(|err| format!("Failed to open {}: {}", name, err))?;
+    // Pass the data through unchanged, before the host configures the port
+    let mut termios = tcgetattr(device.as_raw_fd()).map_err(error)?;
+    cfmakeraw(&mut termios);
+    tcsetattr(device.as_raw_fd(), SetArg::TCSANOW, &termios).map_err(error)?;
+
+    let link = output.args.link.clone();
+    if let Some(link) = &link {
+        create_link(&name, link)?;
+        output.log(&format!("Serial device: {} -> {}", link.display(), name));
+    } else {
+        output.log(&format!("Serial device: {}", name));
+    }
+    let mut recording = match &output.args.record {
+        Some(path) => Some(Recording {
+            file: File::create(path)
+                .map_err(|err| format!("Failed to create {}: {}", path.display(), err))?,
+            last: 0,
+        }),
+        None => None,
+    };
+
+    let result = serve_pty(module, output, &mut master, &mut recording);
+    if let Some(link) = &link {
+        let _ = std::fs::remove_file(link);
+    }
+    result
+}
+
+fn serve_pty(
+    module: &mut dyn Module,
+    output: &mut Output,
+    master: &mut nix::pty::PtyMaster,
+    recording: &mut Option<Recording>,
+) -> Result<(), String> {
+    // Decodes the commands again, only to show them
+    let mut sniffer = FrameDecoder::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let mut fds = [PollFd::new(master.as_raw_fd(), PollFlags::POLLIN)];
+        poll(&mut fds, POLL_TIMEOUT).map_err(|err| format!("Failed to poll: {}", err))?;
+        let now = output.start.elapsed().as_micros() as u64;
+        run_until(module, output, now)?;
+
+        let readable = fds[0]
+            .revents()
+            .is_some_and(|events| events.contains(PollFlags::POLLIN));
+        if !readable {
+            continue;
+        }
+        let count = master
+            .read(&mut buf)
+            .map_err(|err| format!("Failed to read: {}", err))?;
+        let data = &buf[..count];
+
+        let mut commands = vec![];
+        sniffer.push(data);
+        while let Some(received) = sniffer.next_received() {
+            let description = match received.into_command().1 {
+                Ok(command) => describe(&command),
+                Err(code) => format!("Invalid: {}", code),
+            };
+            output.log(&format!("Received: {}", description));
+            commands.push(description);
+        }
+        if let Some(recording) = recording {
+            recording
+                .record(now, data, &commands)
+                .map_err(|err| format!("Failed to record: {}", err))?;
+        }
+
+        let Ok(reply) = module.write(data) else {
+            output.log("Module reset");
+            return Ok(());
+        };
+        master
+            .write_all(&reply)
+            .map_err(|err| format!("Failed to write: {}", err))?;
+        output.show(module)?;
+    }
+}
@@ -0,0 +1,50 @@
+//! Show the LEDs in the terminal or as images
+use std::fmt::Write;
+use std::path::Path;
+
+use fl16_inputmodules::matrix::{Grid, HEIGHT, WIDTH};
+use image::{GrayImage, Luma};
+
+/// Draw the LEDs with ANSI colors, every LED is two characters wide to make it square
+pub fn terminal(pixels: &Grid) -> String {
+    let mut text = String::new();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let val = pixels.0[x][y];
+            write!(text, "\x1b[38;2;{val};{val};{val}m██").unwrap();
+        }
+        text.push_str("\x1b[0m\n");
+    }
+    text
+}
+
+/// Draw the LEDs as text, for when the output isn't a terminal
+///
+/// Off LEDs are shown as `.`, the others with `1` to `9` depending on the brightness.
+pub fn text(pixels: &Grid) -> String {
+    let mut text = String::new();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            text.push(match pixels.0[x][y] {
+                0 => '.',
+                val => char::from_digit(1 + (val as u32 * 9 - 1) / 255, 10).unwrap(),
+            });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Save the LEDs as greyscale PNG, every LED is `scale` pixels wide and high
+pub fn png(pixels: &Grid, scale: u32, path: &Path) -> image::ImageResult<()> {
+    let mut img = GrayImage::new(WIDTH as u32 * scale, HEIGHT as u32 * scale);
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let (led_x, led_y) = (x / scale, y / scale);
+        // Leave a gap between the LEDs, if they're large enough
+        let gap = scale > 2 && (x % scale == scale - 1 || y % scale == scale - 1);
+        if !gap {
+            *pixel = Luma([pixels.0[led_x as usize][led_y as usize]]);
+        }
+    }
+    img.save(path)
+}
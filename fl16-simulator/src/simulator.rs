@@ -0,0 +1,229 @@
+//! Main loop of the LED matrix firmware, running in simulated time
+use fl16_inputmodules::control::*;
+use fl16_inputmodules::games;
+use fl16_inputmodules::matrix::*;
+use fl16_inputmodules::patterns::*;
+use fl16_inputmodules::settings::*;
+use heapless::Vec;
+
+use crate::module::{resets, Module, Reset};
+
+/// LEDs that remember what they show, instead of an LED controller
+pub struct SimulatedLeds {
+    /// Brightness of every LED, after orientation, gamma curve and brightness
+    pub pixels: Grid,
+    pub pwm_freq: PwmFreqArg,
+    /// Whether the LEDs were updated since the last call of `take_updated`
+    updated: bool,
+}
+
+impl SimulatedLeds {
+    /// Check whether the LEDs were updated since the last call
+    pub fn take_updated(&mut self) -> bool {
+        core::mem::take(&mut self.updated)
+    }
+}
+
+impl LedDriver for SimulatedLeds {
+    fn fill_pixels(&mut self, brightnesses: &Grid) {
+        self.pixels = brightnesses.clone();
+        self.updated = true;
+    }
+
+    fn set_pwm_freq(&mut self, freq: PwmFreqArg) {
+        self.pwm_freq = freq;
+    }
+}
+
+/// Persistent storage in RAM, settings are lost when the simulator exits
+pub struct RamFlash(Box<[u8; 2 * SECTOR_SIZE]>);
+
+impl Default for RamFlash {
+    fn default() -> Self {
+        // Erased flash
+        Self(Box::new([0xFF; 2 * SECTOR_SIZE]))
+    }
+}
+
+impl Flash for RamFlash {
+    fn read(&self, offset: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0[offset..offset + buf.len()]);
+    }
+
+    fn erase_sector(&mut self, offset: usize) {
+        self.0[offset..offset + SECTOR_SIZE].fill(0xFF);
+    }
+
+    fn program_page(&mut self, offset: usize, data: &[u8; PAGE_SIZE]) {
+        for (byte, new) in self.0[offset..offset + PAGE_SIZE].iter_mut().zip(data) {
+            *byte &= new;
+        }
+    }
+}
+
+/// Simulated LED matrix module
+///
+/// Does the same as the main loop of the firmware, except for the parts that
+/// depend on the hardware: There's no USB suspend, no SLEEP# pin and no
+/// DIP switch. Sleeping happens instantly and there's no sleep timeout.
+pub struct Simulator {
+    pub state: LedmatrixState,
+    pub leds: SimulatedLeds,
+    settings: SettingsStore<RamFlash>,
+    decoder: FrameDecoder,
+    /// Simulated time since startup in microseconds
+    pub time: u64,
+    animation_timer: u64,
+    game_timer: u64,
+    /// State of the random number generator, replaces the ring oscillator
+    random: u32,
+}
+
+impl Simulator {
+    pub fn new(seed: u32) -> Self {
+        let mut state = LedmatrixState {
+            // addon stuff
+            visual_keypresses: Vec::new(),
+            visual_keypress_life: 10,
+            timer: 0,
+            addon_animation: None,
+            side: DEFAULT_SIDE,
+
+            grid: percentage(0),
+            col_buffer: Grid::default(),
+            animate: false,
+            brightness: DEFAULT_BRIGHTNESS,
+            gamma: DEFAULT_GAMMA,
+            gamma_lut: LINEAR_GAMMA_LUT,
+            custom_gamma_lut: LINEAR_GAMMA_LUT,
+            orientation: DEFAULT_ORIENTATION,
+            sleeping: SleepState::Awake,
+            game: None,
+            animation_period: DEFAULT_ANIMATION_PERIOD,
+            pwm_freq: DEFAULT_PWM_FREQ,
+            debug_mode: false,
+            upcoming_frames: None,
+            custom_frames: Vec::new(),
+        };
+        let settings = SettingsStore::new(RamFlash::default());
+        state.load_settings(&settings);
+
+        let mut leds = SimulatedLeds {
+            pixels: Grid::default(),
+            pwm_freq: state.pwm_freq,
+            updated: false,
+        };
+        fill_grid_pixels(&state, &mut leds);
+
+        Self {
+            state,
+            leds,
+            settings,
+            decoder: FrameDecoder::new(),
+            time: 0,
+            animation_timer: 0,
+            game_timer: 0,
+            // Xorshift doesn't work with 0
+            random: seed.max(1),
+        }
+    }
+
+    fn random_byte(&mut self) -> u8 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random as u8
+    }
+
+    /// Go to sleep or wake up, instantly
+    fn handle_sleep(&mut self, go_sleeping: bool) {
+        let state = &mut self.state;
+        match (&state.sleeping, go_sleeping) {
+            (SleepState::Awake, true) => {
+                state.sleeping = SleepState::Sleeping((state.grid.clone(), state.brightness));
+                // LED controller is turned off
+                self.leds.fill_pixels(&Grid::default());
+            }
+            (SleepState::Sleeping((grid, _)), false) => {
+                state.grid = grid.clone();
+                state.sleeping = SleepState::Awake;
+                fill_grid_pixels(state, &mut self.leds);
+            }
+            _ => {}
+        }
+    }
+
+    /// Periodic display update
+    fn render(&mut self) {
+        self.animation_timer = self.time;
+        if matches!(self.state.sleeping, SleepState::Awake) {
+            self.state.render(&mut self.leds);
+        }
+    }
+}
+
+impl Module for Simulator {
+    fn write(&mut self, data: &[u8]) -> Result<std::vec::Vec<u8>, Reset> {
+        let mut written = std::vec::Vec::new();
+        self.decoder.push(data);
+        while let Some(received) = self.decoder.next_received() {
+            let (reply, command) = received.into_command();
+            let mut reply_buf = [0u8; MAX_FRAME_SIZE];
+            let len = match command {
+                Ok(command) => {
+                    if resets(&command) {
+                        return Err(Reset);
+                    }
+                    let go_sleeping = self.state.receive_command(&command);
+                    self.handle_sleep(go_sleeping);
+
+                    let random = self.random_byte();
+                    let mut handler = LedmatrixHandler {
+                        state: &mut self.state,
+                        matrix: &mut self.leds,
+                        settings: &mut self.settings,
+                        random,
+                    };
+                    let result = handler.handle_command(&command);
+                    if matches!(self.state.sleeping, SleepState::Awake) {
+                        fill_grid_pixels(&self.state, &mut self.leds);
+                    }
+                    reply.respond(&result, &mut reply_buf)
+                }
+                Err(code) => reply.nak(code, &mut reply_buf),
+            };
+            written.extend_from_slice(&reply_buf[..len]);
+        }
+        Ok(written)
+    }
+
+    /// Run the main loop up to the next display update or game step
+    fn step(&mut self, until: u64) -> bool {
+        let next_render = self.animation_timer + self.state.animation_period;
+        let next_game_step = self.game_timer + self.state.game_period();
+        let next = next_render.min(next_game_step);
+        if next > until {
+            self.time = until;
+            return false;
+        }
+        self.time = next;
+
+        if next == next_render {
+            self.render();
+        }
+        if next == next_game_step {
+            let random = self.random_byte();
+            games::game_step(&mut self.state, random);
+            self.game_timer = self.time;
+        }
+        true
+    }
+
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    fn leds(&mut self) -> Option<&mut SimulatedLeds> {
+        Some(&mut self.leds)
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn simulate(script: &str, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fl16-simulator"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Rows of the last frame that was printed
+fn last_frame(output: &Output) -> Vec<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let frame = stdout.rsplit("Time: ").next().unwrap();
+    frame.lines().skip(1).map(str::to_string).collect()
+}
+
+#[test]
+fn draws_and_replies() {
+    // Full brightness, then draw only the top left pixel
+    let mut draw = String::from("32ac 00 ff\n32ac 06 01");
+    draw.push_str(&" 00".repeat(38));
+    let output = simulate(&format!("{draw}\n32ac 20\n"), &[]);
+    assert!(output.status.success());
+
+    let frame = last_frame(&output);
+    assert_eq!(frame.len(), 34);
+    // Columns are mirrored when drawing, like on the module
+    assert_eq!(frame[0], "........9");
+    assert!(frame[1..].iter().all(|row| row == "........."));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Reply: 00 20 00"));
+}
+
+#[test]
+fn games_run_in_simulated_time() {
+    // Tetris, the first piece drops into view after a few steps
+    let output = simulate("32ac 00 ff\n32ac 10 02\nwait 3000\n", &["--seed", "7"]);
+    assert!(output.status.success());
+    assert!(last_frame(&output).iter().any(|row| row.contains('9')));
+}
+
+#[test]
+fn invalid_script() {
+    let output = simulate("32ac 00 ff\n32ac 0\n", &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.trim(), "Line 2: Odd number of hex digits");
+}
+
+#[test]
+fn emulated_module() {
+    // Get the color, then try to change it while sleeping and after waking up
+    let script = "32ac 13\n32ac 03 01\n32ac 13 ff 00 00\n32ac 03 00\n32ac 13 00 00 ff\n32ac 13\n";
+    let output = simulate(script, &["--module", "c1-minimal"]);
+    assert!(output.status.success());
+    // Only the LED matrix is drawn
+    assert!(output.stdout.is_empty());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let replies: Vec<&str> = stderr.lines().collect();
+    assert_eq!(replies.len(), 2);
+    assert!(replies[0].starts_with("Reply: 00 80 00 00"));
+    assert!(replies[1].starts_with("Reply: 00 00 ff 00"));
+}
+
+#[cfg(unix)]
+#[test]
+fn serial_device() {
+    use std::io::Read;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::time::{Duration, Instant};
+
+    let dir = std::env::temp_dir().join(format!("fl16-simulator-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let link = dir.join("tty");
+    let record = dir.join("record.txt");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_fl16-simulator"))
+        .args(["--module", "c1-minimal", "--pty", "--link"])
+        .arg(&link)
+        .arg("--record")
+        .arg(&record)
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let start = Instant::now();
+    while !link.exists() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "No serial device"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut port = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(nix::libc::O_NOCTTY)
+        .open(&link)
+        .unwrap();
+
+    port.write_all(&[0x32, 0xac, 0x13]).unwrap();
+    let mut response = [0; 32];
+    port.read_exact(&mut response).unwrap();
+    assert_eq!(response[..3], [0, 128, 0]);
+
+    // Panicking resets the module, which ends the simulator
+    port.write_all(&[0x32, 0xac, 0x05]).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(!link.exists());
+
+    let record = std::fs::read_to_string(&record).unwrap();
+    assert!(record.contains("# GetColor\n32 ac 13\n"));
+    assert!(record.contains("# Panic\n32 ac 05\n"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
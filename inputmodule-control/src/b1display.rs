@@ -7,7 +7,8 @@ pub enum B1Pattern {
     //Checkerboard,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Fps {
     Quarter,
     Half,
@@ -19,7 +20,8 @@ pub enum Fps {
     ThirtyTwo,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum PowerMode {
     Low,
     High,
@@ -0,0 +1,197 @@
+//! Config file with profiles of settings for the modules
+//!
+//! Which settings a profile has is up to the application, `inputmodule-control`
+//! names them like the arguments of its commands:
+//!
+//! ```toml
+//! default-profile = "day"
+//!
+//! [profiles.day]
+//! brightness = 80
+//! content = { pattern = "lotus-sideways" }
+//!
+//! [[devices]]
+//! serial-number = "FRAKDEAM0020110001"
+//! side = "left"
+//! profile = "night"
+//! ```
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, io};
+
+use inputmodule_protocol::Side;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// Error when loading the config file
+#[derive(Debug)]
+pub enum ConfigError {
+    /// No path given and there's no config directory
+    NoPath,
+    /// Couldn't read the file
+    Read(PathBuf, io::Error),
+    /// File isn't valid TOML or has unknown settings
+    Parse(toml::de::Error),
+    /// Profile is used, but not in the file
+    UnknownProfile(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoPath => write!(
+                f,
+                "Failed to find config file. Please manually specify with --config"
+            ),
+            ConfigError::Read(path, err) => {
+                write!(f, "Failed to read config file {}: {}", path.display(), err)
+            }
+            ConfigError::Parse(err) => write!(f, "Invalid config file: {}", err),
+            ConfigError::UnknownProfile(name) => {
+                write!(f, "No profile named {} in the config file", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read(_, err) => Some(err),
+            ConfigError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Profiles of type `P`, and which modules get them
+#[derive(Debug, Deserialize)]
+#[serde(
+    rename_all = "kebab-case",
+    deny_unknown_fields,
+    bound(deserialize = "P: Deserialize<'de>")
+)]
+pub struct Config<P> {
+    /// Profile for modules that don't have one configured
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, P>,
+    #[serde(default)]
+    pub devices: Vec<Device>,
+}
+
+/// What the module shows, like `content = { pattern = "zigzag" }`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Content {
+    /// Name of a pattern, as for `--pattern` of the module's command
+    Pattern(String),
+    /// LED Matrix only
+    Percentage(u8),
+    /// Grayscale 9x34 image for the LED Matrix, 300x400 for the B1 Display
+    Image(String),
+    /// GIF that the LED Matrix keeps playing by itself
+    Gif(String),
+    /// Text that the LED Matrix keeps scrolling
+    Text(String),
+}
+
+/// Which profile a module gets
+///
+/// Found by its serial number, otherwise by the side it's set to. With both,
+/// the module with the serial number is set to the side.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Device {
+    pub serial_number: Option<String>,
+    pub side: Option<Side>,
+    pub profile: Option<String>,
+    /// Instead of the content of the profile
+    pub content: Option<Content>,
+}
+
+impl Device {
+    /// Side to set the module to
+    pub fn set_side(&self) -> Option<Side> {
+        self.serial_number.as_ref().and(self.side)
+    }
+}
+
+impl<P> Config<P> {
+    /// Entry of the module with this serial number, otherwise of its side
+    ///
+    /// The side is only asked for if there are entries that need it.
+    pub fn device(
+        &self,
+        serial_number: Option<&str>,
+        side: impl FnOnce() -> Option<Side>,
+    ) -> Option<&Device> {
+        let by_serial_number = self.devices.iter().find(|device| {
+            serial_number.is_some() && device.serial_number.as_deref() == serial_number
+        });
+        if by_serial_number.is_some() {
+            return by_serial_number;
+        }
+
+        let mut by_side = self
+            .devices
+            .iter()
+            .filter(|device| device.serial_number.is_none() && device.side.is_some())
+            .peekable();
+        by_side.peek()?;
+        let side = side()?;
+        by_side.find(|device| device.side == Some(side))
+    }
+
+    /// Profile by its name
+    pub fn profile(&self, name: &str) -> Result<&P, ConfigError> {
+        self.profiles
+            .get(name)
+            .ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))
+    }
+}
+
+impl<P: DeserializeOwned> Config<P> {
+    /// Read the config file, by default the one at [`default_path`]
+    ///
+    /// `profile` is the one requested by the user, it has to be in the file.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(default_path)
+            .ok_or(ConfigError::NoPath)?;
+        let text = std::fs::read_to_string(&path).map_err(|err| ConfigError::Read(path, err))?;
+        Self::parse(&text, profile)
+    }
+
+    /// Parse the contents of a config file
+    ///
+    /// Fails if a profile is used, but not defined. `profile` is the one
+    /// requested by the user.
+    pub fn parse(text: &str, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text).map_err(ConfigError::Parse)?;
+        // Catch typos before touching any module
+        let names = config
+            .devices
+            .iter()
+            .filter_map(|device| device.profile.as_deref())
+            .chain(config.default_profile.as_deref())
+            .chain(profile);
+        for name in names {
+            config.profile(name)?;
+        }
+        Ok(config)
+    }
+}
+
+/// Where the config file is, unless specified otherwise
+///
+/// `config.toml` in the `inputmodule-control` directory of the user's config
+/// directory, like `~/.config/inputmodule-control/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("inputmodule-control")
+            .join("config.toml"),
+    )
+}
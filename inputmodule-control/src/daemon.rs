@@ -0,0 +1,342 @@
+//! Daemon that keeps the modules open, so that several clients can use them
+//!
+//! Clients connect to a Unix socket, see [`socket_path`], and send one request
+//! per line as JSON. Each request gets a reply line:
+//!
+//! ```text
+//! > {"request":"modules"}
+//! < {"ok":true,"modules":[{"port":"/dev/ttyACM0","pid":32,"serial_number":"FRAKDEAM0020110001","usb_path":"3-4.3"}]}
+//! > {"request":"command","port":"/dev/ttyACM0","command":[0,50]}
+//! < {"ok":true}
+//! > {"request":"command","port":"/dev/ttyACM0","command":[0]}
+//! < {"ok":true,"response":[50,0,0,...]}
+//! > {"request":"command","port":"/dev/ttyACM0","command":[23]}
+//! < {"ok":false,"error":"Command not supported by this module","code":4}
+//! ```
+//!
+//! A command is the command ID and its parameters, as in [`commands.md`], without
+//! the magic bytes. The module handles the commands of one client at a time.
+//!
+//! [`commands.md`]: https://github.com/FrameworkComputer/inputmodule-rs/blob/main/commands.md
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use inputmodule_protocol::{Capabilities, Command, ErrorCode, Response, ResponseBuf};
+use num_traits::FromPrimitive;
+use serde::{Deserialize, Serialize};
+use serialport::SerialPort;
+
+use crate::error::{Error, Result};
+use crate::select::{find_modules, ModuleInfo};
+use crate::transport::ModulePort;
+
+/// How long a client waits for a reply. Longer than modules take, even when
+/// other clients are using it.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the daemon listens, unless `INPUTMODULE_CONTROL_SOCKET` is set
+///
+/// In the runtime directory of the user, like `/run/user/1000`, or else the
+/// temporary directory.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("INPUTMODULE_CONTROL_SOCKET") {
+        return path.into();
+    }
+    dirs::runtime_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("inputmodule-control.sock")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum Request {
+    /// Modules that are connected
+    Modules,
+    /// Open the port of a module, unless it's open already
+    Open {
+        port: String,
+    },
+    Capabilities {
+        port: String,
+    },
+    /// Command ID and parameters
+    Command {
+        port: String,
+        command: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Reply {
+    ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// [`ErrorCode`] that the module rejected the command with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    code: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modules: Option<Vec<ModuleInfo>>,
+}
+
+impl Reply {
+    fn rejected(code: ErrorCode) -> Self {
+        Self {
+            error: Some(code.to_string()),
+            code: Some(code as u8),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Result<Option<ResponseBuf>>> for Reply {
+    fn from(result: Result<Option<ResponseBuf>>) -> Self {
+        match result {
+            Ok(response) => Self {
+                ok: true,
+                response: response.map(Vec::from),
+                ..Default::default()
+            },
+            Err(Error::Command { code, .. }) => Self::rejected(code),
+            Err(err) => Self {
+                error: Some(err.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+type Opener = dyn Fn(&str) -> Result<Box<dyn SerialPort>> + Send + Sync;
+
+/// Holds the modules open and lets clients send commands to them
+pub struct Daemon {
+    open: Box<Opener>,
+    ports: Mutex<HashMap<String, Arc<Mutex<ModulePort>>>>,
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Daemon {
+    /// Daemon for the modules on the serial ports
+    pub fn new() -> Self {
+        Self::with_opener(crate::device::open_serial_port)
+    }
+
+    /// Daemon that gets the ports of the modules from `open`, like fake ones for testing
+    pub fn with_opener(
+        open: impl Fn(&str) -> Result<Box<dyn SerialPort>> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            open: Box::new(open),
+            ports: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Listen on the socket
+    ///
+    /// Replaces the socket if it's left over from a daemon that stopped.
+    /// Fails if a daemon is still listening on it.
+    pub fn listen(socket: &Path) -> io::Result<UnixListener> {
+        if UnixStream::connect(socket).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "Daemon is already running",
+            ));
+        }
+        match std::fs::symlink_metadata(socket) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(socket)?,
+            _ => {}
+        }
+        UnixListener::bind(socket)
+    }
+
+    /// Answer clients until accepting them fails
+    pub fn serve(self, listener: UnixListener) -> io::Result<()> {
+        let daemon = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let daemon = daemon.clone();
+            thread::spawn(move || daemon.client(stream));
+        }
+        Ok(())
+    }
+
+    /// Open the port of a module now, instead of when a client first uses it
+    pub fn open(&self, port: &str) -> Result<()> {
+        self.port(port).map(|_| ())
+    }
+
+    fn port(&self, name: &str) -> Result<Arc<Mutex<ModulePort>>> {
+        let mut ports = self.ports.lock().unwrap();
+        if let Some(port) = ports.get(name) {
+            return Ok(port.clone());
+        }
+        let port = Arc::new(Mutex::new(ModulePort::new((self.open)(name)?)));
+        ports.insert(name.to_string(), port.clone());
+        Ok(port)
+    }
+
+    /// Forget the port, so that it's opened again when it's used next
+    fn close(&self, name: &str) {
+        self.ports.lock().unwrap().remove(name);
+    }
+
+    /// Use the port of a module, while no other client does
+    fn with_port<T>(&self, name: &str, f: impl FnOnce(&mut ModulePort) -> Result<T>) -> Result<T> {
+        let port = self.port(name)?;
+        let result = f(&mut port.lock().unwrap());
+        // Likely unplugged, the port won't work anymore
+        if matches!(result, Err(Error::Io(_))) {
+            self.close(name);
+        }
+        result
+    }
+
+    /// Answer the requests of a client until it disconnects
+    fn client(&self, stream: UnixStream) {
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            let reply = match serde_json::from_str(&line) {
+                Ok(request) => self.handle(request),
+                Err(err) => Reply {
+                    error: Some(format!("Invalid request: {err}")),
+                    ..Default::default()
+                },
+            };
+            let mut reply = serde_json::to_string(&reply).unwrap();
+            reply.push('\n');
+            if writer.write_all(reply.as_bytes()).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn handle(&self, request: Request) -> Reply {
+        match request {
+            Request::Modules => match find_modules() {
+                Ok(modules) => Reply {
+                    ok: true,
+                    modules: Some(modules),
+                    ..Default::default()
+                },
+                Err(err) => Reply::from(Err(err)),
+            },
+            Request::Open { port } => Reply::from(self.port(&port).map(|_| None)),
+            Request::Capabilities { port } => Reply::from(self.with_port(&port, |port| {
+                let capabilities = port.capabilities()?;
+                Ok(capabilities.map(|capabilities| Response::Capabilities(capabilities).encode()))
+            })),
+            Request::Command { port, command } => {
+                let command = match Command::decode_body(&command) {
+                    Ok(command) => command,
+                    Err(code) => return Reply::rejected(code),
+                };
+                let reply = Reply::from(self.with_port(&port, |module| module.command(&command)));
+                // The module resets, its port goes away
+                if matches!(command, Command::BootloaderReset) {
+                    self.close(&port);
+                }
+                reply
+            }
+        }
+    }
+}
+
+/// Connection to the daemon, to use one of its modules
+pub(crate) struct DaemonPort {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    port: String,
+}
+
+impl DaemonPort {
+    /// Connect to the daemon and have it open the module's port
+    ///
+    /// Fails with [`Error::Io`] if the daemon isn't running.
+    pub fn connect(socket: &Path, port: &str) -> Result<Self> {
+        let stream = UnixStream::connect(socket)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        let mut daemon = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            port: port.to_string(),
+        };
+        let reply = daemon.request(&Request::Open {
+            port: port.to_string(),
+        })?;
+        match reply.error {
+            Some(error) if !reply.ok => Err(Error::Daemon(error)),
+            _ => Ok(daemon),
+        }
+    }
+
+    pub fn capabilities(&mut self) -> Result<Option<Capabilities>> {
+        let request = Request::Capabilities {
+            port: self.port.clone(),
+        };
+        let response = self.response(&Command::GetCapabilities, &request)?;
+        Ok(response
+            .and_then(|response| Command::GetCapabilities.decode_response(&response))
+            .and_then(|response| match response {
+                Response::Capabilities(capabilities) => Some(capabilities),
+                _ => None,
+            }))
+    }
+
+    pub fn command(&mut self, command: &Command) -> Result<Option<ResponseBuf>> {
+        let mut body = [0; inputmodule_protocol::MAX_PAYLOAD_SIZE];
+        let len = command.encode_body(&mut body);
+        let request = Request::Command {
+            port: self.port.clone(),
+            command: body[..len].to_vec(),
+        };
+        self.response(command, &request)
+    }
+
+    fn response(&mut self, command: &Command, request: &Request) -> Result<Option<ResponseBuf>> {
+        let reply = self.request(request)?;
+        if !reply.ok {
+            return Err(match reply.code.and_then(ErrorCode::from_u8) {
+                Some(code) => Error::Command {
+                    command: command.id(),
+                    code,
+                },
+                None => Error::Daemon(reply.error.unwrap_or_default()),
+            });
+        }
+        match reply.response {
+            Some(response) => ResponseBuf::try_from(response.as_slice())
+                .map(Some)
+                .map_err(|_| Error::InvalidResponse(command.id())),
+            None => Ok(None),
+        }
+    }
+
+    fn request(&mut self, request: &Request) -> Result<Reply> {
+        let mut line = serde_json::to_string(request).unwrap();
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        serde_json::from_str(&line).map_err(|err| Error::Io(err.into()))
+    }
+}
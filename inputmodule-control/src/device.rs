@@ -0,0 +1,487 @@
+use std::time::Duration;
+
+use inputmodule_protocol::{
+    AddonAnimationVals, Capabilities, Command, CommandVals, DisplayMode, Game, GameControlArg,
+    GameStatus, GammaCurve, GreyFrame, GreyPixels, Orientation, PatternVals, PwmFreqArg, Response,
+    ResponseBuf, ScrollDirection, Side, Version, DRAW_BYTES, GAMMA_LUT_SIZE, HEIGHT,
+    PIXEL_COLUMN_BYTES,
+};
+use serialport::SerialPort;
+
+#[cfg(unix)]
+use crate::daemon::DaemonPort;
+use crate::error::{Error, Result};
+use crate::transport::ModulePort;
+
+/// How long to wait for data from the module, before giving up on a read
+const SERIAL_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// Client of a single input module
+///
+/// Every method sends one or more commands and waits until the module handled them.
+/// If the firmware reports which commands it supports, others fail with
+/// [`ErrorCode::Unsupported`](inputmodule_protocol::ErrorCode::Unsupported)
+/// without being sent.
+pub struct InputModule {
+    port: Port,
+}
+
+/// How the commands get to the module
+enum Port {
+    Serial(ModulePort),
+    #[cfg(unix)]
+    Daemon(DaemonPort),
+}
+
+impl Port {
+    fn capabilities(&mut self) -> Result<Option<Capabilities>> {
+        match self {
+            Port::Serial(port) => port.capabilities(),
+            #[cfg(unix)]
+            Port::Daemon(port) => port.capabilities(),
+        }
+    }
+
+    fn command(&mut self, command: &Command) -> Result<Option<ResponseBuf>> {
+        match self {
+            Port::Serial(port) => port.command(command),
+            #[cfg(unix)]
+            Port::Daemon(port) => port.command(command),
+        }
+    }
+}
+
+/// Open the serial port of a module, with the settings it needs
+pub(crate) fn open_serial_port(path: &str) -> Result<Box<dyn SerialPort>> {
+    serialport::new(path, 115_200)
+        .timeout(SERIAL_TIMEOUT)
+        .open()
+        .map_err(Error::Open)
+}
+
+impl InputModule {
+    /// Open the serial port of a module, like `/dev/ttyACM0` or `COM3`
+    ///
+    /// If the [`Daemon`](crate::Daemon) is running, it goes through the daemon instead.
+    pub fn open(path: &str) -> Result<Self> {
+        #[cfg(unix)]
+        match Self::connect(&crate::socket_path(), path) {
+            // Not running
+            Err(Error::Io(_)) => {}
+            result => return result,
+        }
+        Self::open_serial(path)
+    }
+
+    /// Open the serial port of a module, even if the daemon is running
+    pub fn open_serial(path: &str) -> Result<Self> {
+        Ok(Self::new(open_serial_port(path)?))
+    }
+
+    /// Use a module through the daemon that listens on the socket
+    #[cfg(unix)]
+    pub fn connect(socket: &std::path::Path, path: &str) -> Result<Self> {
+        Ok(Self {
+            port: Port::Daemon(DaemonPort::connect(socket, path)?),
+        })
+    }
+
+    /// Talk to a module over a serial port that's already open
+    ///
+    /// The port needs a short read timeout, because that's how firmware
+    /// without framing support is detected.
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        Self {
+            port: Port::Serial(ModulePort::new(port)),
+        }
+    }
+
+    /// Features of the module
+    ///
+    /// Returns `None` if the firmware is too old to report them.
+    pub fn capabilities(&mut self) -> Result<Option<Capabilities>> {
+        self.port.capabilities()
+    }
+
+    /// Whether the module reports that it supports the command
+    ///
+    /// Always `false` if the firmware is too old to report its capabilities.
+    pub fn supports(&mut self, command: CommandVals) -> Result<bool> {
+        Ok(self
+            .capabilities()?
+            .is_some_and(|capabilities| capabilities.supports_command(command)))
+    }
+
+    /// Send a command, ignoring its response
+    pub fn send(&mut self, command: &Command) -> Result<()> {
+        self.port.command(command)?;
+        Ok(())
+    }
+
+    /// Send a command and return the response of the module
+    pub fn query(&mut self, command: &Command) -> Result<Response> {
+        self.port
+            .command(command)?
+            .and_then(|response| command.decode_response(&response))
+            .ok_or(Error::InvalidResponse(command.id()))
+    }
+
+    // Commands of all modules
+
+    pub fn version(&mut self) -> Result<Version> {
+        let Response::Version(version) = self.query(&Command::Version)? else {
+            return Err(Error::InvalidResponse(CommandVals::Version));
+        };
+        Ok(version)
+    }
+
+    /// Reset into the bootloader, to update the firmware
+    ///
+    /// The serial port disappears afterwards.
+    pub fn bootloader(&mut self) -> Result<()> {
+        self.send(&Command::BootloaderReset)
+    }
+
+    /// Make the firmware panic, to test what happens
+    pub fn panic(&mut self) -> Result<()> {
+        self.send(&Command::Panic)
+    }
+
+    pub fn sleeping(&mut self) -> Result<bool> {
+        let Response::Sleeping(sleeping) = self.query(&Command::IsSleeping)? else {
+            return Err(Error::InvalidResponse(CommandVals::Sleep));
+        };
+        Ok(sleeping)
+    }
+
+    pub fn set_sleeping(&mut self, sleeping: bool) -> Result<()> {
+        self.send(&Command::Sleep(sleeping))
+    }
+
+    // LED Matrix and C1 Minimal
+
+    pub fn brightness(&mut self) -> Result<u8> {
+        let Response::Brightness(brightness) = self.query(&Command::GetBrightness)? else {
+            return Err(Error::InvalidResponse(CommandVals::Brightness));
+        };
+        Ok(brightness)
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) -> Result<()> {
+        self.send(&Command::SetBrightness(brightness))
+    }
+
+    // LED Matrix
+
+    pub fn set_pattern(&mut self, pattern: PatternVals) -> Result<()> {
+        self.send(&Command::Pattern(pattern))
+    }
+
+    /// Light up a percentage of the screen
+    pub fn set_percentage(&mut self, percentage: u8) -> Result<()> {
+        self.send(&Command::Percentage(percentage))
+    }
+
+    /// Whether the picture scrolls vertically
+    pub fn animate(&mut self) -> Result<bool> {
+        let Response::Animate(animate) = self.query(&Command::GetAnimate)? else {
+            return Err(Error::InvalidResponse(CommandVals::Animate));
+        };
+        Ok(animate)
+    }
+
+    pub fn set_animate(&mut self, animate: bool) -> Result<()> {
+        self.send(&Command::SetAnimate(animate))
+    }
+
+    /// Draw black and white, one bit per LED, row by row
+    pub fn draw_bw(&mut self, vals: &[u8; DRAW_BYTES]) -> Result<()> {
+        self.send(&Command::Draw(*vals))
+    }
+
+    /// Stage the greyscale values of a single column, shown with [`Self::commit_grey_cols`]
+    pub fn stage_grey_col(&mut self, x: u8, vals: &[u8; HEIGHT]) -> Result<()> {
+        self.send(&Command::StageGreyCol(x, *vals))
+    }
+
+    /// Show the columns staged with [`Self::stage_grey_col`]
+    ///
+    /// This makes sure that the matrix isn't partially updated.
+    pub fn commit_grey_cols(&mut self) -> Result<()> {
+        self.send(&Command::DrawGreyColBuffer)
+    }
+
+    /// Draw a whole greyscale frame
+    ///
+    /// Sends it in a single command, if the firmware supports it, otherwise column by column.
+    /// `previous` is the currently displayed frame, if known, so that only changes are sent.
+    pub fn draw_grey(&mut self, pixels: &GreyPixels, previous: Option<&GreyPixels>) -> Result<()> {
+        if self.supports(CommandVals::DrawGreyFrame)? {
+            let frame = GreyFrame::encode(pixels, previous);
+            self.send(&Command::DrawGreyFrame(frame))
+        } else {
+            for (x, vals) in pixels.iter().enumerate() {
+                self.stage_grey_col(x as u8, vals)?;
+            }
+            self.commit_grey_cols()
+        }
+    }
+
+    /// Whether the module applies a brightness curve itself
+    ///
+    /// Then images can be sent as they are, without mapping them to LED brightness.
+    pub fn applies_gamma(&mut self) -> Result<bool> {
+        self.supports(CommandVals::Gamma)
+    }
+
+    /// Store the staged columns as frame of the custom animation
+    ///
+    /// Frame 0 starts a new animation. Delay in milliseconds.
+    pub fn store_animation_frame(&mut self, index: u8, delay: u16) -> Result<()> {
+        self.send(&Command::StoreAnimationFrame { index, delay })
+    }
+
+    pub fn playing_custom_animation(&mut self) -> Result<bool> {
+        let Response::PlayingCustomAnimation(playing) =
+            self.query(&Command::IsPlayingCustomAnimation)?
+        else {
+            return Err(Error::InvalidResponse(CommandVals::CustomAnimation));
+        };
+        Ok(playing)
+    }
+
+    pub fn play_custom_animation(&mut self, play: bool) -> Result<()> {
+        self.send(&Command::PlayCustomAnimation(play))
+    }
+
+    /// Let the module scroll text with its own font
+    ///
+    /// Speed in rows per second. Loops forever if `loops` is 0.
+    /// Empty text stops scrolling.
+    pub fn scroll_text(
+        &mut self,
+        text: &str,
+        speed: u8,
+        direction: ScrollDirection,
+        loops: u8,
+    ) -> Result<()> {
+        let text = heapless::String::try_from(text).map_err(|_| Error::TextTooLong)?;
+        self.send(&Command::ScrollText {
+            speed,
+            direction,
+            loops,
+            text,
+        })
+    }
+
+    pub fn scrolling_text(&mut self) -> Result<bool> {
+        let Response::ScrollingText(scrolling) = self.query(&Command::IsScrollingText)? else {
+            return Err(Error::InvalidResponse(CommandVals::ScrollText));
+        };
+        Ok(scrolling)
+    }
+
+    pub fn start_game(&mut self, game: Game) -> Result<()> {
+        self.send(&Command::StartGame(game))
+    }
+
+    pub fn game_control(&mut self, control: GameControlArg) -> Result<()> {
+        self.send(&Command::GameControl(control))
+    }
+
+    pub fn game_status(&mut self) -> Result<GameStatus> {
+        let Response::GameStatus(status) = self.query(&Command::GameStatus)? else {
+            return Err(Error::InvalidResponse(CommandVals::GameStatus));
+        };
+        Ok(status)
+    }
+
+    /// Time between two frames of animations, in milliseconds
+    pub fn animation_period(&mut self) -> Result<u16> {
+        let Response::AnimationPeriod(period) = self.query(&Command::GetAnimationPeriod)? else {
+            return Err(Error::InvalidResponse(CommandVals::AnimationPeriod));
+        };
+        Ok(period)
+    }
+
+    pub fn set_animation_period(&mut self, period: u16) -> Result<()> {
+        self.send(&Command::SetAnimationPeriod(period))
+    }
+
+    pub fn pwm_freq(&mut self) -> Result<PwmFreqArg> {
+        let Response::PwmFreq(freq) = self.query(&Command::GetPwmFreq)? else {
+            return Err(Error::InvalidResponse(CommandVals::PwmFreq));
+        };
+        Ok(freq)
+    }
+
+    pub fn set_pwm_freq(&mut self, freq: PwmFreqArg) -> Result<()> {
+        self.send(&Command::SetPwmFreq(freq))
+    }
+
+    pub fn debug_mode(&mut self) -> Result<bool> {
+        let Response::DebugMode(debug_mode) = self.query(&Command::GetDebugMode)? else {
+            return Err(Error::InvalidResponse(CommandVals::DebugMode));
+        };
+        Ok(debug_mode)
+    }
+
+    pub fn set_debug_mode(&mut self, debug_mode: bool) -> Result<()> {
+        self.send(&Command::SetDebugMode(debug_mode))
+    }
+
+    pub fn gamma(&mut self) -> Result<GammaCurve> {
+        let Response::Gamma(gamma) = self.query(&Command::GetGamma)? else {
+            return Err(Error::InvalidResponse(CommandVals::Gamma));
+        };
+        Ok(gamma)
+    }
+
+    pub fn set_gamma(&mut self, gamma: GammaCurve) -> Result<()> {
+        self.send(&Command::SetGamma(gamma))
+    }
+
+    /// Upload a custom gamma lookup table and use it
+    pub fn set_gamma_lut(&mut self, lut: &[u8; GAMMA_LUT_SIZE]) -> Result<()> {
+        self.send(&Command::SetGammaLut(*lut))
+    }
+
+    pub fn orientation(&mut self) -> Result<Orientation> {
+        let Response::Orientation(orientation) = self.query(&Command::GetOrientation)? else {
+            return Err(Error::InvalidResponse(CommandVals::Orientation));
+        };
+        Ok(orientation)
+    }
+
+    pub fn set_orientation(&mut self, orientation: Orientation) -> Result<()> {
+        self.send(&Command::SetOrientation(orientation))
+    }
+
+    /// Store the current settings in the flash
+    pub fn save_settings(&mut self) -> Result<()> {
+        self.send(&Command::SaveSettings)
+    }
+
+    /// Apply the settings stored in the flash
+    pub fn load_settings(&mut self) -> Result<()> {
+        self.send(&Command::LoadSettings)
+    }
+
+    /// Erase the stored settings and go back to the defaults
+    pub fn reset_settings(&mut self) -> Result<()> {
+        self.send(&Command::ResetSettings)
+    }
+
+    // addon stuff
+    pub fn keypress(&mut self, keycode: u32, pressed: bool) -> Result<()> {
+        self.send(&Command::Keypress { keycode, pressed })
+    }
+
+    pub fn set_addon_animation(&mut self, animation: AddonAnimationVals) -> Result<()> {
+        self.send(&Command::SetAddonAnimation(animation))
+    }
+
+    pub fn stop_addon_animation(&mut self) -> Result<()> {
+        self.send(&Command::StopAddonAnimation)
+    }
+
+    pub fn set_side(&mut self, side: Side) -> Result<()> {
+        self.send(&Command::SetSide(side))
+    }
+
+    /// Side of the keyboard that the module was configured for, with [`Self::set_side`]
+    pub fn side(&mut self) -> Result<Side> {
+        let Response::Side(side) = self.query(&Command::GetSide)? else {
+            return Err(Error::InvalidResponse(CommandVals::SetSide));
+        };
+        Ok(side)
+    }
+
+    // B1 Display
+
+    pub fn display_on(&mut self) -> Result<bool> {
+        let Response::DisplayOn(on) = self.query(&Command::GetDisplayOn)? else {
+            return Err(Error::InvalidResponse(CommandVals::DisplayOn));
+        };
+        Ok(on)
+    }
+
+    pub fn set_display_on(&mut self, on: bool) -> Result<()> {
+        self.send(&Command::DisplayOn(on))
+    }
+
+    pub fn screen_inverted(&mut self) -> Result<bool> {
+        let Response::InvertScreen(inverted) = self.query(&Command::GetInvertScreen)? else {
+            return Err(Error::InvalidResponse(CommandVals::InvertScreen));
+        };
+        Ok(inverted)
+    }
+
+    pub fn set_screen_inverted(&mut self, inverted: bool) -> Result<()> {
+        self.send(&Command::InvertScreen(inverted))
+    }
+
+    pub fn screensaver(&mut self) -> Result<bool> {
+        let Response::ScreenSaver(on) = self.query(&Command::GetScreenSaver)? else {
+            return Err(Error::InvalidResponse(CommandVals::ScreenSaver));
+        };
+        Ok(on)
+    }
+
+    pub fn set_screensaver(&mut self, on: bool) -> Result<()> {
+        self.send(&Command::ScreenSaver(on))
+    }
+
+    /// Frame rate of the display
+    ///
+    /// Bit 4 is the high power mode FPS, bits 0-2 are the low power mode FPS.
+    pub fn fps(&mut self) -> Result<u8> {
+        let Response::Fps(fps) = self.query(&Command::GetFps)? else {
+            return Err(Error::InvalidResponse(CommandVals::SetFps));
+        };
+        Ok(fps)
+    }
+
+    pub fn set_fps(&mut self, fps: u8) -> Result<()> {
+        self.send(&Command::SetFps(fps))
+    }
+
+    pub fn power_mode(&mut self) -> Result<DisplayMode> {
+        let Response::PowerMode(mode) = self.query(&Command::GetPowerMode)? else {
+            return Err(Error::InvalidResponse(CommandVals::SetPowerMode));
+        };
+        Ok(mode)
+    }
+
+    pub fn set_power_mode(&mut self, mode: DisplayMode) -> Result<()> {
+        self.send(&Command::SetPowerMode(mode))
+    }
+
+    /// Draw a column of the framebuffer, one bit per pixel, set bits are black
+    ///
+    /// Shown with [`Self::flush_framebuffer`].
+    pub fn set_pixel_column(&mut self, x: usize, pixels: &[u8; PIXEL_COLUMN_BYTES]) -> Result<()> {
+        self.send(&Command::SetPixelColumn(x, *pixels))
+    }
+
+    pub fn flush_framebuffer(&mut self) -> Result<()> {
+        self.send(&Command::FlushFramebuffer)
+    }
+
+    pub fn clear_ram(&mut self) -> Result<()> {
+        self.send(&Command::ClearRam)
+    }
+
+    // C1 Minimal
+
+    pub fn color(&mut self) -> Result<[u8; 3]> {
+        let Response::Color(color) = self.query(&Command::GetColor)? else {
+            return Err(Error::InvalidResponse(CommandVals::SetColor));
+        };
+        Ok(color)
+    }
+
+    pub fn set_color(&mut self, rgb: [u8; 3]) -> Result<()> {
+        self.send(&Command::SetColor(rgb))
+    }
+}
@@ -0,0 +1,55 @@
+use std::{fmt, io};
+
+use inputmodule_protocol::{CommandVals, ErrorCode, MAX_SCROLL_TEXT_LEN};
+
+/// Error when talking to a module
+#[derive(Debug)]
+pub enum Error {
+    /// Couldn't open the serial port
+    Open(serialport::Error),
+    /// Reading from or writing to the serial port failed, or the module didn't reply in time
+    Io(io::Error),
+    /// The module didn't handle the command
+    Command {
+        command: CommandVals,
+        code: ErrorCode,
+    },
+    /// The reply of the module doesn't belong to the command
+    InvalidResponse(CommandVals),
+    /// Text is longer than [`MAX_SCROLL_TEXT_LEN`]
+    TextTooLong,
+    /// The daemon couldn't handle the request, with its description of the error
+    Daemon(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Open(err) if err.kind == serialport::ErrorKind::Io(io::ErrorKind::PermissionDenied) => write!(f, "Permission denied, couldn't access inputmodule serialport. Ensure that you have permission, for example using a udev rule or sudo."),
+            Error::Open(err) => write!(f, "Couldn't open port: {}", err),
+            Error::Io(err) => write!(f, "Failed to communicate with module: {}", err),
+            Error::Command { command, code } => write!(f, "{:?} command failed: {}", command, code),
+            Error::InvalidResponse(command) => write!(f, "Invalid response to {:?} command", command),
+            Error::TextTooLong => write!(f, "Text must be at most {} bytes", MAX_SCROLL_TEXT_LEN),
+            Error::Daemon(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Open(err) => Some(err),
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
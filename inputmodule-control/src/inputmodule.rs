@@ -1,82 +1,62 @@
+use std::collections::HashMap;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use chrono::Local;
+use clap::ValueEnum;
 use image::codecs::gif::GifDecoder;
 use image::{io::Reader as ImageReader, Luma};
 use image::{AnimationDecoder, DynamicImage, ImageBuffer};
 use rand::prelude::*;
-use serialport::{SerialPort, SerialPortInfo, SerialPortType};
+use serialport::{SerialPortInfo, SerialPortType, UsbPortInfo};
+
+use inputmodule_control::config::{ConfigError, Content};
+use inputmodule_control::protocol::{
+    DisplayMode, GameControlArg, GameOfLifeParam, GameOfLifeStartParam, GameVal, GreyPixels,
+    PongParam, PwmFreqArg, ScrollDirection, SnakeParam, DRAW_BYTES, GAMMA_LUT_SIZE, HEIGHT,
+    MAX_ANIMATION_FRAMES, PIXEL_COLUMN_BYTES, WIDTH,
+};
+use inputmodule_control::{
+    find_modules, modules, Error, InputModule, ModuleInfo, ModuleKind, Selector, FRAMEWORK_VID,
+};
+#[cfg(unix)]
+use inputmodule_control::{socket_path, Daemon};
+use serde_json::{json, Value};
 
 use crate::b1display::{B1Pattern, Fps, PowerMode};
 use crate::c1minimal::Color;
 use crate::font::{convert_font, convert_symbol};
-use crate::ledmatrix::{AddonAnimation, Game, GameOfLifeStartParam, KeypressArg, Pattern, Side};
-
-const FWK_MAGIC: &[u8] = &[0x32, 0xAC];
-pub const FRAMEWORK_VID: u16 = 0x32AC;
-pub const LED_MATRIX_PID: u16 = 0x0020;
-pub const B1_LCD_PID: u16 = 0x0021;
+use crate::ledmatrix::{
+    AddonAnimation, Game, Gamma, KeypressArg, LedMatrixSubcommand, Orientation, Pattern,
+};
+use crate::profile::{ApplySubcommand, Config};
+use crate::report::{capabilities_json, device_json, print_json, version_json, Report};
 
 type Brightness = u8;
 
-// TODO: Use a shared enum with the firmware code
-#[derive(Clone, Copy)]
-#[repr(u8)]
-enum Command {
-    // addon stuff
-    Keypress = 0x30,
-    SetAddonAnimation = 0x31,
-    StopAddonAnimation = 0x32,
-    SetSide = 0x33,
-
-    Brightness = 0x00,
-    Pattern = 0x01,
-    Bootloader = 0x02,
-    Sleeping = 0x03,
-    Animate = 0x04,
-    Panic = 0x05,
-    DisplayBwImage = 0x06,
-    SendCol = 0x07,
-    CommitCols = 0x08,
-    _B1Reserved = 0x09,
-    StartGame = 0x10,
-    GameControl = 0x11,
-    _GameStatus = 0x12,
-    SetColor = 0x13,
-    DisplayOn = 0x14,
-    InvertScreen = 0x15,
-    SetPixelColumn = 0x16,
-    FlushFramebuffer = 0x17,
-    ClearRam = 0x18,
-    ScreenSaver = 0x19,
-    Fps = 0x1A,
-    PowerMode = 0x1B,
-    AnimationPeriod = 0x1C,
-    PwmFreq = 0x1E,
-    DebugMode = 0x1F,
-    Version = 0x20,
-}
-
-enum GameControlArg {
-    _Up = 0,
-    _Down = 1,
-    _Left = 2,
-    _Right = 3,
-    Exit = 4,
-    _SecondLeft = 5,
-    _SecondRight = 6,
-}
-
-const WIDTH: usize = 9;
-const HEIGHT: usize = 34;
-
-const SERIAL_TIMEOUT: Duration = Duration::from_millis(20);
+/// Which modules the command goes to, from the commandline
+fn selector(args: &crate::ClapCli) -> Selector {
+    let command_kind = args.command.as_ref().and_then(|x| x.kind());
+    let requested_kind = args.module.map(ModuleKind::from);
+    if let (Some(command_kind), Some(requested_kind)) = (command_kind, requested_kind) {
+        if command_kind != requested_kind {
+            eprintln!("--module {requested_kind:?} doesn't match the {command_kind:?} command");
+            std::process::exit(1);
+        }
+    }
+    Selector {
+        serial_number: args.serial_number.clone(),
+        kind: command_kind.or(requested_kind),
+        side: args.side.map(Into::into),
+        usb_path: args.usb_path.clone(),
+        index: args.index,
+    }
+}
 
 fn match_serialdevs(
     ports: &[SerialPortInfo],
     requested: &Option<String>,
-    pid: Option<u16>,
+    selector: &Selector,
 ) -> Vec<String> {
     if let Some(requested) = requested {
         for p in ports {
@@ -84,42 +64,44 @@ fn match_serialdevs(
                 return vec![p.port_name.clone()];
             }
         }
+        // Not found by the enumeration, like pseudo-terminals and symlinks.
+        // Use it anyway, for example to talk to the simulator.
+        if std::path::Path::new(requested).exists() {
+            return vec![requested.clone()];
+        }
         vec![]
     } else {
-        let mut compatible_devs = vec![];
-        let pids = if let Some(pid) = pid {
-            vec![pid]
-        } else {
-            // By default accept any type
-            vec![LED_MATRIX_PID, B1_LCD_PID, 0x22, 0xFF]
-        };
         // Find all supported Framework devices
-        for p in ports {
-            if let SerialPortType::UsbPort(usbinfo) = &p.port_type {
-                // macOS creates a /dev/cu.* and /dev/tty.* device.
-                // The latter can only be used for reading, not writing, so we have to ignore it.
-                #[cfg(target_os = "macos")]
-                if !p.port_name.starts_with("/dev/tty.") {
-                    continue;
-                }
-                if usbinfo.vid == FRAMEWORK_VID && pids.contains(&usbinfo.pid) {
-                    compatible_devs.push(p.port_name.clone());
-                }
-            }
-        }
-        compatible_devs
+        selector
+            .select(modules(ports))
+            .into_iter()
+            .map(|module| module.port_name)
+            .collect()
     }
 }
 
 pub fn find_serialdevs(args: &crate::ClapCli, wait_for_device: bool) -> (Vec<String>, bool) {
+    let selector = selector(args);
     let mut serialdevs: Vec<String>;
     let mut waited = false;
     loop {
         let ports = serialport::available_ports().expect("No ports found!");
+        serialdevs = match_serialdevs(&ports, &args.serial_dev, &selector);
         if args.list || args.verbose {
+            let mut listed = vec![];
             for p in &ports {
                 match &p.port_type {
                     SerialPortType::UsbPort(usbinfo) => {
+                        // Only list the selected modules, if some were selected
+                        if selector != Selector::default() && !serialdevs.contains(&p.port_name) {
+                            continue;
+                        }
+                        if args.json {
+                            if args.list {
+                                listed.push(list_json(p, usbinfo));
+                            }
+                            continue;
+                        }
                         println!("{}", p.port_name);
                         println!("  VID     {:#06X}", usbinfo.vid);
                         println!("  PID     {:#06X}", usbinfo.pid);
@@ -130,6 +112,12 @@ pub fn find_serialdevs(args: &crate::ClapCli, wait_for_device: bool) -> (Vec<Str
                             // TODO: Seems to replace the spaces with underscore, not sure why
                             println!("  Product {}", product);
                         }
+                        if let Some(path) = ModuleInfo::from_port(p).and_then(|m| m.usb_path) {
+                            println!("  Path    {}", path);
+                        }
+                        if args.list && usbinfo.vid == FRAMEWORK_VID {
+                            print_capabilities(&p.port_name);
+                        }
                     }
                     _ => {
                         //println!("{}", p.port_name);
@@ -137,12 +125,10 @@ pub fn find_serialdevs(args: &crate::ClapCli, wait_for_device: bool) -> (Vec<Str
                     }
                 }
             }
+            if args.list && args.json {
+                print_json(&Value::Array(listed));
+            }
         }
-        serialdevs = match_serialdevs(
-            &ports,
-            &args.serial_dev,
-            args.command.as_ref().map(|x| x.to_pid()),
-        );
         if serialdevs.is_empty() {
             if wait_for_device {
                 // Waited at least once, that means the device was not present
@@ -162,6 +148,190 @@ pub fn find_serialdevs(args: &crate::ClapCli, wait_for_device: bool) -> (Vec<Str
     (serialdevs, waited)
 }
 
+/// USB serial device for `--list --json`, with what the module reports about itself
+fn list_json(port: &SerialPortInfo, usbinfo: &UsbPortInfo) -> Value {
+    let mut device = device_json(&port.port_name, ModuleInfo::from_port(port).as_ref());
+    // Not just modules are listed, so take these from the USB device
+    device.insert("vid".to_string(), usbinfo.vid.into());
+    device.insert("pid".to_string(), usbinfo.pid.into());
+    device.insert(
+        "serial_number".to_string(),
+        usbinfo.serial_number.clone().into(),
+    );
+    device.insert("product".to_string(), usbinfo.product.clone().into());
+    if usbinfo.vid == FRAMEWORK_VID {
+        let mut module = InputModule::open(&port.port_name).ok();
+        let version = module.as_mut().and_then(|m| m.version().ok());
+        let capabilities = module
+            .as_mut()
+            .and_then(|m| m.capabilities().ok().flatten());
+        device.insert(
+            "version".to_string(),
+            version.map_or(Value::Null, version_json),
+        );
+        device.insert(
+            "capabilities".to_string(),
+            capabilities.map_or(Value::Null, |c| capabilities_json(&c)),
+        );
+    }
+    Value::Object(device)
+}
+
+/// Print the features that the module reports about itself
+fn print_capabilities(serialdev: &str) {
+    let capabilities = match InputModule::open(serialdev).and_then(|mut m| m.capabilities()) {
+        Ok(Some(capabilities)) => capabilities,
+        Ok(None) => {
+            println!("  Firmware too old to report capabilities");
+            return;
+        }
+        Err(err) => {
+            println!("  Failed to query capabilities: {}", err);
+            return;
+        }
+    };
+
+    fn join<T: std::fmt::Debug>(items: impl Iterator<Item = T>) -> String {
+        let items: Vec<String> = items.map(|x| format!("{:?}", x)).collect();
+        if items.is_empty() {
+            "-".to_string()
+        } else {
+            items.join(", ")
+        }
+    }
+
+    println!("  Module     {:?}", capabilities.module);
+    println!(
+        "  Size       {}x{}",
+        capabilities.width, capabilities.height
+    );
+    println!("  Protocol   {}", capabilities.protocol_version);
+    println!("  Commands   {}", join(capabilities.commands()));
+    println!("  Games      {}", join(capabilities.games()));
+    println!("  Patterns   {}", join(capabilities.patterns()));
+    println!("  Animations {}", join(capabilities.addon_animations()));
+}
+
+/// Hold the modules open for other clients, until stopped
+#[cfg(unix)]
+pub fn daemon_cmd(args: &crate::ClapCli) {
+    // Before listening, selecting by side asks the modules and that would wait
+    // for this daemon
+    let (serialdevs, _) = find_serialdevs(args, false);
+
+    let socket = socket_path();
+    let listener = Daemon::listen(&socket).unwrap_or_else(|err| {
+        eprintln!("Failed to listen on {}: {err}", socket.display());
+        std::process::exit(1);
+    });
+    // Others are opened when a client first uses them
+    let daemon = Daemon::new();
+    for serialdev in &serialdevs {
+        match daemon.open(serialdev) {
+            Ok(()) if args.verbose => println!("Opened {serialdev}"),
+            Ok(()) => {}
+            Err(err) => eprintln!("Failed to open {serialdev}: {err}"),
+        }
+    }
+
+    println!("Listening on {}", socket.display());
+    if let Err(err) = daemon.serve(listener) {
+        eprintln!("Failed to accept clients: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// How often to look for modules that were connected or disconnected
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+/// Polls further apart than this mean that the system was suspended
+const SUSPEND_GAP: Duration = Duration::from_secs(5);
+
+/// Apply the profiles whenever modules are connected, until stopped
+pub fn watch_cmd(args: &crate::ClapCli, watch_args: &ApplySubcommand) {
+    let config = load_config(args, watch_args.profile.as_deref());
+    let selector = selector(args);
+    // Modules by serial number, or by port if they have none, mapped to their port.
+    // Selected modules that got their profile
+    let mut applied: HashMap<String, String> = HashMap::new();
+    // Modules that aren't selected, not asked again until they reconnect
+    let mut rejected: HashMap<String, String> = HashMap::new();
+    let mut last_poll = Instant::now();
+    loop {
+        // Modules lose their settings during suspend, even if they stay connected
+        if last_poll.elapsed() > SUSPEND_GAP {
+            if args.verbose {
+                println!("Resumed from suspend");
+            }
+            applied.clear();
+            rejected.clear();
+        }
+
+        let ports = serialport::available_ports().unwrap_or_default();
+        let infos = modules(&ports);
+        let present: HashMap<String, String> = match args.serial_dev {
+            // Whether it exists, without asking the module anything
+            Some(_) => match_serialdevs(&ports, &args.serial_dev, &selector),
+            None => infos.iter().map(|info| info.port_name.clone()).collect(),
+        }
+        .into_iter()
+        .map(|port| {
+            let info = infos.iter().find(|info| info.port_name == port);
+            let key = info.and_then(|info| info.serial_number.clone());
+            (key.unwrap_or_else(|| port.clone()), port)
+        })
+        .collect();
+        applied.retain(|key, port| {
+            let connected = present.get(key) == Some(port);
+            if !connected {
+                println!("Disconnected {port}");
+            }
+            connected
+        });
+        rejected.retain(|key, port| present.get(key) == Some(port));
+
+        let arrived: Vec<(&String, &String)> = present
+            .iter()
+            .filter(|(key, _)| !applied.contains_key(*key) && !rejected.contains_key(*key))
+            .collect();
+        // Asks modules for their side, only do that when something changed
+        let selected = if arrived.is_empty() {
+            vec![]
+        } else {
+            match_serialdevs(&ports, &args.serial_dev, &selector)
+        };
+        for (key, port) in arrived {
+            if !selected.contains(port) {
+                if args.verbose {
+                    println!("Not selected {port}");
+                }
+                rejected.insert(key.clone(), port.clone());
+                continue;
+            }
+            let info = infos.iter().find(|info| info.port_name == *port);
+            match InputModule::open(port) {
+                Ok(mut module) => {
+                    match info.and_then(|info| info.serial_number.as_deref()) {
+                        Some(serial_number) => println!("Connected {port} ({serial_number})"),
+                        None => println!("Connected {port}"),
+                    }
+                    let profile = watch_args.profile.as_deref();
+                    apply_cmd(&mut module, info, &config, profile, &mut Report::new(false));
+                    applied.insert(key.clone(), port.clone());
+                }
+                // Might not be ready yet, try again next time
+                Err(err) => {
+                    if args.verbose {
+                        println!("Failed to open {port}: {err}");
+                    }
+                }
+            }
+        }
+
+        last_poll = Instant::now();
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
 /// Commands that interact with serial devices
 pub fn serial_commands(args: &crate::ClapCli) {
     let (serialdevs, waited): (Vec<String>, bool) = find_serialdevs(args, args.wait_for_device);
@@ -173,6 +343,14 @@ pub fn serial_commands(args: &crate::ClapCli) {
         thread::sleep(Duration::from_millis(1000));
         return;
     }
+    // Only needed to tell which module is which
+    let applying = matches!(args.command, Some(crate::Commands::Apply(_)));
+    let infos = if args.json || applying {
+        find_modules().unwrap_or_default()
+    } else {
+        vec![]
+    };
+    let mut devices = vec![];
 
     match &args.command {
         // TODO: Handle generic commands without code deduplication
@@ -181,108 +359,146 @@ pub fn serial_commands(args: &crate::ClapCli) {
                 if args.verbose {
                     println!("Selected serialdev: {:?}", serialdev);
                 }
+                let module = &mut open_module(serialdev);
+                let mut report = Report::new(args.json);
+
+                // Apply stored settings first, so that other arguments can override them
+                if ledmatrix_args.reset_settings {
+                    check(module.reset_settings());
+                }
+                if ledmatrix_args.load_settings {
+                    check(module.load_settings());
+                }
 
                 // addon stuff
                 if let Some(KeypressArg { keycode, pressed }) = ledmatrix_args.keypress {
-                    keypress_cmd(serialdev, keycode, pressed);
+                    keypress_cmd(module, keycode, pressed);
                 }
                 if let Some(addon_animation) = ledmatrix_args.set_addon_animation {
-                    set_addon_animation_cmd(serialdev, addon_animation);
+                    set_addon_animation_cmd(module, addon_animation);
                 }
                 if ledmatrix_args.stop_addon_animation {
-                    stop_addon_animation_cmd(serialdev);
+                    stop_addon_animation_cmd(module);
+                }
+                if let Some(side) = ledmatrix_args.set_side {
+                    set_side_cmd(module, side);
                 }
 
                 if ledmatrix_args.bootloader {
-                    bootloader_cmd(serialdev);
+                    bootloader_cmd(module);
                 }
                 if let Some(sleeping_arg) = ledmatrix_args.sleeping {
-                    sleeping_cmd(serialdev, sleeping_arg);
+                    sleeping_cmd(module, sleeping_arg, &mut report);
                 }
                 if let Some(brightness_arg) = ledmatrix_args.brightness {
-                    brightness_cmd(serialdev, brightness_arg);
+                    brightness_cmd(module, brightness_arg, &mut report);
                 }
                 if let Some(percentage) = ledmatrix_args.percentage {
                     assert!(percentage <= 100);
-                    percentage_cmd(serialdev, percentage);
+                    percentage_cmd(module, percentage);
                 }
                 if let Some(animate_arg) = ledmatrix_args.animate {
-                    animate_cmd(serialdev, animate_arg);
+                    animate_cmd(module, animate_arg, &mut report);
                 }
                 if let Some(pattern) = ledmatrix_args.pattern {
-                    pattern_cmd(serialdev, pattern);
+                    pattern_cmd(module, pattern);
                 }
                 if ledmatrix_args.all_brightnesses {
-                    all_brightnesses_cmd(serialdev);
+                    all_brightnesses_cmd(module);
                 }
                 if ledmatrix_args.panic {
-                    simple_cmd(serialdev, Command::Panic, &[0x00]);
+                    check(module.panic());
                 }
                 if let Some(image_path) = &ledmatrix_args.image_bw {
-                    display_bw_image_cmd(serialdev, image_path);
+                    display_bw_image_cmd(module, image_path);
                 }
 
                 if let Some(image_path) = &ledmatrix_args.image_gray {
-                    display_gray_image_cmd(serialdev, image_path);
+                    display_gray_image_cmd(module, image_path);
+                }
+
+                if let Some(image_path) = &ledmatrix_args.upload_gif {
+                    upload_gif_cmd(module, image_path);
+                }
+                if let Some(play) = ledmatrix_args.custom_animation {
+                    custom_animation_cmd(module, play, &mut report);
                 }
 
                 if let Some(values) = &ledmatrix_args.eq {
-                    eq_cmd(serialdev, values);
+                    eq_cmd(module, values);
                 }
 
                 if let Some(s) = &ledmatrix_args.string {
-                    show_string(serialdev, s);
+                    show_string(module, s);
                 }
 
                 if let Some(symbols) = &ledmatrix_args.symbols {
-                    show_symbols(serialdev, symbols);
+                    show_symbols(module, symbols);
+                }
+
+                if let Some(text) = &ledmatrix_args.scroll_text {
+                    scroll_text_cmd(module, text.as_deref(), ledmatrix_args, &mut report);
                 }
 
                 if let Some(game) = ledmatrix_args.start_game {
-                    start_game_cmd(serialdev, game, ledmatrix_args.game_param);
+                    start_game_cmd(module, game, ledmatrix_args);
                 }
 
                 if let Some(fps) = ledmatrix_args.animation_fps {
-                    animation_fps_cmd(serialdev, fps);
+                    animation_fps_cmd(module, fps, &mut report);
                 }
 
                 if let Some(freq) = ledmatrix_args.pwm_freq {
-                    pwm_freq_cmd(serialdev, freq);
+                    pwm_freq_cmd(module, freq, &mut report);
                 }
                 if let Some(debug_mode) = ledmatrix_args.debug_mode {
-                    debug_mode_cmd(serialdev, debug_mode);
+                    debug_mode_cmd(module, debug_mode, &mut report);
+                }
+                if let Some(lut_path) = &ledmatrix_args.gamma_lut {
+                    gamma_lut_cmd(module, lut_path);
+                }
+                if let Some(gamma) = ledmatrix_args.gamma {
+                    gamma_cmd(module, gamma, &mut report);
+                }
+                if let Some(orientation) = ledmatrix_args.orientation {
+                    orientation_cmd(module, orientation, &mut report);
                 }
 
                 if ledmatrix_args.stop_game {
-                    simple_cmd(
-                        serialdev,
-                        Command::GameControl,
-                        &[GameControlArg::Exit as u8],
-                    );
+                    check(module.game_control(GameControlArg::Exit));
+                }
+                if ledmatrix_args.game_status {
+                    game_status_cmd(module, &mut report);
+                }
+                if ledmatrix_args.save_settings {
+                    check(module.save_settings());
                 }
                 if ledmatrix_args.version {
-                    get_device_version(serialdev);
+                    get_device_version(module, &mut report);
+                }
+                if args.json {
+                    devices.push(device_report(report, serialdev, module, &infos));
                 }
             }
             // Commands that block and need manual looping
             if ledmatrix_args.blinking {
-                blinking_cmd(&serialdevs);
+                blinking_cmd(&mut open_modules(&serialdevs));
             }
             if ledmatrix_args.breathing {
-                breathing_cmd(&serialdevs);
+                breathing_cmd(&mut open_modules(&serialdevs));
             }
 
             if ledmatrix_args.random_eq {
-                random_eq_cmd(&serialdevs);
+                random_eq_cmd(&mut open_modules(&serialdevs));
             }
 
             #[cfg(feature = "audio-visualizations")]
             if ledmatrix_args.input_eq {
-                input_eq_cmd(&serialdevs);
+                input_eq_cmd(&mut open_modules(&serialdevs));
             }
 
             if ledmatrix_args.clock {
-                clock_cmd(&serialdevs);
+                clock_cmd(&mut open_modules(&serialdevs));
             }
         }
         Some(crate::Commands::B1Display(b1display_args)) => {
@@ -290,48 +506,53 @@ pub fn serial_commands(args: &crate::ClapCli) {
                 if args.verbose {
                     println!("Selected serialdev: {:?}", serialdev);
                 }
+                let module = &mut open_module(serialdev);
+                let mut report = Report::new(args.json);
 
                 if b1display_args.bootloader {
-                    bootloader_cmd(serialdev);
+                    bootloader_cmd(module);
                 }
                 if let Some(sleeping_arg) = b1display_args.sleeping {
-                    sleeping_cmd(serialdev, sleeping_arg);
+                    sleeping_cmd(module, sleeping_arg, &mut report);
                 }
                 if b1display_args.panic {
-                    simple_cmd(serialdev, Command::Panic, &[0x00]);
+                    check(module.panic());
                 }
                 if b1display_args.version {
-                    get_device_version(serialdev);
+                    get_device_version(module, &mut report);
                 }
                 if let Some(display_on) = b1display_args.display_on {
-                    display_on_cmd(serialdev, display_on);
+                    display_on_cmd(module, display_on, &mut report);
                 }
                 if let Some(invert_screen) = b1display_args.invert_screen {
-                    invert_screen_cmd(serialdev, invert_screen);
+                    invert_screen_cmd(module, invert_screen, &mut report);
                 }
                 if let Some(screensaver_on) = b1display_args.screen_saver {
-                    screensaver_cmd(serialdev, screensaver_on);
+                    screensaver_cmd(module, screensaver_on, &mut report);
                 }
                 if let Some(fps) = b1display_args.fps {
-                    fps_cmd(serialdev, fps);
+                    fps_cmd(module, fps, &mut report);
                 }
                 if let Some(power_mode) = b1display_args.power_mode {
-                    power_mode_cmd(serialdev, power_mode);
+                    power_mode_cmd(module, power_mode, &mut report);
                 }
                 if let Some(fps) = b1display_args.animation_fps {
-                    animation_fps_cmd(serialdev, fps);
+                    animation_fps_cmd(module, fps, &mut report);
                 }
                 if let Some(image_path) = &b1display_args.image {
-                    b1display_bw_image_cmd(serialdev, image_path);
+                    b1display_bw_image_cmd(module, image_path);
                 }
                 if let Some(image_path) = &b1display_args.animated_gif {
-                    gif_cmd(serialdev, image_path);
+                    gif_cmd(module, image_path);
                 }
                 if b1display_args.clear_ram {
-                    simple_cmd(serialdev, Command::ClearRam, &[0x00]);
+                    check(module.clear_ram());
                 }
                 if let Some(pattern) = b1display_args.pattern {
-                    b1_display_pattern(serialdev, pattern);
+                    b1_display_pattern(module, pattern);
+                }
+                if args.json {
+                    devices.push(device_report(report, serialdev, module, &infos));
                 }
             }
         }
@@ -340,282 +561,478 @@ pub fn serial_commands(args: &crate::ClapCli) {
                 if args.verbose {
                     println!("Selected serialdev: {:?}", serialdev);
                 }
+                let module = &mut open_module(serialdev);
+                let mut report = Report::new(args.json);
 
                 if c1minimal_args.bootloader {
-                    bootloader_cmd(serialdev);
+                    bootloader_cmd(module);
                 }
                 if let Some(sleeping_arg) = c1minimal_args.sleeping {
-                    sleeping_cmd(serialdev, sleeping_arg);
+                    sleeping_cmd(module, sleeping_arg, &mut report);
                 }
                 if c1minimal_args.panic {
-                    simple_cmd(serialdev, Command::Panic, &[0x00]);
+                    check(module.panic());
                 }
                 if c1minimal_args.version {
-                    get_device_version(serialdev);
+                    get_device_version(module, &mut report);
                 }
                 if let Some(color) = c1minimal_args.set_color {
-                    set_color_cmd(serialdev, color);
+                    set_color_cmd(module, color);
+                }
+                if args.json {
+                    devices.push(device_report(report, serialdev, module, &infos));
+                }
+            }
+        }
+        Some(crate::Commands::Apply(apply_args)) => {
+            let config = load_config(args, apply_args.profile.as_deref());
+            for serialdev in &serialdevs {
+                if args.verbose {
+                    println!("Selected serialdev: {:?}", serialdev);
+                }
+                let module = &mut open_module(serialdev);
+                let mut report = Report::new(args.json);
+
+                let info = infos.iter().find(|info| info.port_name == *serialdev);
+                apply_cmd(
+                    module,
+                    info,
+                    &config,
+                    apply_args.profile.as_deref(),
+                    &mut report,
+                );
+                if args.json {
+                    devices.push(device_report(report, serialdev, module, &infos));
                 }
             }
         }
         _ => {}
     }
+    if args.json {
+        print_json(&Value::Array(devices));
+    }
 }
 
-fn get_device_version(serialdev: &str) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
-    simple_cmd_port(&mut port, Command::Version, &[]);
+/// Queried values of one module, for the JSON output
+fn device_report(
+    report: Report,
+    serialdev: &str,
+    module: &mut InputModule,
+    infos: &[ModuleInfo],
+) -> Value {
+    let info = infos.iter().find(|info| info.port_name == *serialdev);
+    report.into_device(serialdev, info, module.version().ok())
+}
 
-    let mut response: Vec<u8> = vec![0; 32];
-    port.read_exact(response.as_mut_slice())
-        .expect("Found no data!");
+/// Push the settings and content of the module's profile
+fn apply_cmd(
+    module: &mut InputModule,
+    info: Option<&ModuleInfo>,
+    config: &Config,
+    profile: Option<&str>,
+    report: &mut Report,
+) {
+    let serial_number = info.and_then(|info| info.serial_number.as_deref());
+    let device = config.device(serial_number, || module.side().ok());
+    let Some(name) = profile
+        .or(device.and_then(|device| device.profile.as_deref()))
+        .or(config.default_profile.as_deref())
+    else {
+        report.value("profile", Value::Null, "No profile configured".to_string());
+        return;
+    };
+    let profile = config
+        .profile(name)
+        .unwrap_or_else(|err| exit_config_error(err));
+    let content = device
+        .and_then(|device| device.content.as_ref())
+        .or(profile.content.as_ref());
+
+    // Modules that aren't found over USB, like the simulator, can tell themselves
+    let kind = info.and_then(ModuleInfo::kind).or_else(|| {
+        let capabilities = module.capabilities().ok().flatten();
+        capabilities.map(|capabilities| capabilities.module.into())
+    });
+    match kind {
+        Some(ModuleKind::LedMatrix) => {
+            if let Some(side) = device.and_then(|device| device.set_side()) {
+                set_side_cmd(module, side);
+            }
+            if let Some(brightness) = profile.brightness {
+                brightness_cmd(module, Some(brightness), report);
+            }
+            if let Some(freq) = profile.pwm_freq {
+                pwm_freq_cmd(module, Some(freq), report);
+            }
+            if let Some(gamma) = profile.gamma {
+                gamma_cmd(module, Some(gamma), report);
+            }
+            if let Some(orientation) = profile.orientation {
+                orientation_cmd(module, Some(orientation), report);
+            }
+            if let Some(fps) = profile.animation_fps {
+                animation_fps_cmd(module, Some(fps), report);
+            }
+            if let Some(addon_animation) = profile.addon_animation {
+                set_addon_animation_cmd(module, addon_animation);
+            }
+            match content {
+                Some(Content::Pattern(pattern)) => match Pattern::from_str(pattern, true) {
+                    Ok(pattern) => pattern_cmd(module, pattern),
+                    Err(err) => eprintln!("Invalid pattern in profile {name}: {err}"),
+                },
+                Some(Content::Percentage(percentage)) => percentage_cmd(module, *percentage),
+                Some(Content::Image(image_path)) => display_gray_image_cmd(module, image_path),
+                Some(Content::Gif(image_path)) => upload_gif_cmd(module, image_path),
+                // Keeps scrolling, at the default speed of --scroll-text
+                Some(Content::Text(text)) => {
+                    check(module.scroll_text(text, 8, ScrollDirection::Down, 0))
+                }
+                None => {}
+            }
+        }
+        Some(ModuleKind::B1Display) => {
+            if let Some(fps) = profile.fps {
+                fps_cmd(module, Some(fps), report);
+            }
+            if let Some(power_mode) = profile.power_mode {
+                power_mode_cmd(module, Some(power_mode), report);
+            }
+            if let Some(fps) = profile.animation_fps {
+                animation_fps_cmd(module, Some(fps), report);
+            }
+            if let Some(screen_saver) = profile.screen_saver {
+                screensaver_cmd(module, Some(screen_saver), report);
+            }
+            match content {
+                Some(Content::Pattern(pattern)) => match B1Pattern::from_str(pattern, true) {
+                    Ok(pattern) => b1_display_pattern(module, pattern),
+                    Err(err) => eprintln!("Invalid pattern in profile {name}: {err}"),
+                },
+                Some(Content::Image(image_path)) => b1display_bw_image_cmd(module, image_path),
+                Some(content) => eprintln!("B1 Display can't show {content:?}"),
+                None => {}
+            }
+        }
+        Some(ModuleKind::C1Minimal) => {
+            if let Some(color) = profile.color {
+                set_color_cmd(module, color);
+            }
+            if let Some(content) = content {
+                eprintln!("C1 Minimal can't show {content:?}");
+            }
+        }
+        None => {
+            eprintln!("Unknown kind of module, not applying profile {name}");
+            return;
+        }
+    }
+    report.value("profile", name, format!("Applied profile: {name}"));
+}
 
-    let major = response[0];
-    let minor = (response[1] & 0xF0) >> 4;
-    let patch = response[1] & 0x0F;
-    let pre_release = response[2] == 1;
-    print!("Device Version: {major}.{minor}.{patch}");
-    if pre_release {
-        print!(" (Pre-Release)");
+fn get_device_version(module: &mut InputModule, report: &mut Report) {
+    let version = query(module.version());
+    let mut text = format!(
+        "Device Version: {}.{}.{}",
+        version.major, version.minor, version.patch
+    );
+    if version.pre_release {
+        text.push_str(" (Pre-Release)");
     }
-    println!();
+    report.value("version", version_json(version), text);
 }
 
 // addon stuff
-fn keypress_cmd(serialdev: &str, keycode: u32, pressed: bool) {
-    let keycode_bytes = keycode.to_le_bytes();
-    simple_cmd(serialdev, Command::Keypress, &[keycode_bytes[0], keycode_bytes[1], pressed as u8]);
+fn keypress_cmd(module: &mut InputModule, keycode: u32, pressed: bool) {
+    check(module.keypress(keycode, pressed));
 }
-fn set_addon_animation_cmd(serialdev: &str, addon_animation: AddonAnimation) {
-    simple_cmd(serialdev, Command::SetAddonAnimation, &[addon_animation as u8])
+fn set_addon_animation_cmd(module: &mut InputModule, addon_animation: AddonAnimation) {
+    check(module.set_addon_animation(addon_animation.into()));
 }
-fn stop_addon_animation_cmd(serialdev: &str) {
-    simple_cmd(serialdev, Command::StopAddonAnimation, &[0x00]);
+fn stop_addon_animation_cmd(module: &mut InputModule) {
+    check(module.stop_addon_animation());
 }
-fn set_side_cmd(serialdev: &str, side: Side) {
-    simple_cmd(serialdev, Command::SetSide, &[matches!(side, Side::Right) as u8]);
+fn set_side_cmd(module: &mut InputModule, side: impl Into<inputmodule_control::protocol::Side>) {
+    check(module.set_side(side.into()));
 }
 
-fn bootloader_cmd(serialdev: &str) {
-    simple_cmd(serialdev, Command::Bootloader, &[0x00]);
+fn bootloader_cmd(module: &mut InputModule) {
+    check(module.bootloader());
 }
 
-fn percentage_cmd(serialdev: &str, arg: u8) {
-    simple_cmd(
-        serialdev,
-        Command::Pattern,
-        &[Pattern::Percentage as u8, arg],
-    );
+fn percentage_cmd(module: &mut InputModule, arg: u8) {
+    check(module.set_percentage(arg));
 }
 
-fn pattern_cmd(serialdev: &str, arg: Pattern) {
-    simple_cmd(serialdev, Command::Pattern, &[arg as u8]);
+fn pattern_cmd(module: &mut InputModule, arg: Pattern) {
+    check(module.set_pattern(arg.into()));
+}
+
+fn start_game_cmd(module: &mut InputModule, game: Game, args: &LedMatrixSubcommand) {
+    let game = match (game, args.game_param) {
+        (Game::Snake, _) => inputmodule_protocol::Game::Snake(SnakeParam {
+            wrap: args.snake_wrap,
+            speed_up: args.snake_speed_up,
+        }),
+        (Game::Pong, _) => inputmodule_protocol::Game::Pong(PongParam {
+            cpu: args.pong_cpu.map(Into::into),
+            difficulty: args.pong_difficulty.into(),
+        }),
+        (Game::Tetris, _) => inputmodule_protocol::Game::Tetris,
+        (Game::GameOfLife, param) => {
+            let mut life = match (&args.life_seed, param) {
+                (Some(image_path), _) => GameOfLifeParam {
+                    seed: bw_image(image_path),
+                    ..GameOfLifeStartParam::Upload.into()
+                },
+                (None, Some(param)) => GameOfLifeStartParam::from(param).into(),
+                (None, None) => {
+                    println!("To start Game of Life, provide a --game-param or --life-seed");
+                    return;
+                }
+            };
+            life.edges = args.life_edges.into();
+            life.rule = args.life_rule.0;
+            life.age_brightness = args.life_age_brightness;
+            life.reseed = args.life_reseed;
+            inputmodule_protocol::Game::GameOfLife(life)
+        }
+    };
+    check(module.start_game(game));
 }
 
-fn start_game_cmd(serialdev: &str, game: Game, param: Option<GameOfLifeStartParam>) {
-    match (game, param) {
-        (Game::GameOfLife, Some(param)) => {
-            simple_cmd(serialdev, Command::StartGame, &[game as u8, param as u8])
+fn game_status_cmd(module: &mut InputModule, report: &mut Report) {
+    let status = query(module.game_status());
+    let Some(game) = status.game else {
+        report.value("game", Value::Null, "No game running".to_string());
+        return;
+    };
+
+    let mut text = format!("Game: {game:?}\n");
+    text += &format!(
+        "  Status: {}\n",
+        if status.game_over { "Over" } else { "Running" }
+    );
+    let mut value = json!({
+        "game": format!("{game:?}"),
+        "game_over": status.game_over,
+    });
+    match game {
+        GameVal::Snake => {
+            text += &format!("  Score:  {}\n", status.score);
+            text += &format!("  Length: {}", status.snake_length);
+            value["score"] = status.score.into();
+            value["length"] = status.snake_length.into();
+        }
+        GameVal::Pong => {
+            text += &format!("  Speed:  {}\n", status.level);
+            text += &format!(
+                "  Score:  {}:{}",
+                status.pong_score[0], status.pong_score[1]
+            );
+            value["speed"] = status.level.into();
+            value["score"] = json!(status.pong_score);
         }
-        (Game::GameOfLife, None) => {
-            println!("To start Game of Life, provide a --game-param");
+        GameVal::Tetris => {
+            text += &format!("  Score:  {}\n", status.score);
+            text += &format!("  Level:  {}", status.level);
+            value["score"] = status.score.into();
+            value["level"] = status.level.into();
+        }
+        GameVal::GameOfLife => {
+            text += &format!("  Living cells: {}", status.score);
+            value["living_cells"] = status.score.into();
         }
-        (_, _) => simple_cmd(serialdev, Command::StartGame, &[game as u8]),
     }
+    report.value("game", value, text);
 }
 
-fn simple_cmd_multiple(serialdevs: &Vec<String>, command: Command, args: &[u8]) {
-    for serialdev in serialdevs {
-        simple_cmd(serialdev, command, args);
-    }
+/// Load the config file. Exits if it's missing or invalid.
+fn load_config(args: &crate::ClapCli, profile: Option<&str>) -> Config {
+    Config::load(args.config.as_deref(), profile).unwrap_or_else(|err| exit_config_error(err))
 }
 
-fn simple_cmd(serialdev: &str, command: Command, args: &[u8]) {
-    let port_result = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open();
+fn exit_config_error(err: ConfigError) -> ! {
+    eprintln!("{err}");
+    std::process::exit(1);
+}
 
-    match port_result {
-        Ok(mut port) => simple_cmd_port(&mut port, command, args),
-        Err(error) => match error.kind {
-            serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied) => panic!("Permission denied, couldn't access inputmodule serialport. Ensure that you have permission, for example using a udev rule or sudo."),
-            other_error => panic!("Couldn't open port: {:?}", other_error)
-        }
-    };
+/// Open the serial port of a module, without it nothing can be done
+fn open_module(serialdev: &str) -> InputModule {
+    InputModule::open(serialdev).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    })
 }
 
-fn open_serialport(serialdev: &str) -> Box<dyn SerialPort> {
-    serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port")
+/// Open the serial ports of all modules, for commands that keep running
+fn open_modules(serialdevs: &[String]) -> Vec<InputModule> {
+    serialdevs.iter().map(|dev| open_module(dev)).collect()
 }
 
-fn simple_open_cmd(serialport: &mut Box<dyn SerialPort>, command: Command, args: &[u8]) {
-    simple_cmd_port(serialport, command, args);
+/// Report a failed command. The following ones might still work.
+fn check(result: Result<(), Error>) {
+    if let Err(err) = result {
+        eprintln!("{err}");
+    }
 }
 
-fn simple_cmd_port(port: &mut Box<dyn SerialPort>, command: Command, args: &[u8]) {
-    let mut buffer: [u8; 64] = [0; 64];
-    buffer[..2].copy_from_slice(FWK_MAGIC);
-    buffer[2] = command as u8;
-    buffer[3..3 + args.len()].copy_from_slice(args);
-    port.write_all(&buffer[..3 + args.len()])
-        .expect("Write failed!");
+/// Result of a query. Without it there's nothing to show.
+fn query<T>(result: Result<T, Error>) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    })
 }
 
-fn sleeping_cmd(serialdev: &str, arg: Option<bool>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
+fn set_brightness_multiple(modules: &mut [InputModule], brightness: u8) {
+    for module in modules {
+        check(module.set_brightness(brightness));
+    }
+}
 
+fn sleeping_cmd(module: &mut InputModule, arg: Option<bool>, report: &mut Report) {
     if let Some(goto_sleep) = arg {
-        simple_cmd_port(&mut port, Command::Sleeping, &[u8::from(goto_sleep)]);
+        check(module.set_sleeping(goto_sleep));
     } else {
-        simple_cmd_port(&mut port, Command::Sleeping, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let sleeping: bool = response[0] == 1;
-        println!("Currently sleeping: {sleeping}");
+        let sleeping = query(module.sleeping());
+        report.value(
+            "sleeping",
+            sleeping,
+            format!("Currently sleeping: {sleeping}"),
+        );
     }
 }
 
-fn debug_mode_cmd(serialdev: &str, arg: Option<bool>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn debug_mode_cmd(module: &mut InputModule, arg: Option<bool>, report: &mut Report) {
     if let Some(enable_debug) = arg {
-        simple_cmd_port(&mut port, Command::DebugMode, &[u8::from(enable_debug)]);
+        check(module.set_debug_mode(enable_debug));
     } else {
-        simple_cmd_port(&mut port, Command::DebugMode, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let debug_mode: bool = response[0] == 1;
-        println!("Debug Mode enabled: {debug_mode}");
+        let debug_mode = query(module.debug_mode());
+        report.value(
+            "debug_mode",
+            debug_mode,
+            format!("Debug Mode enabled: {debug_mode}"),
+        );
     }
 }
 
-fn brightness_cmd(serialdev: &str, arg: Option<u8>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
-    if let Some(brightness) = arg {
-        simple_cmd_port(&mut port, Command::Brightness, &[brightness]);
+fn gamma_cmd(module: &mut InputModule, arg: Option<Gamma>, report: &mut Report) {
+    if let Some(gamma) = arg {
+        check(module.set_gamma(gamma.into()));
     } else {
-        simple_cmd_port(&mut port, Command::Brightness, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let brightness: u8 = response[0];
-        println!("Current brightness: {brightness}");
+        let gamma = query(module.gamma());
+        report.value(
+            "gamma",
+            format!("{gamma:?}"),
+            format!("Brightness curve: {gamma:?}"),
+        );
     }
 }
 
-fn animate_cmd(serialdev: &str, arg: Option<bool>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
-    if let Some(animate) = arg {
-        simple_cmd_port(&mut port, Command::Animate, &[animate as u8]);
+fn orientation_cmd(module: &mut InputModule, arg: Option<Orientation>, report: &mut Report) {
+    if let Some(orientation) = arg {
+        check(module.set_orientation(orientation.into()));
     } else {
-        simple_cmd_port(&mut port, Command::Animate, &[]);
+        let orientation = query(module.orientation());
+        report.value(
+            "orientation",
+            format!("{orientation:?}"),
+            format!("Orientation: {orientation:?}"),
+        );
+    }
+}
 
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
+fn gamma_lut_cmd(module: &mut InputModule, lut_path: &str) {
+    let contents = std::fs::read_to_string(lut_path).unwrap_or_else(|err| {
+        eprintln!("Failed to read gamma LUT {lut_path}: {err}");
+        std::process::exit(1);
+    });
+    let values: Result<Vec<u8>, _> = contents.split_whitespace().map(str::parse).collect();
+    let Ok(values) = values else {
+        eprintln!("Gamma LUT values must be between 0 and 255");
+        std::process::exit(1);
+    };
+    let Ok(lut) = <[u8; GAMMA_LUT_SIZE]>::try_from(values) else {
+        eprintln!("Gamma LUT must have {GAMMA_LUT_SIZE} values");
+        std::process::exit(1);
+    };
 
-        let animating = response[0] == 1;
-        println!("Currently animating: {animating}");
-    }
+    check(module.set_gamma_lut(&lut));
 }
 
-/// Stage greyscale values for a single column. Must be committed with commit_cols()
-fn send_col(port: &mut Box<dyn SerialPort>, x: u8, vals: &[u8]) {
-    let mut buffer: [u8; 64] = [0; 64];
-    buffer[0] = x;
-    buffer[1..vals.len() + 1].copy_from_slice(vals);
-    simple_cmd_port(port, Command::SendCol, &buffer[0..vals.len() + 1]);
+fn brightness_cmd(module: &mut InputModule, arg: Option<u8>, report: &mut Report) {
+    if let Some(brightness) = arg {
+        check(module.set_brightness(brightness));
+    } else {
+        let brightness = query(module.brightness());
+        report.value(
+            "brightness",
+            brightness,
+            format!("Current brightness: {brightness}"),
+        );
+    }
 }
 
-/// Commit the changes from sending individual cols with send_col(), displaying the matrix.
-/// This makes sure that the matrix isn't partially updated.
-fn commit_cols(port: &mut Box<dyn SerialPort>) {
-    simple_cmd_port(port, Command::CommitCols, &[]);
+fn animate_cmd(module: &mut InputModule, arg: Option<bool>, report: &mut Report) {
+    if let Some(animate) = arg {
+        check(module.set_animate(animate));
+    } else {
+        let animating = query(module.animate());
+        report.value(
+            "animating",
+            animating,
+            format!("Currently animating: {animating}"),
+        );
+    }
 }
 
 ///Increase the brightness with each pixel.
 ///Only 0-255 available, so it can't fill all 306 LEDs
-fn all_brightnesses_cmd(serialdev: &str) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
-    for x in 0..WIDTH {
-        let mut vals: [u8; HEIGHT] = [0; HEIGHT];
-
-        for y in 0..HEIGHT {
+fn all_brightnesses_cmd(module: &mut InputModule) {
+    let mut pixels: GreyPixels = [[0; HEIGHT]; WIDTH];
+    for (x, vals) in pixels.iter_mut().enumerate() {
+        for (y, val) in vals.iter_mut().enumerate() {
             let brightness = x + WIDTH * y;
-            vals[y] = if brightness > 255 { 0 } else { brightness } as u8;
+            *val = if brightness > 255 { 0 } else { brightness } as u8;
         }
-
-        send_col(&mut port, x as u8, &vals);
     }
-    commit_cols(&mut port);
+    check(module.draw_grey(&pixels, None));
 }
 
-fn blinking_cmd(serialdevs: &Vec<String>) {
+fn blinking_cmd(modules: &mut [InputModule]) {
     let duration = Duration::from_millis(500);
     loop {
-        simple_cmd_multiple(serialdevs, Command::Brightness, &[0]);
+        set_brightness_multiple(modules, 0);
         thread::sleep(duration);
-        simple_cmd_multiple(serialdevs, Command::Brightness, &[200]);
+        set_brightness_multiple(modules, 200);
         thread::sleep(duration);
     }
 }
 
-fn breathing_cmd(serialdevs: &Vec<String>) {
+fn breathing_cmd(modules: &mut [InputModule]) {
     loop {
         // Go quickly from 250 to 50
         for i in 0..40 {
-            simple_cmd_multiple(serialdevs, Command::Brightness, &[250 - i * 5]);
+            set_brightness_multiple(modules, 250 - i * 5);
             thread::sleep(Duration::from_millis(25));
         }
 
         // Go slowly from 50 to 0
         for i in 0..50 {
-            simple_cmd_multiple(serialdevs, Command::Brightness, &[50 - i]);
+            set_brightness_multiple(modules, 50 - i);
             thread::sleep(Duration::from_millis(10));
         }
 
         // Go slowly from 0 to 50
         for i in 0..50 {
-            simple_cmd_multiple(serialdevs, Command::Brightness, &[i]);
+            set_brightness_multiple(modules, i);
             thread::sleep(Duration::from_millis(10));
         }
 
         // Go quickly from 50 to 250
         for i in 0..40 {
-            simple_cmd_multiple(serialdevs, Command::Brightness, &[50 + i * 5]);
+            set_brightness_multiple(modules, 50 + i * 5);
             thread::sleep(Duration::from_millis(25));
         }
     }
@@ -625,8 +1042,13 @@ fn breathing_cmd(serialdevs: &Vec<String>) {
 /// Confirmed working with PNG and GIF.
 /// Must be 9x34 in size.
 /// Sends everything in a single command
-fn display_bw_image_cmd(serialdev: &str, image_path: &str) {
-    let mut vals: [u8; 39] = [0; 39];
+fn display_bw_image_cmd(module: &mut InputModule, image_path: &str) {
+    check(module.draw_bw(&bw_image(image_path)));
+}
+
+/// Convert an image (9x34px) to black/white, in the format of [`Command::Draw`]
+fn bw_image(image_path: &str) -> [u8; DRAW_BYTES] {
+    let mut vals: [u8; DRAW_BYTES] = [0; DRAW_BYTES];
 
     let img = ImageReader::open(image_path)
         .unwrap()
@@ -644,13 +1066,16 @@ fn display_bw_image_cmd(serialdev: &str, image_path: &str) {
             vals[i / 8] |= 1 << (i % 8);
         }
     }
-
-    simple_cmd(serialdev, Command::DisplayBwImage, &vals);
+    vals
 }
 
 // Calculate pixel brightness from an RGB triple
-fn pixel_to_brightness(pixel: &Luma<u8>) -> u8 {
+// Modules that apply a brightness curve themselves get the value unchanged
+fn pixel_to_brightness(pixel: &Luma<u8>, device_gamma: bool) -> u8 {
     let brightness = pixel.0[0];
+    if device_gamma {
+        return brightness;
+    }
     // Poor man's scaling to make the greyscale pop better.
     // Should find a good function.
     if brightness > 200 {
@@ -668,12 +1093,7 @@ fn pixel_to_brightness(pixel: &Luma<u8>) -> u8 {
 
 /// Display an image in greyscale
 /// Sends each 1x34 column and then commits => 10 commands
-fn display_gray_image_cmd(serialdev: &str, image_path: &str) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn display_gray_image_cmd(module: &mut InputModule, image_path: &str) {
     let img = ImageReader::open(image_path)
         .unwrap()
         .decode()
@@ -683,21 +1103,67 @@ fn display_gray_image_cmd(serialdev: &str, image_path: &str) {
     let height = img.height();
     assert!(width == 9);
     assert!(height == 34);
-    for x in 0..WIDTH {
-        let mut vals: [u8; HEIGHT] = [0; HEIGHT];
+    let device_gamma = query(module.applies_gamma());
+    let mut pixels: GreyPixels = [[0; HEIGHT]; WIDTH];
+    for (x, vals) in pixels.iter_mut().enumerate() {
+        for (y, val) in vals.iter_mut().enumerate() {
+            *val = pixel_to_brightness(img.get_pixel(x as u32, y as u32), device_gamma);
+        }
+    }
+    check(module.draw_grey(&pixels, None));
+}
+
+/// Upload the frames of a GIF as custom animation and start playing it
+fn upload_gif_cmd(module: &mut InputModule, image_path: &str) {
+    let img = std::fs::File::open(image_path).unwrap();
+    let frames = GifDecoder::new(img)
+        .unwrap()
+        .into_frames()
+        .collect_frames()
+        .unwrap();
+    if frames.len() > MAX_ANIMATION_FRAMES {
+        println!(
+            "GIF has {} frames, only the first {} are uploaded",
+            frames.len(),
+            MAX_ANIMATION_FRAMES
+        );
+    }
 
-        for y in 0..HEIGHT {
-            let pixel = img.get_pixel(x as u32, y as u32);
-            vals[y] = pixel_to_brightness(pixel);
+    let device_gamma = query(module.applies_gamma());
+    for (index, frame) in frames.into_iter().take(MAX_ANIMATION_FRAMES).enumerate() {
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay = (numer / denom).min(u16::MAX as u32) as u16;
+        let img = DynamicImage::from(frame.into_buffer()).into_luma8();
+        assert!(img.width() == WIDTH as u32);
+        assert!(img.height() == HEIGHT as u32);
+
+        for x in 0..WIDTH {
+            let mut vals: [u8; HEIGHT] = [0; HEIGHT];
+            for y in 0..HEIGHT {
+                vals[y] = pixel_to_brightness(img.get_pixel(x as u32, y as u32), device_gamma);
+            }
+            check(module.stage_grey_col(x as u8, &vals));
         }
+        check(module.store_animation_frame(index as u8, delay));
+    }
+    check(module.play_custom_animation(true));
+}
 
-        send_col(&mut port, x as u8, &vals)
+fn custom_animation_cmd(module: &mut InputModule, arg: Option<bool>, report: &mut Report) {
+    if let Some(play) = arg {
+        check(module.play_custom_animation(play));
+    } else {
+        let playing = query(module.playing_custom_animation());
+        report.value(
+            "custom_animation",
+            playing,
+            format!("Playing custom animation: {playing}"),
+        );
     }
-    commit_cols(&mut port);
 }
 
 /// Display an equlizer looking animation with random values.
-fn random_eq_cmd(serialdevs: &Vec<String>) {
+fn random_eq_cmd(modules: &mut [InputModule]) {
     loop {
         // Lower values more likely, makes it look nicer
         //weights = [i*i for i in range(33, 0, -1)]
@@ -708,8 +1174,8 @@ fn random_eq_cmd(serialdevs: &Vec<String>) {
             .unwrap()
             .copied()
             .collect::<Vec<_>>();
-        for serialdev in serialdevs {
-            eq_cmd(serialdev, vals.as_slice());
+        for module in modules.iter_mut() {
+            eq_cmd(module, vals.as_slice());
         }
         thread::sleep(Duration::from_millis(200));
     }
@@ -726,7 +1192,7 @@ pub struct AnalyzerResult {
 
 #[cfg(feature = "audio-visualizations")]
 // Equalizer-like animation that expands as volume goes up and retracts as it goes down
-fn input_eq_cmd(serialdevs: &Vec<String>) {
+fn input_eq_cmd(modules: &mut [InputModule]) {
     // Example from https://github.com/Rahix/visualizer2/blob/canon/README.md
 
     // Initialize the logger.  Take a look at the sources if you want to customize
@@ -784,8 +1250,8 @@ fn input_eq_cmd(serialdevs: &Vec<String>) {
                 })
                 .collect::<Vec<_>>();
 
-            for serialdev in serialdevs {
-                eq_cmd(serialdev, volumes_to_display.as_slice())
+            for module in modules.iter_mut() {
+                eq_cmd(module, volumes_to_display.as_slice())
             }
         });
         thread::sleep(Duration::from_millis(30));
@@ -794,7 +1260,7 @@ fn input_eq_cmd(serialdevs: &Vec<String>) {
 
 /// Display 9 values in equalizer diagram starting from the middle, going up and down
 /// TODO: Implement a commandline parameter for this
-fn eq_cmd(serialdev: &str, vals: &[u8]) {
+fn eq_cmd(module: &mut InputModule, vals: &[u8]) {
     assert!(vals.len() <= WIDTH);
     let mut matrix: [[Brightness; 34]; 9] = [[0; 34]; 9];
 
@@ -811,15 +1277,15 @@ fn eq_cmd(serialdev: &str, vals: &[u8]) {
         }
     }
 
-    render_matrix(serialdev, &matrix);
+    render_matrix(module, &matrix);
 }
 
 /// Show a black/white matrix
 /// Send everything in a single command
-fn render_matrix(serialdev: &str, matrix: &[[u8; 34]; 9]) {
+fn render_matrix(module: &mut InputModule, matrix: &[[u8; 34]; 9]) {
     // One bit for each LED, on or off
     // 39 = ceil(34 * 9 / 8)
-    let mut vals: [u8; 39] = [0x00; 39];
+    let mut vals: [u8; DRAW_BYTES] = [0x00; DRAW_BYTES];
 
     for x in 0..9 {
         for y in 0..34 {
@@ -830,33 +1296,68 @@ fn render_matrix(serialdev: &str, matrix: &[[u8; 34]; 9]) {
         }
     }
 
-    simple_cmd(serialdev, Command::DisplayBwImage, &vals);
+    check(module.draw_bw(&vals));
 }
 
 /// Render the current time and display.
 /// Loops forever, updating every second
-fn clock_cmd(serialdevs: &Vec<String>) {
+fn clock_cmd(modules: &mut [InputModule]) {
     loop {
         let date = Local::now();
         let current_time = date.format("%H:%M").to_string();
         println!("Current Time = {current_time}");
 
-        for serialdev in serialdevs {
-            show_string(serialdev, &current_time);
+        for module in modules.iter_mut() {
+            show_string(module, &current_time);
         }
         thread::sleep(Duration::from_millis(1000));
     }
 }
 
 /// Render a string with up to five letters
-fn show_string(serialdev: &str, s: &str) {
+fn show_string(module: &mut InputModule, s: &str) {
     let items: Vec<Vec<u8>> = s.chars().take(5).map(convert_font).collect();
-    show_font(serialdev, &items);
+    show_font(module, &items);
+}
+
+/// Let the module scroll text with its own font
+///
+/// Without text, check whether the text is still scrolling.
+fn scroll_text_cmd(
+    module: &mut InputModule,
+    text: Option<&str>,
+    args: &LedMatrixSubcommand,
+    report: &mut Report,
+) {
+    if let Some(text) = text {
+        check(module.scroll_text(
+            text,
+            args.scroll_speed,
+            args.scroll_direction.into(),
+            args.scroll_loops,
+        ));
+        if !args.scroll_wait {
+            return;
+        }
+    }
+
+    loop {
+        let scrolling = query(module.scrolling_text());
+        if !args.scroll_wait || !scrolling {
+            report.value(
+                "scrolling_text",
+                scrolling,
+                format!("Scrolling text: {scrolling}"),
+            );
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
 }
 
 /// Render up to five 5x6 pixel font items
-fn show_font(serialdev: &str, font_items: &[Vec<u8>]) {
-    let mut vals: [u8; 39] = [0x00; 39];
+fn show_font(module: &mut InputModule, font_items: &[Vec<u8>]) {
+    let mut vals: [u8; DRAW_BYTES] = [0x00; DRAW_BYTES];
 
     for (digit_i, digit_pixels) in font_items.iter().enumerate() {
         let offset = digit_i * 7;
@@ -871,90 +1372,53 @@ fn show_font(serialdev: &str, font_items: &[Vec<u8>]) {
         }
     }
 
-    simple_cmd(serialdev, Command::DisplayBwImage, &vals);
+    check(module.draw_bw(&vals));
 }
 
 /// Render a list of up to five symbols
 /// Can use letters/numbers or symbol names, like 'sun', ':)'
-fn show_symbols(serialdev: &str, symbols: &Vec<String>) {
+fn show_symbols(module: &mut InputModule, symbols: &Vec<String>) {
     println!("Symbols: {symbols:?}");
     let font_items: Vec<Vec<u8>> = symbols.iter().map(|x| convert_symbol(x)).collect();
-    show_font(serialdev, &font_items);
+    show_font(module, &font_items);
 }
 
-fn display_on_cmd(serialdev: &str, arg: Option<bool>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn display_on_cmd(module: &mut InputModule, arg: Option<bool>, report: &mut Report) {
     if let Some(display_on) = arg {
-        simple_cmd_port(&mut port, Command::DisplayOn, &[display_on as u8]);
+        check(module.set_display_on(display_on));
     } else {
-        simple_cmd_port(&mut port, Command::DisplayOn, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let on = response[0] == 1;
-        println!("Currently on: {on}");
+        let on = query(module.display_on());
+        report.value("display_on", on, format!("Currently on: {on}"));
     }
 }
 
-fn invert_screen_cmd(serialdev: &str, arg: Option<bool>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn invert_screen_cmd(module: &mut InputModule, arg: Option<bool>, report: &mut Report) {
     if let Some(invert_on) = arg {
-        simple_cmd_port(&mut port, Command::InvertScreen, &[invert_on as u8]);
+        check(module.set_screen_inverted(invert_on));
     } else {
-        simple_cmd_port(&mut port, Command::InvertScreen, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let inverted = response[0] == 1;
-        println!("Currently inverted: {inverted}");
+        let inverted = query(module.screen_inverted());
+        report.value(
+            "screen_inverted",
+            inverted,
+            format!("Currently inverted: {inverted}"),
+        );
     }
 }
 
-fn screensaver_cmd(serialdev: &str, arg: Option<bool>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn screensaver_cmd(module: &mut InputModule, arg: Option<bool>, report: &mut Report) {
     if let Some(display_on) = arg {
-        simple_cmd_port(&mut port, Command::ScreenSaver, &[display_on as u8]);
+        check(module.set_screensaver(display_on));
     } else {
-        simple_cmd_port(&mut port, Command::ScreenSaver, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let on = response[0] == 1;
-        println!("Currently on: {on}");
+        let on = query(module.screensaver());
+        report.value("screensaver", on, format!("Currently on: {on}"));
     }
 }
 
-fn fps_cmd(serialdev: &str, arg: Option<Fps>) {
+fn fps_cmd(module: &mut InputModule, arg: Option<Fps>, report: &mut Report) {
     const HIGH_FPS_MASK: u8 = 0b00010000;
     const LOW_FPS_MASK: u8 = 0b00000111;
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
 
-    simple_cmd_port(&mut port, Command::Fps, &[]);
-    let mut response: Vec<u8> = vec![0; 32];
-    port.read_exact(response.as_mut_slice())
-        .expect("Found no data!");
-    let current_fps = response[0];
+    let current_fps = query(module.fps());
 
     if let Some(fps) = arg {
         let power_mode = match fps {
@@ -971,16 +1435,11 @@ fn fps_cmd(serialdev: &str, arg: Option<Fps>) {
             Fps::Sixteen => current_fps & !HIGH_FPS_MASK,
             Fps::ThirtyTwo => (current_fps & !HIGH_FPS_MASK) | 0b00010000,
         };
-        set_power_mode(&mut port, power_mode);
-        simple_cmd_port(&mut port, Command::Fps, &[fps_bits]);
+        set_power_mode(module, power_mode);
+        check(module.set_fps(fps_bits));
     } else {
-        simple_cmd_port(&mut port, Command::PowerMode, &[]);
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-        let high = response[0] == 1;
-
-        let fps = if high {
+        let mode = query(module.power_mode());
+        let fps = if mode == DisplayMode::Hpm {
             if current_fps & HIGH_FPS_MASK == 0 {
                 16.0
             } else {
@@ -997,46 +1456,34 @@ fn fps_cmd(serialdev: &str, arg: Option<Fps>) {
             }
         };
 
-        println!("Current FPS: {fps}");
+        report.value("fps", fps, format!("Current FPS: {fps}"));
     }
 }
 
-fn power_mode_cmd(serialdev: &str, arg: Option<PowerMode>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn power_mode_cmd(module: &mut InputModule, arg: Option<PowerMode>, report: &mut Report) {
     if let Some(mode) = arg {
-        set_power_mode(&mut port, mode);
+        set_power_mode(module, mode);
     } else {
-        simple_cmd_port(&mut port, Command::PowerMode, &[]);
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-        let high = response[0] == 1;
-
-        if high {
-            println!("Current Power Mode: High");
-        } else {
-            println!("Current Power Mode: Low");
-        }
+        let mode = match query(module.power_mode()) {
+            DisplayMode::Hpm => "High",
+            DisplayMode::Lpm => "Low",
+        };
+        report.value(
+            "power_mode",
+            mode.to_lowercase(),
+            format!("Current Power Mode: {mode}"),
+        );
     }
 }
 
-fn set_power_mode(port: &mut Box<dyn SerialPort>, mode: PowerMode) {
+fn set_power_mode(module: &mut InputModule, mode: PowerMode) {
     match mode {
-        PowerMode::Low => simple_cmd_port(port, Command::PowerMode, &[0]),
-        PowerMode::High => simple_cmd_port(port, Command::PowerMode, &[1]),
+        PowerMode::Low => check(module.set_power_mode(DisplayMode::Lpm)),
+        PowerMode::High => check(module.set_power_mode(DisplayMode::Hpm)),
     }
 }
 
-fn animation_fps_cmd(serialdev: &str, arg: Option<u16>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn animation_fps_cmd(module: &mut InputModule, arg: Option<u16>, report: &mut Report) {
     if let Some(fps) = arg {
         const MS: u16 = 1000;
         if fps < MS {
@@ -1044,70 +1491,46 @@ fn animation_fps_cmd(serialdev: &str, arg: Option<u16>) {
             println!("Unable to set FPS over 1000");
             return;
         }
-        let period = (MS / fps).to_le_bytes();
-        simple_cmd_port(&mut port, Command::AnimationPeriod, &[period[0], period[1]]);
+        check(module.set_animation_period(MS / fps));
     } else {
-        simple_cmd_port(&mut port, Command::AnimationPeriod, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let period = u16::from_le_bytes([response[0], response[1]]);
-        println!("Animation Frequency: {}ms / {}Hz", period, 1_000 / period);
+        let period = query(module.animation_period());
+        report.value(
+            "animation_period_ms",
+            period,
+            format!("Animation Frequency: {}ms / {}Hz", period, 1_000 / period),
+        );
     }
 }
 
-fn pwm_freq_cmd(serialdev: &str, arg: Option<u16>) {
-    let mut port = serialport::new(serialdev, 115_200)
-        .timeout(SERIAL_TIMEOUT)
-        .open()
-        .expect("Failed to open port");
-
+fn pwm_freq_cmd(module: &mut InputModule, arg: Option<u16>, report: &mut Report) {
     if let Some(freq) = arg {
-        let hz = match freq {
-            29000 => 0,
-            3600 => 1,
-            1800 => 2,
-            900 => 3,
-            _ => panic!("Invalid frequency"),
-        };
-        simple_cmd_port(&mut port, Command::PwmFreq, &[hz]);
+        let freq = PwmFreqArg::from_hz(freq).expect("Invalid frequency");
+        check(module.set_pwm_freq(freq));
     } else {
-        simple_cmd_port(&mut port, Command::PwmFreq, &[]);
-
-        let mut response: Vec<u8> = vec![0; 32];
-        port.read_exact(response.as_mut_slice())
-            .expect("Found no data!");
-
-        let hz = match response[0] {
-            0 => 29000,
-            1 => 3600,
-            2 => 1800,
-            3 => 900,
-            _ => panic!("Invalid frequency"),
-        };
-        println!("Animation Frequency: {}Hz", hz);
+        let freq = query(module.pwm_freq());
+        report.value(
+            "pwm_freq_hz",
+            freq.hz(),
+            format!("Animation Frequency: {}Hz", freq.hz()),
+        );
     }
 }
 
-fn set_color_cmd(serialdev: &str, color: Color) {
-    let args = match color {
-        Color::White => &[0xFF, 0xFF, 0xFF],
-        Color::Black => &[0x00, 0x00, 0x00],
-        Color::Red => &[0xFF, 0x00, 0x00],
-        Color::Green => &[0x00, 0xFF, 0x00],
-        Color::Blue => &[0x00, 0x00, 0xFF],
-        Color::Yellow => &[0xFF, 0xFF, 0x00],
-        Color::Cyan => &[0x00, 0xFF, 0xFF],
-        Color::Purple => &[0xFF, 0x00, 0xFF],
+fn set_color_cmd(module: &mut InputModule, color: Color) {
+    let rgb = match color {
+        Color::White => [0xFF, 0xFF, 0xFF],
+        Color::Black => [0x00, 0x00, 0x00],
+        Color::Red => [0xFF, 0x00, 0x00],
+        Color::Green => [0x00, 0xFF, 0x00],
+        Color::Blue => [0x00, 0x00, 0xFF],
+        Color::Yellow => [0xFF, 0xFF, 0x00],
+        Color::Cyan => [0x00, 0xFF, 0xFF],
+        Color::Purple => [0xFF, 0x00, 0xFF],
     };
-    simple_cmd(serialdev, Command::SetColor, args);
+    check(module.set_color(rgb));
 }
 
-fn gif_cmd(serialdev: &str, image_path: &str) {
-    let mut serialport = open_serialport(serialdev);
-
+fn gif_cmd(module: &mut InputModule, image_path: &str) {
     loop {
         let img = std::fs::File::open(image_path).unwrap();
         let gif = GifDecoder::new(img).unwrap();
@@ -1120,7 +1543,7 @@ fn gif_cmd(serialdev: &str, image_path: &str) {
             let frame_img = DynamicImage::from(frame_img);
             let frame_img = frame_img.resize(300, 400, image::imageops::FilterType::Gaussian);
             let frame_img = frame_img.into_luma8();
-            display_img(&mut serialport, &frame_img);
+            display_img(module, &frame_img);
             // Not delaying any further. Current transmission delay is big enough
             //thread::sleep(delay.into());
         }
@@ -1131,21 +1554,20 @@ fn gif_cmd(serialdev: &str, image_path: &str) {
 /// Confirmed working with PNG and GIF.
 /// Must be 300x400 in size.
 /// Sends one 400px column in a single commands and a flush at the end
-fn generic_img_cmd(serialdev: &str, image_path: &str) {
-    let mut serialport = open_serialport(serialdev);
+fn generic_img_cmd(module: &mut InputModule, image_path: &str) {
     let img = ImageReader::open(image_path)
         .unwrap()
         .decode()
         .unwrap()
         .to_luma8();
-    display_img(&mut serialport, &img);
+    display_img(module, &img);
 }
 
-fn b1display_bw_image_cmd(serialdev: &str, image_path: &str) {
-    generic_img_cmd(serialdev, image_path);
+fn b1display_bw_image_cmd(module: &mut InputModule, image_path: &str) {
+    generic_img_cmd(module, image_path);
 }
 
-fn display_img(serialport: &mut Box<dyn SerialPort>, img: &ImageBuffer<Luma<u8>, Vec<u8>>) {
+fn display_img(module: &mut InputModule, img: &ImageBuffer<Luma<u8>, Vec<u8>>) {
     let width = img.width();
     let height = img.height();
     assert!(width == 300);
@@ -1165,10 +1587,7 @@ fn display_img(serialport: &mut Box<dyn SerialPort>, img: &ImageBuffer<Luma<u8>,
     let threshold = darkest + (bright_diff / 10) * 9;
 
     for x in 0..300 {
-        let mut vals: [u8; 2 + 50] = [0; 2 + 50];
-        let column = (x as u16).to_le_bytes();
-        vals[0] = column[0];
-        vals[1] = column[1];
+        let mut vals: [u8; PIXEL_COLUMN_BYTES] = [0; PIXEL_COLUMN_BYTES];
 
         let mut byte: u8 = 0;
         for y in 0..400usize {
@@ -1184,32 +1603,28 @@ fn display_img(serialport: &mut Box<dyn SerialPort>, img: &ImageBuffer<Luma<u8>,
                 byte |= 1 << bit;
             }
             if bit == 7 {
-                vals[2 + y / 8] = byte;
+                vals[y / 8] = byte;
             }
         }
 
-        simple_open_cmd(serialport, Command::SetPixelColumn, &vals);
+        check(module.set_pixel_column(x as usize, &vals));
     }
 
-    simple_open_cmd(serialport, Command::FlushFramebuffer, &[]);
+    check(module.flush_framebuffer());
 }
 
-fn b1_display_color(serialdev: &str, black: bool) {
-    let mut serialport = open_serialport(serialdev);
+fn b1_display_color(module: &mut InputModule, black: bool) {
     for x in 0..300 {
         let byte = if black { 0xFF } else { 0x00 };
-        let mut vals: [u8; 2 + 50] = [byte; 2 + 50];
-        let column = (x as u16).to_le_bytes();
-        vals[0] = column[0];
-        vals[1] = column[1];
-        simple_open_cmd(&mut serialport, Command::SetPixelColumn, &vals);
+        let vals: [u8; PIXEL_COLUMN_BYTES] = [byte; PIXEL_COLUMN_BYTES];
+        check(module.set_pixel_column(x, &vals));
     }
-    simple_open_cmd(&mut serialport, Command::FlushFramebuffer, &[]);
+    check(module.flush_framebuffer());
 }
 
-fn b1_display_pattern(serialdev: &str, pattern: B1Pattern) {
+fn b1_display_pattern(module: &mut InputModule, pattern: B1Pattern) {
     match pattern {
-        B1Pattern::Black => b1_display_color(serialdev, true),
-        B1Pattern::White => b1_display_color(serialdev, false),
+        B1Pattern::Black => b1_display_color(module, true),
+        B1Pattern::White => b1_display_color(module, false),
     }
 }
@@ -1,5 +1,7 @@
-use std::str::FromStr;
 use clap::Parser;
+use std::str::FromStr;
+
+use inputmodule_protocol::{AddonAnimationVals, GammaCurve, LifeRule, PatternVals};
 
 #[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
 #[repr(u8)]
@@ -14,6 +16,20 @@ pub enum Pattern {
     LotusTopDown = 7,
     //AllBrightnesses
 }
+impl From<Pattern> for PatternVals {
+    fn from(pattern: Pattern) -> Self {
+        match pattern {
+            Pattern::Percentage => PatternVals::Percentage,
+            Pattern::Gradient => PatternVals::Gradient,
+            Pattern::DoubleGradient => PatternVals::DoubleGradient,
+            Pattern::LotusSideways => PatternVals::DisplayLotus,
+            Pattern::Zigzag => PatternVals::ZigZag,
+            Pattern::AllOn => PatternVals::FullBrightness,
+            Pattern::Panic => PatternVals::DisplayPanic,
+            Pattern::LotusTopDown => PatternVals::DisplayLotus2,
+        }
+    }
+}
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
@@ -25,13 +41,53 @@ pub enum Game {
     GameOfLife = 3,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 #[repr(u8)]
 pub enum AddonAnimation {
     Spiral = 0x00,
     Splashes = 0x01,
     Helix = 0x02,
 }
+impl From<AddonAnimation> for AddonAnimationVals {
+    fn from(animation: AddonAnimation) -> Self {
+        match animation {
+            AddonAnimation::Spiral => AddonAnimationVals::Spiral,
+            AddonAnimation::Splashes => AddonAnimationVals::Splashes,
+            AddonAnimation::Helix => AddonAnimationVals::Helix,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum)]
+pub enum PongPaddle {
+    Upper,
+    Lower,
+}
+impl From<PongPaddle> for inputmodule_protocol::PongPaddle {
+    fn from(paddle: PongPaddle) -> Self {
+        match paddle {
+            PongPaddle::Upper => Self::Upper,
+            PongPaddle::Lower => Self::Lower,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum)]
+pub enum PongDifficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+impl From<PongDifficulty> for inputmodule_protocol::PongDifficulty {
+    fn from(difficulty: PongDifficulty) -> Self {
+        match difficulty {
+            PongDifficulty::Easy => Self::Easy,
+            PongDifficulty::Medium => Self::Medium,
+            PongDifficulty::Hard => Self::Hard,
+        }
+    }
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum)]
 pub enum GameOfLifeStartParam {
@@ -43,6 +99,20 @@ pub enum GameOfLifeStartParam {
     Glider = 0x05,
     BeaconToadBlinker = 0x06,
 }
+impl From<GameOfLifeStartParam> for inputmodule_protocol::GameOfLifeStartParam {
+    fn from(param: GameOfLifeStartParam) -> Self {
+        use inputmodule_protocol::GameOfLifeStartParam as P;
+        match param {
+            GameOfLifeStartParam::CurrentMatrix => P::CurrentMatrix,
+            GameOfLifeStartParam::Pattern1 => P::Pattern1,
+            GameOfLifeStartParam::Blinker => P::Blinker,
+            GameOfLifeStartParam::Toad => P::Toad,
+            GameOfLifeStartParam::Beacon => P::Beacon,
+            GameOfLifeStartParam::Glider => P::Glider,
+            GameOfLifeStartParam::BeaconToadBlinker => P::BeaconToadBlinker,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct KeypressArg {
@@ -54,17 +124,114 @@ impl FromStr for KeypressArg {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut parts = s.split(',');
-        let keycode = parts.next().ok_or("missing key")?.parse().map_err(|_| "bad key")?;
-        let pressed = parts.next().ok_or("missing bool")?.parse().map_err(|_| "bad bool")?;
+        let keycode = parts
+            .next()
+            .ok_or("missing key")?
+            .parse()
+            .map_err(|_| "bad key")?;
+        let pressed = parts
+            .next()
+            .ok_or("missing bool")?
+            .parse()
+            .map_err(|_| "bad bool")?;
         Ok(KeypressArg { keycode, pressed })
     }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum)]
+pub enum LifeEdges {
+    Wrap,
+    Bounded,
+}
+impl From<LifeEdges> for inputmodule_protocol::LifeEdges {
+    fn from(edges: LifeEdges) -> Self {
+        match edges {
+            LifeEdges::Wrap => Self::Wrap,
+            LifeEdges::Bounded => Self::Bounded,
+        }
+    }
+}
+
+/// Game of Life rule, like `B36/S23`, or the name of a well-known one
+#[derive(Debug, Clone, Copy)]
+pub struct LifeRuleArg(pub LifeRule);
+
+impl FromStr for LifeRuleArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LifeRule::parse(s)
+            .map(LifeRuleArg)
+            .ok_or_else(|| format!("bad rule '{s}', expected something like B3/S23"))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Side {
     Left,
     Right,
 }
+impl From<Side> for inputmodule_protocol::Side {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Left => inputmodule_protocol::Side::Left,
+            Side::Right => inputmodule_protocol::Side::Right,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Gamma {
+    Linear,
+    Gamma22,
+    Cie,
+    Custom,
+}
+impl From<Gamma> for GammaCurve {
+    fn from(gamma: Gamma) -> Self {
+        match gamma {
+            Gamma::Linear => GammaCurve::Linear,
+            Gamma::Gamma22 => GammaCurve::Gamma22,
+            Gamma::Cie => GammaCurve::Cie,
+            Gamma::Custom => GammaCurve::Custom,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Orientation {
+    Normal,
+    Rotate180,
+    MirrorX,
+    MirrorY,
+}
+impl From<Orientation> for inputmodule_protocol::Orientation {
+    fn from(orientation: Orientation) -> Self {
+        match orientation {
+            Orientation::Normal => inputmodule_protocol::Orientation::Normal,
+            Orientation::Rotate180 => inputmodule_protocol::Orientation::Rotate180,
+            Orientation::MirrorX => inputmodule_protocol::Orientation::MirrorX,
+            Orientation::MirrorY => inputmodule_protocol::Orientation::MirrorY,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, clap::ValueEnum)]
+pub enum ScrollDirection {
+    Down,
+    Up,
+}
+impl From<ScrollDirection> for inputmodule_protocol::ScrollDirection {
+    fn from(direction: ScrollDirection) -> Self {
+        match direction {
+            ScrollDirection::Down => inputmodule_protocol::ScrollDirection::Down,
+            ScrollDirection::Up => inputmodule_protocol::ScrollDirection::Up,
+        }
+    }
+}
 
 /// LED Matrix
 #[derive(Parser, Debug)]
@@ -80,6 +247,7 @@ pub struct LedMatrixSubcommand {
     pub set_addon_animation: Option<AddonAnimation>,
     #[clap(long)]
     pub stop_addon_animation: bool,
+    /// Sets which side of the keyboard the matrix is installed on
     #[clap(long)]
     pub set_side: Option<Side>,
 
@@ -128,6 +296,14 @@ pub struct LedMatrixSubcommand {
     #[arg(long)]
     pub image_gray: Option<String>,
 
+    /// Upload an animated GIF (9x34px), which the module keeps playing by itself
+    #[arg(long)]
+    pub upload_gif: Option<String>,
+
+    /// Start/stop the uploaded animation or check whether it's playing, if no value provided
+    #[arg(long)]
+    pub custom_animation: Option<Option<bool>>,
+
     /// Random EQ
     #[arg(long)]
     pub random_eq: bool,
@@ -153,6 +329,27 @@ pub struct LedMatrixSubcommand {
     #[arg(long, num_args(0..6))]
     pub symbols: Option<Vec<String>>,
 
+    /// Scroll text across the display, rendered by the module. Check whether it's still scrolling, if no value provided
+    #[arg(long)]
+    pub scroll_text: Option<Option<String>>,
+
+    /// Scrolling speed in rows per second
+    #[arg(long, default_value_t = 8)]
+    pub scroll_speed: u8,
+
+    /// Direction the text scrolls in
+    #[arg(long, default_value = "down")]
+    #[clap(value_enum)]
+    pub scroll_direction: ScrollDirection,
+
+    /// How often to scroll the text, 0 to scroll forever
+    #[arg(long, default_value_t = 1)]
+    pub scroll_loops: u8,
+
+    /// Wait until the text has finished scrolling, by asking the module repeatedly
+    #[arg(long)]
+    pub scroll_wait: bool,
+
     /// Start a game
     #[arg(long)]
     #[clap(value_enum)]
@@ -163,11 +360,54 @@ pub struct LedMatrixSubcommand {
     #[clap(value_enum)]
     pub game_param: Option<GameOfLifeStartParam>,
 
+    /// Start Game of Life from a black/white image (9x34px), instead of a --game-param
+    #[arg(long)]
+    pub life_seed: Option<String>,
+
+    /// What's beyond the edges in Game of Life
+    #[arg(long, default_value = "wrap")]
+    #[clap(value_enum)]
+    pub life_edges: LifeEdges,
+
+    /// Game of Life rule, like B36/S23, or one of: conway, highlife, seeds, day-and-night
+    #[arg(long, default_value = "conway")]
+    pub life_rule: LifeRuleArg,
+
+    /// Show young Game of Life cells brighter than old ones
+    #[arg(long)]
+    pub life_age_brightness: bool,
+
+    /// Start over with random cells, when Game of Life stops changing or repeats
+    #[arg(long)]
+    pub life_reseed: bool,
+
+    /// Let the snake come back on the other side, instead of dying at the edges
+    #[arg(long)]
+    pub snake_wrap: bool,
+
+    /// Make the snake faster the longer it gets
+    #[arg(long)]
+    pub snake_speed_up: bool,
+
+    /// Let the module play this paddle in Pong, so that one person can play with Left/Right
+    #[arg(long)]
+    #[clap(value_enum)]
+    pub pong_cpu: Option<PongPaddle>,
+
+    /// How well the module plays Pong
+    #[arg(long, default_value = "medium")]
+    #[clap(value_enum)]
+    pub pong_difficulty: PongDifficulty,
+
     /// Stop the currently running game
     #[arg(long)]
     #[clap(value_enum)]
     pub stop_game: bool,
 
+    /// Show the status of the currently running game
+    #[arg(long)]
+    pub game_status: bool,
+
     /// Set/get animation FPS
     #[arg(long)]
     pub animation_fps: Option<Option<u16>>,
@@ -181,6 +421,32 @@ pub struct LedMatrixSubcommand {
     #[arg(long)]
     pub debug_mode: Option<Option<bool>>,
 
+    /// Set brightness curve or get current curve, if no value provided
+    #[arg(long)]
+    #[clap(value_enum)]
+    pub gamma: Option<Option<Gamma>>,
+
+    /// Upload a custom brightness curve. File with 256 brightness values, separated by whitespace
+    #[arg(long)]
+    pub gamma_lut: Option<String>,
+
+    /// Set display orientation or get current orientation, if no value provided
+    #[arg(long)]
+    #[clap(value_enum)]
+    pub orientation: Option<Option<Orientation>>,
+
+    /// Save the current settings, to restore them after a reboot
+    #[arg(long)]
+    pub save_settings: bool,
+
+    /// Apply the saved settings
+    #[arg(long)]
+    pub load_settings: bool,
+
+    /// Erase the saved settings and go back to the defaults
+    #[arg(long)]
+    pub reset_settings: bool,
+
     /// Crash the firmware (TESTING ONLY!)
     #[arg(long)]
     pub panic: bool,
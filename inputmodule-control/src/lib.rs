@@ -0,0 +1,24 @@
+//! Control the Framework 16 input modules over their serial port
+//!
+//! [`InputModule`] talks to a single module, with a method for every command.
+//! The `inputmodule-control` commandline tool is built on top of it.
+pub mod config;
+#[cfg(unix)]
+mod daemon;
+mod device;
+mod error;
+mod select;
+mod transport;
+
+#[cfg(unix)]
+pub use daemon::{socket_path, Daemon};
+pub use device::InputModule;
+pub use error::{Error, Result};
+/// Commands and responses, as they're sent over the serial port
+pub use inputmodule_protocol as protocol;
+pub use select::{find_modules, modules, ModuleInfo, ModuleKind, Selector};
+
+pub const FRAMEWORK_VID: u16 = 0x32AC;
+pub const LED_MATRIX_PID: u16 = 0x0020;
+pub const B1_LCD_PID: u16 = 0x0021;
+pub const C1_MINIMAL_PID: u16 = 0x0022;
@@ -1,32 +1,75 @@
 #![allow(clippy::needless_range_loop)]
 #![allow(clippy::single_match)]
+#![allow(clippy::large_enum_variant)]
 mod b1display;
 mod c1minimal;
 mod font;
 mod inputmodule;
 mod ledmatrix;
+mod profile;
+mod report;
 
-use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
 use inputmodule::find_serialdevs;
+use inputmodule_control::ModuleKind;
 
 use crate::b1display::B1DisplaySubcommand;
 use crate::c1minimal::C1MinimalSubcommand;
-use crate::inputmodule::{serial_commands, B1_LCD_PID, LED_MATRIX_PID};
-use crate::ledmatrix::LedMatrixSubcommand;
+use crate::inputmodule::serial_commands;
+use crate::ledmatrix::{LedMatrixSubcommand, Side};
+use crate::profile::ApplySubcommand;
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     LedMatrix(LedMatrixSubcommand),
     B1Display(B1DisplaySubcommand),
     C1Minimal(C1MinimalSubcommand),
+    Apply(ApplySubcommand),
+    /// Apply the profiles again whenever a module is connected or the system resumes
+    ///
+    /// Keeps running until stopped. Only the profiles are applied again. Commands
+    /// that keep sending content from the host, like `led-matrix --clock`, have to
+    /// be restarted separately.
+    Watch(ApplySubcommand),
+    /// Keep the modules open and let other clients use them through a socket
+    ///
+    /// While it's running, other commands go through it.
+    #[cfg(unix)]
+    Daemon,
 }
 
 impl Commands {
-    pub fn to_pid(&self) -> u16 {
+    /// Kind of module that the command is for, if it's just for one
+    pub fn kind(&self) -> Option<ModuleKind> {
         match self {
-            Self::LedMatrix(_) => LED_MATRIX_PID,
-            Self::B1Display(_) => B1_LCD_PID,
-            Self::C1Minimal(_) => 0x22,
+            Self::LedMatrix(_) => Some(ModuleKind::LedMatrix),
+            Self::B1Display(_) => Some(ModuleKind::B1Display),
+            Self::C1Minimal(_) => Some(ModuleKind::C1Minimal),
+            Self::Apply(_) | Self::Watch(_) => None,
+            #[cfg(unix)]
+            Self::Daemon => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Module {
+    #[value(name = "ledmatrix", alias = "led-matrix")]
+    LedMatrix,
+    #[value(name = "b1display", alias = "b1-display")]
+    B1Display,
+    #[value(name = "c1minimal", alias = "c1-minimal")]
+    C1Minimal,
+}
+
+impl From<Module> for ModuleKind {
+    fn from(module: Module) -> Self {
+        match module {
+            Module::LedMatrix => ModuleKind::LedMatrix,
+            Module::B1Display => ModuleKind::B1Display,
+            Module::C1Minimal => ModuleKind::C1Minimal,
         }
     }
 }
@@ -46,10 +89,43 @@ pub struct ClapCli {
     #[arg(short, long)]
     verbose: bool,
 
+    /// Print queried values and the device list as JSON
+    #[arg(long)]
+    json: bool,
+
     /// Serial device, like /dev/ttyACM0 or COM0
+    ///
+    /// Can also be any other path, like the pseudo-terminal of fl16-simulator.
     #[arg(long)]
     pub serial_dev: Option<String>,
 
+    /// Only the module with this USB serial number
+    #[arg(long, conflicts_with = "serial_dev")]
+    serial_number: Option<String>,
+
+    /// Only modules of this kind
+    #[arg(long, conflicts_with = "serial_dev")]
+    module: Option<Module>,
+
+    /// Only LED matrices that were set to this side, with `led-matrix --set-side`
+    #[arg(long, conflicts_with = "serial_dev")]
+    side: Option<Side>,
+
+    /// Only the module at this USB path, like 3-1.2 (Linux only)
+    #[arg(long, conflicts_with = "serial_dev")]
+    usb_path: Option<String>,
+
+    /// Only the n-th of the modules that match, counting from 0
+    #[arg(long, conflicts_with = "serial_dev")]
+    index: Option<usize>,
+
+    /// Config file with the profiles for apply
+    ///
+    /// By default config.toml in the inputmodule-control directory of the user's
+    /// config directory, like ~/.config/inputmodule-control/config.toml
+    #[arg(long)]
+    config: Option<PathBuf>,
+
     /// Retry connecting to the device until it works
     #[arg(long)]
     wait_for_device: bool,
@@ -59,7 +135,10 @@ fn main() {
     let args: Vec<String> = std::env::args().collect();
     let args = ClapCli::parse_from(args);
 
-    match args.command {
+    match &args.command {
+        #[cfg(unix)]
+        Some(Commands::Daemon) => inputmodule::daemon_cmd(&args),
+        Some(Commands::Watch(watch_args)) => inputmodule::watch_cmd(&args, watch_args),
         Some(_) => serial_commands(&args),
         None => {
             if args.list {
@@ -0,0 +1,44 @@
+//! Profiles of settings in the config file, that `apply` pushes to the modules
+use clap::Parser;
+use inputmodule_control::config::Content;
+use serde::Deserialize;
+
+use crate::b1display::{Fps, PowerMode};
+use crate::c1minimal::Color;
+use crate::ledmatrix::{AddonAnimation, Gamma, Orientation};
+
+/// Config file with the profiles that the commands have
+pub type Config = inputmodule_control::config::Config<Profile>;
+
+/// Apply a profile from the config file
+#[derive(Parser, Debug)]
+pub struct ApplySubcommand {
+    /// Apply this profile to all selected modules, instead of the configured ones
+    #[arg(long)]
+    pub profile: Option<String>,
+}
+
+/// Settings and content for a module
+///
+/// Named like the arguments of the module commands. Settings that a module
+/// doesn't have are skipped.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    // LED Matrix
+    pub brightness: Option<u8>,
+    pub pwm_freq: Option<u16>,
+    pub gamma: Option<Gamma>,
+    pub orientation: Option<Orientation>,
+    pub addon_animation: Option<AddonAnimation>,
+    // LED Matrix and B1 Display
+    pub animation_fps: Option<u16>,
+    // B1 Display
+    pub fps: Option<Fps>,
+    pub power_mode: Option<PowerMode>,
+    pub screen_saver: Option<bool>,
+    // C1 Minimal
+    pub color: Option<Color>,
+
+    pub content: Option<Content>,
+}
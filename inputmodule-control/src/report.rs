@@ -0,0 +1,93 @@
+//! Results of queries, shown as text or collected as JSON
+use inputmodule_control::protocol::{Capabilities, Version};
+use inputmodule_control::ModuleInfo;
+use serde_json::{json, Map, Value};
+
+/// What was queried from a single module
+pub struct Report {
+    json: bool,
+    values: Map<String, Value>,
+}
+
+impl Report {
+    pub fn new(json: bool) -> Self {
+        Self {
+            json,
+            values: Map::new(),
+        }
+    }
+
+    /// Show a queried value
+    ///
+    /// The text is printed right away. JSON is printed once all modules are done,
+    /// so that it's a single document.
+    pub fn value(&mut self, key: &str, value: impl Into<Value>, text: String) {
+        if self.json {
+            self.values.insert(key.to_string(), value.into());
+        } else {
+            println!("{text}");
+        }
+    }
+
+    /// The queried values, next to what identifies the module
+    pub fn into_device(
+        self,
+        port: &str,
+        info: Option<&ModuleInfo>,
+        version: Option<Version>,
+    ) -> Value {
+        let mut device = device_json(port, info);
+        device.insert(
+            "version".to_string(),
+            version.map_or(Value::Null, version_json),
+        );
+        device.extend(self.values);
+        Value::Object(device)
+    }
+}
+
+/// Identifies the serial port and module, with fields that are known before opening it
+pub fn device_json(port: &str, info: Option<&ModuleInfo>) -> Map<String, Value> {
+    let mut device = Map::new();
+    device.insert("port".to_string(), port.into());
+    device.insert(
+        "serial_number".to_string(),
+        info.and_then(|info| info.serial_number.clone()).into(),
+    );
+    device.insert("pid".to_string(), info.map(|info| info.pid).into());
+    device.insert(
+        "usb_path".to_string(),
+        info.and_then(|info| info.usb_path.clone()).into(),
+    );
+    device
+}
+
+pub fn version_json(version: Version) -> Value {
+    json!({
+        "major": version.major,
+        "minor": version.minor,
+        "patch": version.patch,
+        "pre_release": version.pre_release,
+    })
+}
+
+pub fn capabilities_json(capabilities: &Capabilities) -> Value {
+    fn names<T: std::fmt::Debug>(items: impl Iterator<Item = T>) -> Vec<String> {
+        items.map(|x| format!("{:?}", x)).collect()
+    }
+
+    json!({
+        "module": format!("{:?}", capabilities.module),
+        "width": capabilities.width,
+        "height": capabilities.height,
+        "protocol": capabilities.protocol_version,
+        "commands": names(capabilities.commands()),
+        "games": names(capabilities.games()),
+        "patterns": names(capabilities.patterns()),
+        "animations": names(capabilities.addon_animations()),
+    })
+}
+
+pub fn print_json(value: &Value) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use serialport::{SerialPortInfo, SerialPortType};
+
+use crate::error::{Error, Result};
+use crate::protocol::{ModuleType, Side};
+use crate::{InputModule, B1_LCD_PID, C1_MINIMAL_PID, FRAMEWORK_VID, LED_MATRIX_PID};
+
+/// Product ID that is accepted as well, though it doesn't tell the kind of module
+const OTHER_PID: u16 = 0xFF;
+
+/// Kind of input module, as told by its USB product ID
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModuleKind {
+    LedMatrix,
+    B1Display,
+    C1Minimal,
+}
+
+impl ModuleKind {
+    pub fn pid(self) -> u16 {
+        match self {
+            ModuleKind::LedMatrix => LED_MATRIX_PID,
+            ModuleKind::B1Display => B1_LCD_PID,
+            ModuleKind::C1Minimal => C1_MINIMAL_PID,
+        }
+    }
+
+    pub fn from_pid(pid: u16) -> Option<Self> {
+        match pid {
+            LED_MATRIX_PID => Some(ModuleKind::LedMatrix),
+            B1_LCD_PID => Some(ModuleKind::B1Display),
+            C1_MINIMAL_PID => Some(ModuleKind::C1Minimal),
+            _ => None,
+        }
+    }
+}
+
+impl From<ModuleType> for ModuleKind {
+    fn from(module: ModuleType) -> Self {
+        match module {
+            ModuleType::LedMatrix => ModuleKind::LedMatrix,
+            ModuleType::B1Display => ModuleKind::B1Display,
+            ModuleType::C1Minimal => ModuleKind::C1Minimal,
+        }
+    }
+}
+
+/// Serial port of a module that's connected over USB
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleInfo {
+    /// Name of the serial port, to open it with [`InputModule::open`]
+    #[serde(rename = "port")]
+    pub port_name: String,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+    /// Where the module is connected, like `3-1.2`. Only known on Linux.
+    pub usb_path: Option<String>,
+}
+
+impl ModuleInfo {
+    /// Module behind the serial port, if it's one of ours
+    pub fn from_port(port: &SerialPortInfo) -> Option<Self> {
+        let SerialPortType::UsbPort(usbinfo) = &port.port_type else {
+            return None;
+        };
+        // macOS creates a /dev/cu.* and /dev/tty.* device.
+        // The latter can only be used for reading, not writing, so we have to ignore it.
+        #[cfg(target_os = "macos")]
+        if !port.port_name.starts_with("/dev/tty.") {
+            return None;
+        }
+        let known_pid = ModuleKind::from_pid(usbinfo.pid).is_some() || usbinfo.pid == OTHER_PID;
+        if usbinfo.vid != FRAMEWORK_VID || !known_pid {
+            return None;
+        }
+        Some(Self {
+            port_name: port.port_name.clone(),
+            pid: usbinfo.pid,
+            serial_number: usbinfo.serial_number.clone(),
+            usb_path: usb_path(&port.port_name),
+        })
+    }
+
+    pub fn kind(&self) -> Option<ModuleKind> {
+        ModuleKind::from_pid(self.pid)
+    }
+}
+
+/// USB bus and ports that lead to the device, from the kernel's name of the device
+#[cfg(target_os = "linux")]
+fn usb_path(port_name: &str) -> Option<String> {
+    let tty = std::path::Path::new(port_name).file_name()?;
+    // Links to the USB interface, like .../usb3/3-1/3-1.2/3-1.2:1.0
+    let interface = std::path::Path::new("/sys/class/tty")
+        .join(tty)
+        .join("device")
+        .canonicalize()
+        .ok()?;
+    let name = interface.file_name()?.to_str()?;
+    let (device, _) = name.split_once(':')?;
+    Some(device.to_string())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn usb_path(_port_name: &str) -> Option<String> {
+    None
+}
+
+/// Modules among the serial ports, ordered by the name of their port
+pub fn modules(ports: &[SerialPortInfo]) -> Vec<ModuleInfo> {
+    let mut modules: Vec<ModuleInfo> = ports.iter().filter_map(ModuleInfo::from_port).collect();
+    modules.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    modules
+}
+
+/// All modules that are currently connected, ordered by the name of their port
+pub fn find_modules() -> Result<Vec<ModuleInfo>> {
+    let ports = serialport::available_ports().map_err(Error::Open)?;
+    Ok(modules(&ports))
+}
+
+/// Picks some of the connected modules
+///
+/// Every criterion that's set must match. Without any, all modules are selected.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selector {
+    pub serial_number: Option<String>,
+    pub kind: Option<ModuleKind>,
+    /// Side that the module was configured for. Modules that can't tell never match.
+    pub side: Option<Side>,
+    pub usb_path: Option<String>,
+    /// Position among the modules matching the other criteria, starting at 0
+    pub index: Option<usize>,
+}
+
+impl Selector {
+    /// Whether the module matches, without opening it
+    fn matches_info(&self, module: &ModuleInfo) -> bool {
+        self.serial_number
+            .as_ref()
+            .is_none_or(|sn| module.serial_number.as_ref() == Some(sn))
+            && self.kind.is_none_or(|kind| module.kind() == Some(kind))
+            && self
+                .usb_path
+                .as_ref()
+                .is_none_or(|path| module.usb_path.as_ref() == Some(path))
+    }
+
+    /// Whether the module is on the requested side. Has to ask the module.
+    fn matches_side(&self, module: &ModuleInfo) -> bool {
+        let Some(side) = self.side else {
+            return true;
+        };
+        InputModule::open(&module.port_name)
+            .and_then(|mut module| module.side())
+            .is_ok_and(|module_side| module_side == side)
+    }
+
+    /// The modules that match, in the same order
+    pub fn select(&self, modules: Vec<ModuleInfo>) -> Vec<ModuleInfo> {
+        let selected = modules
+            .into_iter()
+            .filter(|module| self.matches_info(module) && self.matches_side(module));
+        match self.index {
+            Some(index) => selected.skip(index).take(1).collect(),
+            None => selected.collect(),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use inputmodule_protocol::{
+    Capabilities, Command, ErrorCode, FrameDecoder, FrameReply, Received, Response, ResponseBuf,
+    MAX_COMMAND_SIZE, MAX_FRAME_SIZE, RESPONSE_SIZE,
+};
+use serialport::SerialPort;
+
+use crate::error::{Error, Result};
+
+/// How long to wait for the module to acknowledge a framed command.
+/// Firmware without framing support never replies, so this is also how long
+/// it takes to detect such firmware.
+const ACK_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long to wait for commands that write to the flash. The module only
+/// acknowledges them once it's done, after erasing up to two sectors.
+const FLASH_ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Serial port of an input module
+///
+/// Sends commands as frames and waits for the module to acknowledge them.
+/// If the firmware doesn't support framing, it falls back to legacy commands.
+pub struct ModulePort {
+    port: Box<dyn SerialPort>,
+    seq: u8,
+    /// Whether the firmware supports framing. Unknown until the first command.
+    framed: Option<bool>,
+    /// Features of the module. Not queried yet if `None`.
+    capabilities: Option<Option<Capabilities>>,
+}
+
+impl ModulePort {
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        Self {
+            port,
+            seq: 0,
+            framed: None,
+            capabilities: None,
+        }
+    }
+
+    /// Features supported by the module
+    ///
+    /// Returns `None` if the firmware is too old to report them.
+    pub fn capabilities(&mut self) -> Result<Option<Capabilities>> {
+        if let Some(capabilities) = self.capabilities {
+            return Ok(capabilities);
+        }
+        // Firmware without framing doesn't know the command and wouldn't reply
+        let capabilities = if self.framed == Some(false) {
+            None
+        } else {
+            match self.framed_command(&Command::GetCapabilities) {
+                Ok(reply) => {
+                    self.framed = Some(true);
+                    Self::handle_reply(&Command::GetCapabilities, reply)
+                        .ok()
+                        .flatten()
+                        .and_then(|response| Command::GetCapabilities.decode_response(&response))
+                        .and_then(|response| match response {
+                            Response::Capabilities(capabilities) => Some(capabilities),
+                            _ => None,
+                        })
+                }
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    self.framed = Some(false);
+                    None
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+        self.capabilities = Some(capabilities);
+        Ok(capabilities)
+    }
+
+    /// Send a command and return the response, if the command has one
+    ///
+    /// Fails if the module doesn't support the command or reported an error.
+    /// Firmware without framing support can't report errors.
+    pub fn command(&mut self, command: &Command) -> Result<Option<ResponseBuf>> {
+        // The module resets without replying. Legacy commands work with every firmware.
+        if matches!(command, Command::BootloaderReset | Command::Panic) {
+            self.legacy_command(command)?;
+            return Ok(None);
+        }
+
+        // Don't even send commands that the module can't handle
+        if let Some(capabilities) = self.capabilities()? {
+            if !capabilities.supports(command) {
+                return Err(Self::rejected(command, ErrorCode::Unsupported));
+            }
+        }
+
+        match self.framed {
+            Some(false) => self.unframed_command(command),
+            Some(true) => {
+                let reply = self.framed_command(command)?;
+                Self::handle_reply(command, reply)
+            }
+            None => match self.framed_command(command) {
+                Ok(reply) => {
+                    self.framed = Some(true);
+                    Self::handle_reply(command, reply)
+                }
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                    // Old firmware ignores frames, send it again the old way
+                    self.framed = Some(false);
+                    self.unframed_command(command)
+                }
+                Err(err) => Err(err.into()),
+            },
+        }
+    }
+
+    fn rejected(command: &Command, code: ErrorCode) -> Error {
+        Error::Command {
+            command: command.id(),
+            code,
+        }
+    }
+
+    fn handle_reply(command: &Command, reply: FrameReply) -> Result<Option<ResponseBuf>> {
+        match reply {
+            FrameReply::Ack(response) => Ok(response),
+            FrameReply::Nak(code) => Err(Self::rejected(command, code)),
+        }
+    }
+
+    /// Send a command to firmware without framing support
+    fn unframed_command(&mut self, command: &Command) -> Result<Option<ResponseBuf>> {
+        // Such firmware is too old to know the commands that need a frame
+        if command.needs_frame() {
+            return Err(Self::rejected(command, ErrorCode::Unsupported));
+        }
+        self.legacy_command(command)
+    }
+
+    fn legacy_command(&mut self, command: &Command) -> Result<Option<ResponseBuf>> {
+        let mut buffer: [u8; MAX_COMMAND_SIZE] = [0; MAX_COMMAND_SIZE];
+        let len = command.encode(&mut buffer);
+        self.port.write_all(&buffer[..len])?;
+
+        if command.has_response() {
+            let mut response: ResponseBuf = [0; RESPONSE_SIZE];
+            self.port.read_exact(&mut response)?;
+            Ok(Some(response))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn framed_command(&mut self, command: &Command) -> io::Result<FrameReply> {
+        self.seq = self.seq.wrapping_add(1);
+        let mut buffer = [0; MAX_FRAME_SIZE];
+        let len = command.encode_frame(self.seq, &mut buffer);
+        self.port.write_all(&buffer[..len])?;
+
+        let mut decoder = FrameDecoder::new();
+        let timeout = if command.writes_flash() {
+            FLASH_ACK_TIMEOUT
+        } else {
+            ACK_TIMEOUT
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut buf = [0; 64];
+            match self.port.read(&mut buf) {
+                Ok(count) => decoder.push(&buf[..count]),
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+                Err(err) => return Err(err),
+            }
+            while let Some(received) = decoder.next_received() {
+                match received {
+                    // Ignore replies to earlier commands
+                    Received::Frame { seq, payload } if seq == self.seq => {
+                        return FrameReply::decode(&payload).ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidData, "Invalid reply")
+                        });
+                    }
+                    Received::BadFrame { .. } => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Corrupted reply",
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+            if Instant::now() > deadline {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+        }
+    }
+}
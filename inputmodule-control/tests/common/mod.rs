@@ -0,0 +1,157 @@
+//! Fake module, shared by the tests
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use inputmodule_control::protocol::*;
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+pub const CAPABILITIES: Capabilities = Capabilities {
+    module: ModuleType::LedMatrix,
+    protocol_version: FRAME_VERSION,
+    width: WIDTH as u16,
+    height: HEIGHT as u16,
+    commands: command_bits(&[
+        CommandVals::Brightness,
+        CommandVals::ScrollText,
+        CommandVals::GetCapabilities,
+    ]),
+    games: 0,
+    patterns: 0,
+    addon_animations: 0,
+};
+
+/// Module behind a serial port, that only knows about its brightness
+#[derive(Default)]
+pub struct FakeModule {
+    decoder: FrameDecoder,
+    brightness: u8,
+    /// Replies that haven't been read yet
+    replies: Vec<u8>,
+}
+
+impl FakeModule {
+    fn handle(&mut self, command: &Command) -> CommandResult {
+        match command {
+            Command::GetCapabilities => Ok(Some(Response::Capabilities(CAPABILITIES).encode())),
+            Command::SetBrightness(brightness) => {
+                self.brightness = *brightness;
+                Ok(None)
+            }
+            Command::GetBrightness => Ok(Some(Response::Brightness(self.brightness).encode())),
+            _ => Err(ErrorCode::Unsupported),
+        }
+    }
+}
+
+impl Write for FakeModule {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.decoder.push(data);
+        while let Some(received) = self.decoder.next_received() {
+            let (reply, command) = received.into_command();
+            let mut buf = [0; MAX_FRAME_SIZE];
+            let len = match command {
+                Ok(command) => {
+                    let result = self.handle(&command);
+                    reply.respond(&result, &mut buf)
+                }
+                Err(code) => reply.nak(code, &mut buf),
+            };
+            self.replies.extend_from_slice(&buf[..len]);
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for FakeModule {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.replies.is_empty() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let count = buf.len().min(self.replies.len());
+        buf[..count].copy_from_slice(&self.replies[..count]);
+        self.replies.drain(..count);
+        Ok(count)
+    }
+}
+
+impl SerialPort for FakeModule {
+    fn name(&self) -> Option<String> {
+        None
+    }
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(115_200)
+    }
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+    fn timeout(&self) -> Duration {
+        Duration::ZERO
+    }
+    fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_data_bits(&mut self, _: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_flow_control(&mut self, _: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_parity(&mut self, _: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_stop_bits(&mut self, _: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn set_timeout(&mut self, _: Duration) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn write_request_to_send(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn write_data_terminal_ready(&mut self, _: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(self.replies.len() as u32)
+    }
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+    fn clear(&self, _: ClearBuffer) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Err(io::Error::from(io::ErrorKind::Unsupported).into())
+    }
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
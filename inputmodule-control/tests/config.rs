@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use inputmodule_control::config::*;
+use inputmodule_control::protocol::Side;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct Profile {
+    brightness: u8,
+}
+
+const CONFIG: &str = r#"
+default-profile = "day"
+
+[profiles.day]
+brightness = 80
+
+[profiles.night]
+brightness = 10
+
+[profiles.left]
+brightness = 50
+
+[[devices]]
+side = "left"
+profile = "left"
+
+[[devices]]
+serial-number = "FRAKDEAM0020110001"
+side = "right"
+profile = "night"
+content = { text = "Good night" }
+
+[[devices]]
+serial-number = "FRAKDEAM0020110002"
+"#;
+
+fn profile_name<P>(config: &Config<P>, device: Option<&Device>) -> Option<String> {
+    device
+        .and_then(|device| device.profile.clone())
+        .or(config.default_profile.clone())
+}
+
+#[test]
+fn serial_number_before_side() {
+    let config: Config<Profile> = Config::parse(CONFIG, None).unwrap();
+
+    // Set to the left, but has its own entry
+    let device = config.device(Some("FRAKDEAM0020110001"), || Some(Side::Left));
+    assert_eq!(profile_name(&config, device).as_deref(), Some("night"));
+    assert_eq!(
+        device.unwrap().content,
+        Some(Content::Text("Good night".to_string()))
+    );
+    assert_eq!(config.profile("night").unwrap().brightness, 10);
+
+    let device = config.device(Some("FRAKDEAM0020110003"), || Some(Side::Left));
+    assert_eq!(profile_name(&config, device).as_deref(), Some("left"));
+
+    // Not in the file at all
+    let device = config.device(None, || Some(Side::Right));
+    assert!(device.is_none());
+    assert_eq!(profile_name(&config, device).as_deref(), Some("day"));
+}
+
+#[test]
+fn side_only_asked_if_needed() {
+    let config: Config<Profile> = Config::parse(CONFIG, None).unwrap();
+    let device = config.device(Some("FRAKDEAM0020110002"), || panic!("Side asked for"));
+    assert!(device.is_some());
+
+    // No entries by side, modules don't need to know theirs
+    let config: Config<Profile> = Config::parse(
+        r#"
+        [[devices]]
+        serial-number = "FRAKDEAM0020110001"
+        side = "left"
+        "#,
+        None,
+    )
+    .unwrap();
+    let device = config.device(Some("FRAKDEAM0020110003"), || panic!("Side asked for"));
+    assert!(device.is_none());
+}
+
+#[test]
+fn unknown_profile() {
+    assert!(Config::<Profile>::parse(CONFIG, Some("night")).is_ok());
+    assert!(matches!(
+        Config::<Profile>::parse(CONFIG, Some("evening")),
+        Err(ConfigError::UnknownProfile(name)) if name == "evening"
+    ));
+
+    let config = CONFIG.replace(r#"profile = "night""#, r#"profile = "nigth""#);
+    assert!(matches!(
+        Config::<Profile>::parse(&config, None),
+        Err(ConfigError::UnknownProfile(name)) if name == "nigth"
+    ));
+
+    let config: Config<Profile> = Config::parse(CONFIG, None).unwrap();
+    assert!(config.profile("evening").is_err());
+}
+
+#[test]
+fn invalid_files() {
+    assert!(matches!(
+        Config::<Profile>::parse("brightness = 10", None),
+        Err(ConfigError::Parse(_))
+    ));
+    assert!(matches!(
+        Config::<Profile>::load(Some(Path::new("/nonexistent/config.toml")), None),
+        Err(ConfigError::Read(..))
+    ));
+}
+
+#[test]
+fn set_side() {
+    let config: Config<Profile> = Config::parse(CONFIG, None).unwrap();
+    let sides: Vec<_> = config.devices.iter().map(Device::set_side).collect();
+    // Only modules found by their serial number are set to the side
+    assert_eq!(sides, [None, Some(Side::Right), None]);
+}
@@ -0,0 +1,58 @@
+#![cfg(unix)]
+mod common;
+
+use std::path::PathBuf;
+use std::thread;
+
+use inputmodule_control::protocol::*;
+use inputmodule_control::{Daemon, Error, InputModule};
+
+use common::{FakeModule, CAPABILITIES};
+
+/// Start a daemon with a single fake module, called `fake`
+fn daemon(name: &str) -> PathBuf {
+    let socket = std::env::temp_dir().join(format!(
+        "inputmodule-control-test-{}-{name}.sock",
+        std::process::id()
+    ));
+    let listener = Daemon::listen(&socket).unwrap();
+    let daemon = Daemon::with_opener(|path| match path {
+        "fake" => Ok(Box::new(FakeModule::default())),
+        _ => Err(Error::Open(serialport::Error::new(
+            serialport::ErrorKind::NoDevice,
+            "No such module",
+        ))),
+    });
+    thread::spawn(move || daemon.serve(listener));
+    socket
+}
+
+#[test]
+fn clients_share_module() {
+    let socket = daemon("share");
+    let mut first = InputModule::connect(&socket, "fake").unwrap();
+    let mut second = InputModule::connect(&socket, "fake").unwrap();
+
+    first.set_brightness(77).unwrap();
+    assert_eq!(second.brightness().unwrap(), 77);
+    assert_eq!(second.capabilities().unwrap(), Some(CAPABILITIES));
+
+    // Errors of the module get to the client
+    assert!(matches!(
+        second.set_animate(true),
+        Err(Error::Command {
+            command: CommandVals::Animate,
+            code: ErrorCode::Unsupported
+        })
+    ));
+    assert!(matches!(
+        InputModule::connect(&socket, "missing"),
+        Err(Error::Daemon(_))
+    ));
+}
+
+#[test]
+fn single_daemon() {
+    let socket = daemon("single");
+    assert!(Daemon::listen(&socket).is_err());
+}
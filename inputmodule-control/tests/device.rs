@@ -0,0 +1,51 @@
+mod common;
+
+use inputmodule_control::protocol::*;
+use inputmodule_control::{Error, InputModule};
+
+use common::{FakeModule, CAPABILITIES};
+
+fn module() -> InputModule {
+    InputModule::new(Box::new(FakeModule::default()))
+}
+
+#[test]
+fn set_and_get() {
+    let mut module = module();
+    module.set_brightness(77).unwrap();
+    assert_eq!(module.brightness().unwrap(), 77);
+    assert_eq!(module.capabilities().unwrap(), Some(CAPABILITIES));
+    assert!(module.supports(CommandVals::Brightness).unwrap());
+    assert!(!module.supports(CommandVals::Draw).unwrap());
+}
+
+#[test]
+fn unsupported_command() {
+    let mut module = module();
+    assert!(matches!(
+        module.set_animate(true),
+        Err(Error::Command {
+            command: CommandVals::Animate,
+            code: ErrorCode::Unsupported
+        })
+    ));
+    // Without the Gamma command, the module leaves the brightness curve to the host
+    assert!(!module.applies_gamma().unwrap());
+}
+
+#[test]
+fn text_too_long() {
+    let text = "x".repeat(MAX_SCROLL_TEXT_LEN + 1);
+    assert!(matches!(
+        module().scroll_text(&text, 1, ScrollDirection::Up, 0),
+        Err(Error::TextTooLong)
+    ));
+}
+
+#[test]
+fn missing_port() {
+    assert!(matches!(
+        InputModule::open("/nonexistent/ttyACM0"),
+        Err(Error::Open(_))
+    ));
+}
@@ -0,0 +1,77 @@
+use inputmodule_control::{ModuleInfo, ModuleKind, Selector, B1_LCD_PID, LED_MATRIX_PID};
+
+fn module(port_name: &str, pid: u16, serial_number: &str, usb_path: &str) -> ModuleInfo {
+    ModuleInfo {
+        port_name: port_name.to_string(),
+        pid,
+        serial_number: Some(serial_number.to_string()),
+        usb_path: Some(usb_path.to_string()),
+    }
+}
+
+fn modules() -> Vec<ModuleInfo> {
+    vec![
+        module("/dev/ttyACM0", LED_MATRIX_PID, "FRAKDEAM0A", "3-3.3"),
+        module("/dev/ttyACM1", B1_LCD_PID, "FRAKDEBZ0B", "3-4.2"),
+        module("/dev/ttyACM2", LED_MATRIX_PID, "FRAKDEAM0C", "3-4.3"),
+    ]
+}
+
+fn ports(selector: Selector) -> Vec<String> {
+    selector
+        .select(modules())
+        .into_iter()
+        .map(|module| module.port_name)
+        .collect()
+}
+
+#[test]
+fn select_all() {
+    assert_eq!(ports(Selector::default()).len(), 3);
+}
+
+#[test]
+fn select_by_usb_info() {
+    let by_serial_number = Selector {
+        serial_number: Some("FRAKDEAM0C".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ports(by_serial_number), ["/dev/ttyACM2"]);
+
+    let by_kind = Selector {
+        kind: Some(ModuleKind::LedMatrix),
+        ..Default::default()
+    };
+    assert_eq!(ports(by_kind), ["/dev/ttyACM0", "/dev/ttyACM2"]);
+
+    let by_usb_path = Selector {
+        usb_path: Some("3-4.2".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(ports(by_usb_path), ["/dev/ttyACM1"]);
+
+    // All criteria have to match
+    let none = Selector {
+        kind: Some(ModuleKind::B1Display),
+        usb_path: Some("3-4.3".to_string()),
+        ..Default::default()
+    };
+    assert!(ports(none).is_empty());
+}
+
+#[test]
+fn select_by_index() {
+    // Counts only the modules that match the other criteria
+    let second_matrix = Selector {
+        kind: Some(ModuleKind::LedMatrix),
+        index: Some(1),
+        ..Default::default()
+    };
+    assert_eq!(ports(second_matrix), ["/dev/ttyACM2"]);
+
+    let out_of_range = Selector {
+        index: Some(3),
+        ..Default::default()
+    };
+    assert!(ports(out_of_range).is_empty());
+}
@@ -0,0 +1,166 @@
+//! Features supported by a module, so that the host doesn't have to guess
+use num_traits::FromPrimitive;
+
+use crate::{AddonAnimationVals, Command, CommandVals, Game, GameVal, PatternVals};
+
+/// Type of the input module
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum ModuleType {
+    LedMatrix = 0x01,
+    B1Display = 0x02,
+    C1Minimal = 0x03,
+}
+
+/// Features of a module, as reported by [`Command::GetCapabilities`]
+///
+/// Supported commands, games, patterns and animations are bitmaps.
+/// Bit n is set if the value n is supported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub module: ModuleType,
+    /// Version of the framing protocol, see [`crate::FRAME_VERSION`]
+    pub protocol_version: u8,
+    /// Width of the LED grid or screen in pixels
+    pub width: u16,
+    /// Height of the LED grid or screen in pixels
+    pub height: u16,
+    /// Supported [`CommandVals`]
+    pub commands: u64,
+    /// Supported [`GameVal`]
+    pub games: u8,
+    /// Supported [`PatternVals`]
+    pub patterns: u8,
+    /// Supported [`AddonAnimationVals`]
+    pub addon_animations: u8,
+}
+
+impl Capabilities {
+    pub fn supports_command(&self, id: CommandVals) -> bool {
+        self.commands & (1 << id as u8) != 0
+    }
+
+    pub fn supports_game(&self, game: GameVal) -> bool {
+        self.games & (1 << game as u8) != 0
+    }
+
+    pub fn supports_pattern(&self, pattern: PatternVals) -> bool {
+        self.patterns & (1 << pattern as u8) != 0
+    }
+
+    pub fn supports_addon_animation(&self, animation: AddonAnimationVals) -> bool {
+        self.addon_animations & (1 << animation as u8) != 0
+    }
+
+    /// Check whether the module can handle the command, including its parameters
+    pub fn supports(&self, command: &Command) -> bool {
+        let param_supported = match command {
+            Command::Pattern(pattern) => self.supports_pattern(*pattern),
+            Command::Percentage(_) => self.supports_pattern(PatternVals::Percentage),
+            Command::StartGame(game) => self.supports_game(match game {
+                Game::Snake(_) => GameVal::Snake,
+                Game::Pong(_) => GameVal::Pong,
+                Game::Tetris => GameVal::Tetris,
+                Game::GameOfLife(_) => GameVal::GameOfLife,
+            }),
+            Command::SetAddonAnimation(animation) => self.supports_addon_animation(*animation),
+            _ => true,
+        };
+        param_supported && self.supports_command(command.id())
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = CommandVals> {
+        let bits = self.commands;
+        (0..64)
+            .filter(move |id| bits & (1 << id) != 0)
+            .filter_map(FromPrimitive::from_u64)
+    }
+
+    pub fn games(&self) -> impl Iterator<Item = GameVal> {
+        bits_iter(self.games)
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = PatternVals> {
+        bits_iter(self.patterns)
+    }
+
+    pub fn addon_animations(&self) -> impl Iterator<Item = AddonAnimationVals> {
+        bits_iter(self.addon_animations)
+    }
+
+    pub(crate) fn encode(&self, buf: &mut [u8]) {
+        buf[0] = self.module as u8;
+        buf[1] = self.protocol_version;
+        buf[2..4].copy_from_slice(&self.width.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.height.to_le_bytes());
+        buf[6..14].copy_from_slice(&self.commands.to_le_bytes());
+        buf[14] = self.games;
+        buf[15] = self.patterns;
+        buf[16] = self.addon_animations;
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Option<Self> {
+        let mut commands = [0; 8];
+        commands.copy_from_slice(&buf[6..14]);
+        Some(Self {
+            module: FromPrimitive::from_u8(buf[0])?,
+            protocol_version: buf[1],
+            width: u16::from_le_bytes([buf[2], buf[3]]),
+            height: u16::from_le_bytes([buf[4], buf[5]]),
+            commands: u64::from_le_bytes(commands),
+            games: buf[14],
+            patterns: buf[15],
+            addon_animations: buf[16],
+        })
+    }
+}
+
+fn bits_iter<T: FromPrimitive>(bits: u8) -> impl Iterator<Item = T> {
+    (0..8)
+        .filter(move |i| bits & (1 << i) != 0)
+        .filter_map(FromPrimitive::from_u8)
+}
+
+/// Bitmap of the supported commands, for [`Capabilities::commands`]
+pub const fn command_bits(ids: &[CommandVals]) -> u64 {
+    let mut bits = 0;
+    let mut i = 0;
+    while i < ids.len() {
+        bits |= 1 << ids[i] as u8;
+        i += 1;
+    }
+    bits
+}
+
+/// Bitmap of the supported games, for [`Capabilities::games`]
+pub const fn game_bits(games: &[GameVal]) -> u8 {
+    let mut bits = 0;
+    let mut i = 0;
+    while i < games.len() {
+        bits |= 1 << games[i] as u8;
+        i += 1;
+    }
+    bits
+}
+
+/// Bitmap of the supported patterns, for [`Capabilities::patterns`]
+pub const fn pattern_bits(patterns: &[PatternVals]) -> u8 {
+    let mut bits = 0;
+    let mut i = 0;
+    while i < patterns.len() {
+        bits |= 1 << patterns[i] as u8;
+        i += 1;
+    }
+    bits
+}
+
+/// Bitmap of the supported addon animations, for [`Capabilities::addon_animations`]
+pub const fn addon_animation_bits(animations: &[AddonAnimationVals]) -> u8 {
+    let mut bits = 0;
+    let mut i = 0;
+    while i < animations.len() {
+        bits |= 1 << animations[i] as u8;
+        i += 1;
+    }
+    bits
+}
@@ -0,0 +1,988 @@
+//! Commands sent from the host to the module
+use heapless::String;
+use num_traits::FromPrimitive;
+
+use crate::{
+    ErrorCode, GreyFrame, DRAW_BYTES, GAMMA_LUT_SIZE, HEIGHT, MAGIC, MAX_COMMAND_SIZE,
+    MAX_PAYLOAD_SIZE, MAX_SCROLL_TEXT_LEN, PIXEL_COLUMN_BYTES,
+};
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+/// All available commands
+pub enum CommandVals {
+    // addon stuff
+    Keypress = 0x30,
+    SetAddonAnimation = 0x31,
+    StopAddonAnimation = 0x32,
+    SetSide = 0x33,
+
+    Brightness = 0x00,
+    Pattern = 0x01,
+    BootloaderReset = 0x02,
+    Sleep = 0x03,
+    Animate = 0x04,
+    Panic = 0x05,
+    Draw = 0x06,
+    StageGreyCol = 0x07,
+    DrawGreyColBuffer = 0x08,
+    SetText = 0x09,
+    StartGame = 0x10,
+    GameControl = 0x11,
+    GameStatus = 0x12,
+    SetColor = 0x13,
+    DisplayOn = 0x14,
+    InvertScreen = 0x15,
+    SetPixelColumn = 0x16,
+    FlushFramebuffer = 0x17,
+    ClearRam = 0x18,
+    ScreenSaver = 0x19,
+    SetFps = 0x1A,
+    SetPowerMode = 0x1B,
+    AnimationPeriod = 0x1C,
+    PwmFreq = 0x1E,
+    DebugMode = 0x1F,
+    Version = 0x20,
+    GetCapabilities = 0x21,
+    SaveSettings = 0x22,
+    LoadSettings = 0x23,
+    ResetSettings = 0x24,
+    StoreAnimationFrame = 0x25,
+    CustomAnimation = 0x26,
+    DrawGreyFrame = 0x27,
+    Gamma = 0x28,
+    GammaLut = 0x29,
+    Orientation = 0x2A,
+    ScrollText = 0x2B,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum PatternVals {
+    Percentage = 0x00,
+    Gradient = 0x01,
+    DoubleGradient = 0x02,
+    DisplayLotus = 0x03,
+    ZigZag = 0x04,
+    FullBrightness = 0x05,
+    DisplayPanic = 0x06,
+    DisplayLotus2 = 0x07,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Game {
+    Snake(SnakeParam),
+    Pong(PongParam),
+    Tetris,
+    GameOfLife(GameOfLifeParam),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum GameVal {
+    Snake = 0,
+    Pong = 1,
+    Tetris = 2,
+    GameOfLife = 3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum GameControlArg {
+    Up = 0,
+    Down = 1,
+    Left = 2,
+    Right = 3,
+    Exit = 4,
+    SecondLeft = 5,
+    SecondRight = 6,
+}
+
+/// How to start Snake. By default it's the classic game.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SnakeParam {
+    /// Come back on the other side, instead of dying at the edges
+    pub wrap: bool,
+    /// Move faster, the longer the snake gets
+    pub speed_up: bool,
+}
+
+/// Paddle that the module plays by itself
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum PongPaddle {
+    /// Controlled by Left/Right
+    Upper = 0x01,
+    /// Controlled by SecondLeft/SecondRight
+    Lower = 0x02,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum PongDifficulty {
+    Easy = 0x00,
+    #[default]
+    Medium = 0x01,
+    Hard = 0x02,
+}
+
+/// How to start Pong. By default two people play against each other.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PongParam {
+    /// Paddle played by the module, if there's only one player
+    pub cpu: Option<PongPaddle>,
+    pub difficulty: PongDifficulty,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum GameOfLifeStartParam {
+    CurrentMatrix = 0x00,
+    Pattern1 = 0x01,
+    Blinker = 0x02,
+    Toad = 0x03,
+    Beacon = 0x04,
+    Glider = 0x05,
+    BeaconToadBlinker = 0x06,
+    /// Start from [`GameOfLifeParam::seed`]
+    Upload = 0x07,
+}
+
+/// What's beyond the edges of the board
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum LifeEdges {
+    /// Wrap around to the other side, so that gliders keep moving
+    #[default]
+    Wrap = 0x00,
+    /// Only dead cells
+    Bounded = 0x01,
+}
+
+/// Rule of a Life-like cellular automaton
+///
+/// Bit `n` of `birth` means that a dead cell with `n` living neighbors comes
+/// alive. Bit `n` of `survive` means that a living cell with `n` living
+/// neighbors stays alive.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LifeRule {
+    pub birth: u16,
+    pub survive: u16,
+}
+
+impl LifeRule {
+    /// B3/S23
+    pub const CONWAY: Self = Self {
+        birth: 1 << 3,
+        survive: 1 << 2 | 1 << 3,
+    };
+    /// B36/S23
+    pub const HIGHLIFE: Self = Self {
+        birth: 1 << 3 | 1 << 6,
+        survive: 1 << 2 | 1 << 3,
+    };
+    /// B2/S
+    pub const SEEDS: Self = Self {
+        birth: 1 << 2,
+        survive: 0,
+    };
+    /// B3678/S34678
+    pub const DAY_AND_NIGHT: Self = Self {
+        birth: 1 << 3 | 1 << 6 | 1 << 7 | 1 << 8,
+        survive: 1 << 3 | 1 << 4 | 1 << 6 | 1 << 7 | 1 << 8,
+    };
+
+    /// Parse a rule string like `B36/S23`, or the name of a well-known rule
+    pub fn parse(rule: &str) -> Option<Self> {
+        let named = [
+            ("conway", Self::CONWAY),
+            ("highlife", Self::HIGHLIFE),
+            ("seeds", Self::SEEDS),
+            ("day-and-night", Self::DAY_AND_NIGHT),
+        ];
+        if let Some((_, rule)) = named
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(rule))
+        {
+            return Some(*rule);
+        }
+
+        let (birth, survive) = rule.split_once('/')?;
+        let birth = birth.strip_prefix(['B', 'b'])?;
+        let survive = survive.strip_prefix(['S', 's'])?;
+        Some(Self {
+            birth: neighbor_bits(birth)?,
+            survive: neighbor_bits(survive)?,
+        })
+    }
+}
+
+impl Default for LifeRule {
+    fn default() -> Self {
+        Self::CONWAY
+    }
+}
+
+/// Set a bit for every digit from 0 to 8
+fn neighbor_bits(digits: &str) -> Option<u16> {
+    digits.chars().try_fold(0, |bits, c| match c.to_digit(10) {
+        Some(n) if n <= 8 => Some(bits | 1 << n),
+        _ => None,
+    })
+}
+
+/// How to start Game of Life
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GameOfLifeParam {
+    pub start: GameOfLifeStartParam,
+    pub edges: LifeEdges,
+    pub rule: LifeRule,
+    /// Show young cells brighter than old ones
+    pub age_brightness: bool,
+    /// Start over with random cells, when the board stops changing or repeats
+    pub reseed: bool,
+    /// Cells to start with, in the same format as [`Command::Draw`].
+    /// Only sent with [`GameOfLifeStartParam::Upload`].
+    pub seed: [u8; DRAW_BYTES],
+}
+
+/// Classic Game of Life, starting with the given pattern
+impl From<GameOfLifeStartParam> for GameOfLifeParam {
+    fn from(start: GameOfLifeStartParam) -> Self {
+        Self {
+            start,
+            edges: LifeEdges::default(),
+            rule: LifeRule::default(),
+            age_brightness: false,
+            reseed: false,
+            seed: [0; DRAW_BYTES],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum DisplayMode {
+    /// Low Power Mode
+    Lpm = 0x00,
+    /// High Power Mode
+    Hpm = 0x01,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum PwmFreqArg {
+    /// 29kHz
+    P29k = 0x00,
+    /// 3.6kHz
+    P3k6 = 0x01,
+    /// 1.8kHz
+    P1k8 = 0x02,
+    /// 900Hz
+    P900 = 0x03,
+}
+
+impl PwmFreqArg {
+    /// Frequency in Hz
+    pub fn hz(self) -> u16 {
+        match self {
+            PwmFreqArg::P29k => 29000,
+            PwmFreqArg::P3k6 => 3600,
+            PwmFreqArg::P1k8 => 1800,
+            PwmFreqArg::P900 => 900,
+        }
+    }
+
+    /// Look up one of the supported frequencies in Hz
+    pub fn from_hz(hz: u16) -> Option<Self> {
+        match hz {
+            29000 => Some(PwmFreqArg::P29k),
+            3600 => Some(PwmFreqArg::P3k6),
+            1800 => Some(PwmFreqArg::P1k8),
+            900 => Some(PwmFreqArg::P900),
+            _ => None,
+        }
+    }
+}
+
+/// Curve that maps the brightness of a pixel to the brightness of the LED
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum GammaCurve {
+    /// Brightness values are used as they are
+    Linear = 0x00,
+    /// Gamma 2.2, like sRGB
+    Gamma22 = 0x01,
+    /// CIE 1931 lightness
+    Cie = 0x02,
+    /// Lookup table uploaded with [`Command::SetGammaLut`]
+    Custom = 0x03,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum AddonAnimationVals {
+    Spiral = 0x00,
+    Splashes = 0x01,
+    Helix = 0x02,
+}
+
+/// How the image is transformed before it's displayed
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum Orientation {
+    Normal = 0x00,
+    /// Upside-down
+    Rotate180 = 0x01,
+    /// Flip left and right
+    MirrorX = 0x02,
+    /// Flip top and bottom
+    MirrorY = 0x03,
+}
+
+impl Orientation {
+    /// Whether left and right are swapped
+    pub fn mirrors_x(self) -> bool {
+        matches!(self, Orientation::Rotate180 | Orientation::MirrorX)
+    }
+
+    /// Whether top and bottom are swapped
+    pub fn mirrors_y(self) -> bool {
+        matches!(self, Orientation::Rotate180 | Orientation::MirrorY)
+    }
+}
+
+/// Direction that scrolling text moves on the LED Matrix
+///
+/// Letters are shown sideways and read from bottom to top.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum ScrollDirection {
+    /// Text comes in at the top, the usual direction of a marquee
+    Down = 0x00,
+    /// Text comes in at the bottom
+    Up = 0x01,
+}
+
+/// Which side of the keyboard the LED Matrix is installed on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "kebab-case")
+)]
+pub enum Side {
+    Left,
+    Right,
+}
+impl Side {
+    pub fn is_left(&self) -> bool {
+        matches!(self, Side::Left)
+    }
+    pub fn is_right(&self) -> bool {
+        matches!(self, Side::Right)
+    }
+}
+
+// TODO: Reduce size for modules that don't require other commands
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    // addon stuff
+    Keypress {
+        keycode: u32,
+        pressed: bool,
+    },
+    SetAddonAnimation(AddonAnimationVals),
+    StopAddonAnimation,
+    SetSide(Side),
+    /// Side that the module was told it's installed on
+    GetSide,
+
+    /// Get current brightness scaling
+    GetBrightness,
+    /// Set brightness scaling
+    SetBrightness(u8),
+    /// Display pre-programmed pattern
+    Pattern(PatternVals),
+    /// Reset into bootloader
+    BootloaderReset,
+    /// Light up a percentage of the screen
+    Percentage(u8),
+    /// Go to sleepe or wake up
+    Sleep(bool),
+    IsSleeping,
+    /// Start/stop animation (vertical scrolling)
+    SetAnimate(bool),
+    GetAnimate,
+    /// Panic. Just to test what happens
+    Panic,
+    /// Draw black/white on the grid
+    Draw([u8; DRAW_BYTES]),
+    StageGreyCol(u8, [u8; HEIGHT]),
+    DrawGreyColBuffer,
+    /// Draw a whole greyscale frame at once. Usually too large for a legacy command.
+    DrawGreyFrame(GreyFrame),
+    SetText(String<64>),
+    StartGame(Game),
+    GameControl(GameControlArg),
+    GameStatus,
+    Version,
+    /// Get the features supported by the module
+    GetCapabilities,
+    /// Store the current settings in the flash
+    SaveSettings,
+    /// Apply the settings stored in the flash
+    LoadSettings,
+    /// Erase the stored settings and go back to the defaults
+    ResetSettings,
+    /// Store the staged columns as frame of the custom animation
+    ///
+    /// Frame 0 starts a new animation. Delay in milliseconds.
+    StoreAnimationFrame {
+        index: u8,
+        delay: u16,
+    },
+    /// Start/stop playing the custom animation in a loop
+    PlayCustomAnimation(bool),
+    IsPlayingCustomAnimation,
+    GetColor,
+    /// Set RGB color
+    SetColor([u8; 3]),
+    DisplayOn(bool),
+    GetDisplayOn,
+    InvertScreen(bool),
+    GetInvertScreen,
+    SetPixelColumn(usize, [u8; PIXEL_COLUMN_BYTES]),
+    FlushFramebuffer,
+    ClearRam,
+    ScreenSaver(bool),
+    GetScreenSaver,
+    SetFps(u8),
+    GetFps,
+    SetPowerMode(DisplayMode),
+    GetPowerMode,
+    /// Set the animation period in milliseconds
+    SetAnimationPeriod(u16),
+    /// Get the animation period in milliseconds
+    GetAnimationPeriod,
+    SetPwmFreq(PwmFreqArg),
+    GetPwmFreq,
+    SetDebugMode(bool),
+    GetDebugMode,
+    SetGamma(GammaCurve),
+    GetGamma,
+    /// Upload a custom gamma lookup table and use it. Needs to be sent in a frame.
+    SetGammaLut([u8; GAMMA_LUT_SIZE]),
+    SetOrientation(Orientation),
+    GetOrientation,
+    /// Scroll text across the display, rendered with the font of the module
+    ///
+    /// Speed in rows per second. Loops forever if `loops` is 0.
+    /// Empty text stops scrolling.
+    ScrollText {
+        speed: u8,
+        direction: ScrollDirection,
+        loops: u8,
+        text: String<MAX_SCROLL_TEXT_LEN>,
+    },
+    /// Check whether text is still scrolling
+    IsScrollingText,
+}
+
+impl Command {
+    /// ID of the command on the wire. Getters and setters share the same ID.
+    pub fn id(&self) -> CommandVals {
+        match self {
+            Command::Keypress { .. } => CommandVals::Keypress,
+            Command::SetAddonAnimation(_) => CommandVals::SetAddonAnimation,
+            Command::StopAddonAnimation => CommandVals::StopAddonAnimation,
+            Command::SetSide(_) | Command::GetSide => CommandVals::SetSide,
+            Command::GetBrightness | Command::SetBrightness(_) => CommandVals::Brightness,
+            Command::Pattern(_) | Command::Percentage(_) => CommandVals::Pattern,
+            Command::BootloaderReset => CommandVals::BootloaderReset,
+            Command::Sleep(_) | Command::IsSleeping => CommandVals::Sleep,
+            Command::SetAnimate(_) | Command::GetAnimate => CommandVals::Animate,
+            Command::Panic => CommandVals::Panic,
+            Command::Draw(_) => CommandVals::Draw,
+            Command::StageGreyCol(_, _) => CommandVals::StageGreyCol,
+            Command::DrawGreyColBuffer => CommandVals::DrawGreyColBuffer,
+            Command::DrawGreyFrame(_) => CommandVals::DrawGreyFrame,
+            Command::SetGamma(_) | Command::GetGamma => CommandVals::Gamma,
+            Command::SetGammaLut(_) => CommandVals::GammaLut,
+            Command::SetOrientation(_) | Command::GetOrientation => CommandVals::Orientation,
+            Command::ScrollText { .. } | Command::IsScrollingText => CommandVals::ScrollText,
+            Command::SetText(_) => CommandVals::SetText,
+            Command::StartGame(_) => CommandVals::StartGame,
+            Command::GameControl(_) => CommandVals::GameControl,
+            Command::GameStatus => CommandVals::GameStatus,
+            Command::Version => CommandVals::Version,
+            Command::GetCapabilities => CommandVals::GetCapabilities,
+            Command::SaveSettings => CommandVals::SaveSettings,
+            Command::LoadSettings => CommandVals::LoadSettings,
+            Command::ResetSettings => CommandVals::ResetSettings,
+            Command::StoreAnimationFrame { .. } => CommandVals::StoreAnimationFrame,
+            Command::PlayCustomAnimation(_) | Command::IsPlayingCustomAnimation => {
+                CommandVals::CustomAnimation
+            }
+            Command::GetColor | Command::SetColor(_) => CommandVals::SetColor,
+            Command::DisplayOn(_) | Command::GetDisplayOn => CommandVals::DisplayOn,
+            Command::InvertScreen(_) | Command::GetInvertScreen => CommandVals::InvertScreen,
+            Command::SetPixelColumn(_, _) => CommandVals::SetPixelColumn,
+            Command::FlushFramebuffer => CommandVals::FlushFramebuffer,
+            Command::ClearRam => CommandVals::ClearRam,
+            Command::ScreenSaver(_) | Command::GetScreenSaver => CommandVals::ScreenSaver,
+            Command::SetFps(_) | Command::GetFps => CommandVals::SetFps,
+            Command::SetPowerMode(_) | Command::GetPowerMode => CommandVals::SetPowerMode,
+            Command::SetAnimationPeriod(_) | Command::GetAnimationPeriod => {
+                CommandVals::AnimationPeriod
+            }
+            Command::SetPwmFreq(_) | Command::GetPwmFreq => CommandVals::PwmFreq,
+            Command::SetDebugMode(_) | Command::GetDebugMode => CommandVals::DebugMode,
+        }
+    }
+
+    /// Parse a legacy command, received in a single read from the serial port
+    pub fn decode(buf: &[u8]) -> Result<Command, ErrorCode> {
+        if buf.len() < 3 || buf[..2] != MAGIC {
+            return Err(ErrorCode::BadFrame);
+        }
+        Self::decode_body(&buf[2..])
+    }
+
+    /// Parse the command ID and parameters, without magic bytes
+    ///
+    /// This is the payload of a frame, see [`crate::FrameDecoder`].
+    pub fn decode_body(body: &[u8]) -> Result<Command, ErrorCode> {
+        let (id, args) = body.split_first().ok_or(ErrorCode::BadLength)?;
+        let count = args.len();
+        let arg = args.first().copied();
+
+        let command = match FromPrimitive::from_u8(*id).ok_or(ErrorCode::UnknownCommand)? {
+            // addon stuff
+            CommandVals::Keypress => {
+                if count < 5 {
+                    return Err(ErrorCode::BadLength);
+                }
+                Command::Keypress {
+                    keycode: u32::from_le_bytes([args[0], args[1], args[2], args[3]]),
+                    pressed: args[4] == 1,
+                }
+            }
+            CommandVals::SetAddonAnimation => Command::SetAddonAnimation(enum_arg(arg)?),
+            CommandVals::StopAddonAnimation => Command::StopAddonAnimation,
+            CommandVals::SetSide => {
+                if let Some(arg) = arg {
+                    Command::SetSide(if arg == 0 { Side::Left } else { Side::Right })
+                } else {
+                    Command::GetSide
+                }
+            }
+
+            CommandVals::Brightness => {
+                if let Some(brightness) = arg {
+                    Command::SetBrightness(brightness)
+                } else {
+                    Command::GetBrightness
+                }
+            }
+            CommandVals::Pattern => match enum_arg(arg)? {
+                PatternVals::Percentage => {
+                    Command::Percentage(*args.get(1).ok_or(ErrorCode::BadLength)?)
+                }
+                pattern => Command::Pattern(pattern),
+            },
+            CommandVals::BootloaderReset => Command::BootloaderReset,
+            CommandVals::Sleep => {
+                if let Some(go_to_sleep) = arg {
+                    Command::Sleep(go_to_sleep == 1)
+                } else {
+                    Command::IsSleeping
+                }
+            }
+            CommandVals::Animate => {
+                if let Some(run_animation) = arg {
+                    Command::SetAnimate(run_animation == 1)
+                } else {
+                    Command::GetAnimate
+                }
+            }
+            CommandVals::Panic => Command::Panic,
+            CommandVals::Draw => {
+                if count < DRAW_BYTES {
+                    return Err(ErrorCode::BadLength);
+                }
+                let mut bytes = [0; DRAW_BYTES];
+                bytes.clone_from_slice(&args[..DRAW_BYTES]);
+                Command::Draw(bytes)
+            }
+            CommandVals::StageGreyCol => {
+                if count <= HEIGHT {
+                    return Err(ErrorCode::BadLength);
+                }
+                let mut bytes = [0; HEIGHT];
+                bytes.clone_from_slice(&args[1..1 + HEIGHT]);
+                Command::StageGreyCol(args[0], bytes)
+            }
+            CommandVals::DrawGreyColBuffer => Command::DrawGreyColBuffer,
+            CommandVals::Gamma => {
+                if arg.is_some() {
+                    Command::SetGamma(enum_arg(arg)?)
+                } else {
+                    Command::GetGamma
+                }
+            }
+            CommandVals::Orientation => {
+                if arg.is_some() {
+                    Command::SetOrientation(enum_arg(arg)?)
+                } else {
+                    Command::GetOrientation
+                }
+            }
+            CommandVals::ScrollText => {
+                if count == 0 {
+                    return Ok(Command::IsScrollingText);
+                }
+                if count < 3 {
+                    return Err(ErrorCode::BadLength);
+                }
+                let text = core::str::from_utf8(&args[3..]).map_err(|_| ErrorCode::BadArgument)?;
+                Command::ScrollText {
+                    speed: args[0],
+                    direction: enum_arg(Some(args[1]))?,
+                    loops: args[2],
+                    text: String::try_from(text).map_err(|_| ErrorCode::BadLength)?,
+                }
+            }
+            CommandVals::GammaLut => {
+                if count < GAMMA_LUT_SIZE {
+                    return Err(ErrorCode::BadLength);
+                }
+                let mut lut = [0; GAMMA_LUT_SIZE];
+                lut.copy_from_slice(&args[..GAMMA_LUT_SIZE]);
+                Command::SetGammaLut(lut)
+            }
+            CommandVals::DrawGreyFrame => {
+                let data = args.get(1..).ok_or(ErrorCode::BadLength)?;
+                Command::DrawGreyFrame(GreyFrame {
+                    encoding: enum_arg(arg)?,
+                    data: heapless::Vec::from_slice(data).map_err(|_| ErrorCode::BadLength)?,
+                })
+            }
+            CommandVals::SetText => {
+                let str_len = arg.ok_or(ErrorCode::BadLength)? as usize;
+                let bytes = args.get(1..1 + str_len).ok_or(ErrorCode::BadLength)?;
+                let text = core::str::from_utf8(bytes).map_err(|_| ErrorCode::BadArgument)?;
+                Command::SetText(String::try_from(text).map_err(|_| ErrorCode::BadLength)?)
+            }
+            CommandVals::StartGame => match enum_arg(arg)? {
+                GameVal::Snake => {
+                    // Both parameters are optional, to stay compatible with older hosts
+                    Command::StartGame(Game::Snake(SnakeParam {
+                        wrap: args.get(1) == Some(&1),
+                        speed_up: args.get(2) == Some(&1),
+                    }))
+                }
+                GameVal::Pong => {
+                    // Both parameters are optional, to stay compatible with older hosts
+                    let cpu = match args.get(1) {
+                        None | Some(0) => None,
+                        Some(paddle) => Some(enum_arg(Some(*paddle))?),
+                    };
+                    let difficulty = match args.get(2) {
+                        None => PongDifficulty::default(),
+                        Some(difficulty) => enum_arg(Some(*difficulty))?,
+                    };
+                    Command::StartGame(Game::Pong(PongParam { cpu, difficulty }))
+                }
+                GameVal::Tetris => Command::StartGame(Game::Tetris),
+                GameVal::GameOfLife => {
+                    let start: GameOfLifeStartParam = enum_arg(args.get(1).copied())?;
+                    let mut param = GameOfLifeParam::from(start);
+                    // Older hosts only send the starting pattern
+                    if count > 2 || start == GameOfLifeStartParam::Upload {
+                        let options = args.get(2..8).ok_or(ErrorCode::BadLength)?;
+                        param.edges = enum_arg(Some(options[0]))?;
+                        param.rule = LifeRule {
+                            birth: u16::from_le_bytes([options[1], options[2]]),
+                            survive: u16::from_le_bytes([options[3], options[4]]),
+                        };
+                        param.age_brightness = options[5] & 0x01 != 0;
+                        param.reseed = options[5] & 0x02 != 0;
+                    }
+                    if start == GameOfLifeStartParam::Upload {
+                        let seed = args.get(8..8 + DRAW_BYTES).ok_or(ErrorCode::BadLength)?;
+                        param.seed.copy_from_slice(seed);
+                    }
+                    Command::StartGame(Game::GameOfLife(param))
+                }
+            },
+            CommandVals::GameControl => Command::GameControl(enum_arg(arg)?),
+            CommandVals::GameStatus => Command::GameStatus,
+            CommandVals::SetColor => {
+                if count >= 3 {
+                    Command::SetColor([args[0], args[1], args[2]])
+                } else if arg.is_none() {
+                    Command::GetColor
+                } else {
+                    return Err(ErrorCode::BadLength);
+                }
+            }
+            CommandVals::DisplayOn => {
+                if let Some(on) = arg {
+                    Command::DisplayOn(on == 1)
+                } else {
+                    Command::GetDisplayOn
+                }
+            }
+            CommandVals::InvertScreen => {
+                if let Some(invert) = arg {
+                    Command::InvertScreen(invert == 1)
+                } else {
+                    Command::GetInvertScreen
+                }
+            }
+            CommandVals::SetPixelColumn => {
+                //  2B for column (u16)
+                // 50B for 400 pixels (400/8=50)
+                if count != 2 + PIXEL_COLUMN_BYTES {
+                    return Err(ErrorCode::BadLength);
+                }
+                let column = u16::from_le_bytes([args[0], args[1]]);
+                let mut pixels = [0; PIXEL_COLUMN_BYTES];
+                pixels.clone_from_slice(&args[2..2 + PIXEL_COLUMN_BYTES]);
+                Command::SetPixelColumn(column as usize, pixels)
+            }
+            CommandVals::FlushFramebuffer => Command::FlushFramebuffer,
+            CommandVals::ClearRam => Command::ClearRam,
+            CommandVals::ScreenSaver => {
+                if let Some(on) = arg {
+                    Command::ScreenSaver(on == 1)
+                } else {
+                    Command::GetScreenSaver
+                }
+            }
+            CommandVals::SetFps => {
+                if let Some(fps) = arg {
+                    Command::SetFps(fps)
+                } else {
+                    Command::GetFps
+                }
+            }
+            CommandVals::SetPowerMode => {
+                if arg.is_some() {
+                    Command::SetPowerMode(enum_arg(arg)?)
+                } else {
+                    Command::GetPowerMode
+                }
+            }
+            CommandVals::AnimationPeriod => {
+                if count == 2 {
+                    let period = u16::from_le_bytes([args[0], args[1]]);
+                    Command::SetAnimationPeriod(period)
+                } else {
+                    Command::GetAnimationPeriod
+                }
+            }
+            CommandVals::PwmFreq => {
+                if arg.is_some() {
+                    Command::SetPwmFreq(enum_arg(arg)?)
+                } else {
+                    Command::GetPwmFreq
+                }
+            }
+            CommandVals::DebugMode => {
+                if let Some(debug_mode) = arg {
+                    Command::SetDebugMode(debug_mode == 1)
+                } else {
+                    Command::GetDebugMode
+                }
+            }
+            CommandVals::Version => Command::Version,
+            CommandVals::GetCapabilities => Command::GetCapabilities,
+            CommandVals::SaveSettings => Command::SaveSettings,
+            CommandVals::LoadSettings => Command::LoadSettings,
+            CommandVals::ResetSettings => Command::ResetSettings,
+            CommandVals::StoreAnimationFrame => {
+                if count < 3 {
+                    return Err(ErrorCode::BadLength);
+                }
+                Command::StoreAnimationFrame {
+                    index: args[0],
+                    delay: u16::from_le_bytes([args[1], args[2]]),
+                }
+            }
+            CommandVals::CustomAnimation => {
+                if let Some(play) = arg {
+                    Command::PlayCustomAnimation(play == 1)
+                } else {
+                    Command::IsPlayingCustomAnimation
+                }
+            }
+        };
+        Ok(command)
+    }
+
+    /// Serialize the command as legacy command, including magic bytes, into `buf`
+    ///
+    /// Returns the number of bytes written.
+    /// Panics if the command is too large, see [`Command::needs_frame`].
+    pub fn encode(&self, buf: &mut [u8; MAX_COMMAND_SIZE]) -> usize {
+        buf[..2].copy_from_slice(&MAGIC);
+        2 + self.encode_body(&mut buf[2..])
+    }
+
+    /// Serialize command ID and parameters, without magic bytes, into `buf`
+    ///
+    /// `buf` must be large enough for the command, `MAX_PAYLOAD_SIZE` always is.
+    /// Returns the number of bytes written.
+    pub fn encode_body(&self, buf: &mut [u8]) -> usize {
+        buf[0] = self.id() as u8;
+        let args = &mut buf[1..];
+
+        let args_len = match self {
+            Command::Keypress { keycode, pressed } => {
+                args[..4].copy_from_slice(&keycode.to_le_bytes());
+                args[4] = *pressed as u8;
+                5
+            }
+            Command::SetAddonAnimation(animation) => put_u8(args, *animation as u8),
+            Command::SetSide(side) => put_u8(args, side.is_right() as u8),
+            Command::SetBrightness(brightness) => put_u8(args, *brightness),
+            Command::Pattern(pattern) => put_u8(args, *pattern as u8),
+            Command::Percentage(percentage) => {
+                args[0] = PatternVals::Percentage as u8;
+                args[1] = *percentage;
+                2
+            }
+            Command::Sleep(sleep) => put_u8(args, *sleep as u8),
+            Command::SetAnimate(animate) => put_u8(args, *animate as u8),
+            Command::Draw(vals) => put_slice(args, vals),
+            Command::StageGreyCol(col, vals) => {
+                args[0] = *col;
+                1 + put_slice(&mut args[1..], vals)
+            }
+            Command::SetGamma(curve) => put_u8(args, *curve as u8),
+            Command::SetGammaLut(lut) => put_slice(args, lut),
+            Command::SetOrientation(orientation) => put_u8(args, *orientation as u8),
+            Command::ScrollText {
+                speed,
+                direction,
+                loops,
+                text,
+            } => {
+                args[..3].copy_from_slice(&[*speed, *direction as u8, *loops]);
+                3 + put_slice(&mut args[3..], text.as_bytes())
+            }
+            Command::DrawGreyFrame(frame) => {
+                args[0] = frame.encoding as u8;
+                1 + put_slice(&mut args[1..], &frame.data)
+            }
+            Command::SetText(text) => {
+                // Text that doesn't fit into a single command is cut off,
+                // between characters so that it stays valid UTF-8
+                let mut len = text.len().min(MAX_COMMAND_SIZE - 4);
+                while !text.is_char_boundary(len) {
+                    len -= 1;
+                }
+                args[0] = len as u8;
+                1 + put_slice(&mut args[1..], &text.as_bytes()[..len])
+            }
+            Command::StartGame(game) => match game {
+                Game::Snake(param) => {
+                    args[0] = GameVal::Snake as u8;
+                    args[1] = param.wrap as u8;
+                    args[2] = param.speed_up as u8;
+                    3
+                }
+                Game::Pong(param) => {
+                    args[0] = GameVal::Pong as u8;
+                    args[1] = param.cpu.map_or(0, |paddle| paddle as u8);
+                    args[2] = param.difficulty as u8;
+                    3
+                }
+                Game::Tetris => put_u8(args, GameVal::Tetris as u8),
+                Game::GameOfLife(param) => {
+                    args[0] = GameVal::GameOfLife as u8;
+                    args[1] = param.start as u8;
+                    args[2] = param.edges as u8;
+                    args[3..5].copy_from_slice(&param.rule.birth.to_le_bytes());
+                    args[5..7].copy_from_slice(&param.rule.survive.to_le_bytes());
+                    args[7] = param.age_brightness as u8 | (param.reseed as u8) << 1;
+                    if param.start == GameOfLifeStartParam::Upload {
+                        8 + put_slice(&mut args[8..], &param.seed)
+                    } else {
+                        8
+                    }
+                }
+            },
+            Command::GameControl(arg) => put_u8(args, *arg as u8),
+            Command::SetColor(rgb) => put_slice(args, rgb),
+            Command::DisplayOn(on) => put_u8(args, *on as u8),
+            Command::InvertScreen(invert) => put_u8(args, *invert as u8),
+            Command::SetPixelColumn(column, pixels) => {
+                args[..2].copy_from_slice(&(*column as u16).to_le_bytes());
+                2 + put_slice(&mut args[2..], pixels)
+            }
+            Command::ScreenSaver(on) => put_u8(args, *on as u8),
+            Command::SetFps(fps) => put_u8(args, *fps),
+            Command::SetPowerMode(mode) => put_u8(args, *mode as u8),
+            Command::SetAnimationPeriod(period) => put_slice(args, &period.to_le_bytes()),
+            Command::SetPwmFreq(freq) => put_u8(args, *freq as u8),
+            Command::SetDebugMode(debug_mode) => put_u8(args, *debug_mode as u8),
+            Command::StoreAnimationFrame { index, delay } => {
+                args[0] = *index;
+                1 + put_slice(&mut args[1..], &delay.to_le_bytes())
+            }
+            Command::PlayCustomAnimation(play) => put_u8(args, *play as u8),
+            // Commands without parameters
+            Command::StopAddonAnimation
+            | Command::GetBrightness
+            | Command::BootloaderReset
+            | Command::IsSleeping
+            | Command::GetAnimate
+            | Command::Panic
+            | Command::DrawGreyColBuffer
+            | Command::GameStatus
+            | Command::Version
+            | Command::GetCapabilities
+            | Command::SaveSettings
+            | Command::LoadSettings
+            | Command::ResetSettings
+            | Command::IsPlayingCustomAnimation
+            | Command::GetColor
+            | Command::GetDisplayOn
+            | Command::GetInvertScreen
+            | Command::FlushFramebuffer
+            | Command::ClearRam
+            | Command::GetScreenSaver
+            | Command::GetFps
+            | Command::GetSide
+            | Command::GetPowerMode
+            | Command::GetAnimationPeriod
+            | Command::GetPwmFreq
+            | Command::GetDebugMode
+            | Command::GetGamma
+            | Command::GetOrientation
+            | Command::IsScrollingText => 0,
+        };
+
+        1 + args_len
+    }
+
+    /// Whether the command is too large for a legacy command and must be sent in a frame
+    pub fn needs_frame(&self) -> bool {
+        let mut body = [0; MAX_PAYLOAD_SIZE];
+        MAGIC.len() + self.encode_body(&mut body) > MAX_COMMAND_SIZE
+    }
+
+    /// Whether the module writes to the flash before acknowledging the command
+    ///
+    /// Erasing a flash sector takes up to 400ms, so the reply can take a while.
+    pub fn writes_flash(&self) -> bool {
+        matches!(self, Command::SaveSettings | Command::ResetSettings)
+    }
+}
+
+fn put_u8(args: &mut [u8], val: u8) -> usize {
+    args[0] = val;
+    1
+}
+
+fn put_slice(args: &mut [u8], vals: &[u8]) -> usize {
+    args[..vals.len()].copy_from_slice(vals);
+    vals.len()
+}
+
+/// Parse a parameter that must be one of the values of an enum
+fn enum_arg<T: FromPrimitive>(arg: Option<u8>) -> Result<T, ErrorCode> {
+    let arg = arg.ok_or(ErrorCode::BadLength)?;
+    FromPrimitive::from_u8(arg).ok_or(ErrorCode::BadArgument)
+}
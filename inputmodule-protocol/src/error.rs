@@ -0,0 +1,39 @@
+//! Errors reported by the module
+use core::fmt;
+
+/// Reason why the module did not handle a command
+///
+/// Sent back to the host in the NAK reply to a framed command.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum ErrorCode {
+    /// Command ID is not known
+    UnknownCommand = 0x01,
+    /// Command has too few or too many parameters
+    BadLength = 0x02,
+    /// A parameter has an invalid value
+    BadArgument = 0x03,
+    /// Command is valid, but this module doesn't support it
+    Unsupported = 0x04,
+    /// Module is sleeping and only handles commands to wake it up
+    Sleeping = 0x05,
+    /// Frame or magic bytes are invalid, the data was likely corrupted
+    BadFrame = 0x06,
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            ErrorCode::UnknownCommand => "Unknown command",
+            ErrorCode::BadLength => "Wrong number of parameters",
+            ErrorCode::BadArgument => "Invalid parameter",
+            ErrorCode::Unsupported => "Command not supported by this module",
+            ErrorCode::Sleeping => "Module is sleeping",
+            ErrorCode::BadFrame => "Data was corrupted",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Result of handling a command on the module
+pub type CommandResult = Result<Option<crate::ResponseBuf>, ErrorCode>;
@@ -0,0 +1,367 @@
+//! Framing layer, to transfer commands and responses with integrity checks
+//!
+//! Every frame looks like this (multi-byte values are little endian):
+//!
+//! | Bytes | Content                                      |
+//! |-------|----------------------------------------------|
+//! | 2     | Magic bytes [`FRAME_MAGIC`]                  |
+//! | 1     | Protocol version [`FRAME_VERSION`]           |
+//! | 1     | Sequence number, echoed back in the reply    |
+//! | 2     | Length of the payload                        |
+//! | n     | Payload                                      |
+//! | 4     | CRC32 of version, seq, len and payload       |
+//!
+//! Commands from the host have the command ID and parameters as payload.
+//! The module answers every frame with a frame with the same sequence number,
+//! with a [`FrameStatus`] as payload. It is followed by the optional response
+//! for [`FrameStatus::Ack`] and by an [`ErrorCode`] for [`FrameStatus::Nak`].
+//!
+//! Legacy commands, without framing, keep working. They start with [`MAGIC`]
+//! and must be sent in a single write.
+use crc::{Crc, CRC_32_ISO_HDLC};
+use heapless::Vec;
+use num_traits::FromPrimitive;
+
+use crate::{
+    Command, CommandResult, ErrorCode, ResponseBuf, MAGIC, MAX_COMMAND_SIZE, RESPONSE_SIZE,
+};
+
+/// Magic bytes at the start of every frame
+pub const FRAME_MAGIC: [u8; 2] = [0x32, 0xAD];
+/// Version of the framing protocol
+pub const FRAME_VERSION: u8 = 1;
+/// Bytes before the payload
+pub const FRAME_HEADER_SIZE: usize = 6;
+/// Bytes after the payload
+pub const FRAME_CRC_SIZE: usize = 4;
+/// Maximum size of the payload
+/// Frames can span multiple USB packets, so this can be larger than a packet.
+/// Large enough for a raw [`crate::GreyFrame`].
+pub const MAX_PAYLOAD_SIZE: usize = 320;
+/// Maximum size of a whole frame
+pub const MAX_FRAME_SIZE: usize = FRAME_HEADER_SIZE + MAX_PAYLOAD_SIZE + FRAME_CRC_SIZE;
+/// Partially received frames are dropped after this long without data, in microseconds
+///
+/// The host sends all packets of a frame at once, so it gave up on the frame.
+pub const FRAME_TIMEOUT: u64 = 100_000;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// First byte of the payload of a frame sent by the module
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum FrameStatus {
+    /// Command was received and handled
+    Ack = 0x06,
+    /// Command was not handled
+    Nak = 0x15,
+}
+
+/// Payload of a frame sent by the module
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameReply {
+    /// Command was handled, with the response if the command has one
+    Ack(Option<ResponseBuf>),
+    /// Command was not handled, because of the error
+    Nak(ErrorCode),
+}
+
+impl FrameReply {
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        let (status, response) = payload.split_first()?;
+        match FromPrimitive::from_u8(*status)? {
+            FrameStatus::Ack if response.is_empty() => Some(FrameReply::Ack(None)),
+            FrameStatus::Ack => response.try_into().ok().map(|r| FrameReply::Ack(Some(r))),
+            FrameStatus::Nak => {
+                let code = response.first()?;
+                FromPrimitive::from_u8(*code).map(FrameReply::Nak)
+            }
+        }
+    }
+}
+
+/// A complete and valid frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub seq: u8,
+    pub payload: &'a [u8],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameError {
+    /// Need more data to decode the frame
+    Incomplete,
+    /// Doesn't start with [`FRAME_MAGIC`]
+    BadMagic,
+    /// Length in the header is larger than [`MAX_PAYLOAD_SIZE`]
+    TooLong,
+    /// Frame was sent with an unsupported protocol version
+    BadVersion { seq: u8 },
+    /// Checksum doesn't match, the frame was corrupted
+    BadCrc { seq: u8 },
+}
+
+/// Write a frame with the given payload into `buf`
+///
+/// Returns the number of bytes written.
+pub fn encode_frame(seq: u8, payload: &[u8], buf: &mut [u8]) -> usize {
+    let len = payload.len();
+    assert!(len <= MAX_PAYLOAD_SIZE);
+    buf[..2].copy_from_slice(&FRAME_MAGIC);
+    buf[2] = FRAME_VERSION;
+    buf[3] = seq;
+    buf[4..6].copy_from_slice(&(len as u16).to_le_bytes());
+    buf[FRAME_HEADER_SIZE..FRAME_HEADER_SIZE + len].copy_from_slice(payload);
+    let crc = CRC.checksum(&buf[2..FRAME_HEADER_SIZE + len]);
+    buf[FRAME_HEADER_SIZE + len..FRAME_HEADER_SIZE + len + FRAME_CRC_SIZE]
+        .copy_from_slice(&crc.to_le_bytes());
+    FRAME_HEADER_SIZE + len + FRAME_CRC_SIZE
+}
+
+/// Total size of the frame, based on its header
+pub fn frame_size(header: &[u8]) -> Result<usize, FrameError> {
+    if header.len() < 2 {
+        return Err(FrameError::Incomplete);
+    }
+    if header[..2] != FRAME_MAGIC {
+        return Err(FrameError::BadMagic);
+    }
+    if header.len() < FRAME_HEADER_SIZE {
+        return Err(FrameError::Incomplete);
+    }
+    let len = u16::from_le_bytes([header[4], header[5]]) as usize;
+    if len > MAX_PAYLOAD_SIZE {
+        return Err(FrameError::TooLong);
+    }
+    Ok(FRAME_HEADER_SIZE + len + FRAME_CRC_SIZE)
+}
+
+/// Decode the frame at the start of `buf`
+///
+/// Returns the frame and the number of bytes it takes up in `buf`.
+pub fn decode_frame(buf: &[u8]) -> Result<(Frame<'_>, usize), FrameError> {
+    let size = frame_size(buf)?;
+    if buf.len() < size {
+        return Err(FrameError::Incomplete);
+    }
+    let seq = buf[3];
+    let crc_start = size - FRAME_CRC_SIZE;
+    let expected = u32::from_le_bytes([
+        buf[crc_start],
+        buf[crc_start + 1],
+        buf[crc_start + 2],
+        buf[crc_start + 3],
+    ]);
+    if CRC.checksum(&buf[2..crc_start]) != expected {
+        return Err(FrameError::BadCrc { seq });
+    }
+    if buf[2] != FRAME_VERSION {
+        return Err(FrameError::BadVersion { seq });
+    }
+    let frame = Frame {
+        seq,
+        payload: &buf[FRAME_HEADER_SIZE..crc_start],
+    };
+    Ok((frame, size))
+}
+
+impl Command {
+    /// Serialize the command into a frame
+    ///
+    /// Returns the number of bytes written.
+    pub fn encode_frame(&self, seq: u8, buf: &mut [u8; MAX_FRAME_SIZE]) -> usize {
+        let mut body = [0; MAX_PAYLOAD_SIZE];
+        let len = self.encode_body(&mut body);
+        encode_frame(seq, &body[..len], buf)
+    }
+}
+
+/// Data received by the module
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Received {
+    /// Unframed command, from a host that doesn't support framing
+    Legacy(Vec<u8, MAX_COMMAND_SIZE>),
+    /// Valid frame
+    Frame {
+        seq: u8,
+        payload: Vec<u8, MAX_PAYLOAD_SIZE>,
+    },
+    /// Frame that can't be handled
+    BadFrame { seq: u8 },
+}
+
+impl Received {
+    /// Decode the command and figure out how to reply to it
+    pub fn into_command(self) -> (Reply, Result<Command, ErrorCode>) {
+        match self {
+            Received::Legacy(buf) => (Reply::Legacy, Command::decode(&buf)),
+            Received::Frame { seq, payload } => {
+                (Reply::Framed { seq }, Command::decode_body(&payload))
+            }
+            Received::BadFrame { seq } => (Reply::Framed { seq }, Err(ErrorCode::BadFrame)),
+        }
+    }
+}
+
+/// How to reply to a received command
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Reply {
+    /// Only send the response, if there is one
+    Legacy,
+    /// Always reply with a frame
+    Framed { seq: u8 },
+}
+
+impl Reply {
+    /// Encode the reply to a successfully handled command
+    ///
+    /// Returns the number of bytes written. Might be zero for legacy commands.
+    pub fn ack(&self, response: Option<&ResponseBuf>, buf: &mut [u8; MAX_FRAME_SIZE]) -> usize {
+        match (self, response) {
+            (Reply::Legacy, None) => 0,
+            (Reply::Legacy, Some(response)) => {
+                buf[..RESPONSE_SIZE].copy_from_slice(response);
+                RESPONSE_SIZE
+            }
+            (Reply::Framed { seq }, response) => {
+                let mut payload = [0; 1 + RESPONSE_SIZE];
+                payload[0] = FrameStatus::Ack as u8;
+                let len = if let Some(response) = response {
+                    payload[1..].copy_from_slice(response);
+                    1 + RESPONSE_SIZE
+                } else {
+                    1
+                };
+                encode_frame(*seq, &payload[..len], buf)
+            }
+        }
+    }
+
+    /// Encode the reply to a command that couldn't be handled
+    ///
+    /// Returns the number of bytes written. Legacy commands don't get a reply.
+    pub fn nak(&self, code: ErrorCode, buf: &mut [u8; MAX_FRAME_SIZE]) -> usize {
+        match self {
+            Reply::Legacy => 0,
+            Reply::Framed { seq } => encode_frame(*seq, &[FrameStatus::Nak as u8, code as u8], buf),
+        }
+    }
+
+    /// Encode the reply based on the result of handling the command
+    ///
+    /// Returns the number of bytes written.
+    pub fn respond(&self, result: &CommandResult, buf: &mut [u8; MAX_FRAME_SIZE]) -> usize {
+        match result {
+            Ok(response) => self.ack(response.as_ref(), buf),
+            Err(code) => self.nak(*code, buf),
+        }
+    }
+}
+
+/// Reassembles frames from the bytes read from the serial port
+///
+/// Frames may be split across multiple reads, or multiple frames may arrive
+/// in a single read. Garbage between frames is skipped.
+pub struct FrameDecoder {
+    buf: Vec<u8, { MAX_FRAME_SIZE + MAX_COMMAND_SIZE }>,
+    legacy: Option<Vec<u8, MAX_COMMAND_SIZE>>,
+    /// When data was last pushed, in microseconds
+    last_data: u64,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            legacy: None,
+            last_data: 0,
+        }
+    }
+
+    /// Add the data of a single read from the serial port
+    pub fn push(&mut self, data: &[u8]) {
+        // Legacy commands always come in a single read
+        if self.buf.is_empty() && data.len() >= 3 && data[..2] == MAGIC {
+            self.legacy = Vec::from_slice(&data[..data.len().min(MAX_COMMAND_SIZE)]).ok();
+            return;
+        }
+        if self.buf.extend_from_slice(data).is_err() {
+            // Not a valid frame, start over
+            self.buf.clear();
+            let _ = self
+                .buf
+                .extend_from_slice(&data[..data.len().min(MAX_FRAME_SIZE)]);
+        }
+    }
+
+    /// Add the data of a single read, that arrived at `now` in microseconds
+    ///
+    /// Use together with [`FrameDecoder::expire`].
+    pub fn push_at(&mut self, data: &[u8], now: u64) {
+        self.last_data = now;
+        self.push(data);
+    }
+
+    /// Drop a partial frame, if no data has arrived for [`FRAME_TIMEOUT`]
+    ///
+    /// Otherwise, after the host gave up on a frame, the data that it sends
+    /// next would be taken as the rest of the frame.
+    pub fn expire(&mut self, now: u64) {
+        if !self.buf.is_empty() && now.saturating_sub(self.last_data) > FRAME_TIMEOUT {
+            self.reset();
+        }
+    }
+
+    /// Drop all data that hasn't been decoded yet
+    pub fn reset(&mut self) {
+        self.buf.clear();
+        self.legacy = None;
+    }
+
+    /// Take the next command out of the received data, if there's a complete one
+    pub fn next_received(&mut self) -> Option<Received> {
+        if let Some(legacy) = self.legacy.take() {
+            return Some(Received::Legacy(legacy));
+        }
+        loop {
+            // Skip to the start of the next frame
+            let start = self
+                .buf
+                .iter()
+                .position(|b| *b == FRAME_MAGIC[0])
+                .unwrap_or(self.buf.len());
+            self.consume(start);
+
+            match decode_frame(&self.buf) {
+                Ok((frame, size)) => {
+                    let received = Received::Frame {
+                        seq: frame.seq,
+                        payload: Vec::from_slice(frame.payload).unwrap(),
+                    };
+                    self.consume(size);
+                    return Some(received);
+                }
+                Err(FrameError::Incomplete) => return None,
+                Err(FrameError::BadMagic) | Err(FrameError::TooLong) => self.consume(1),
+                Err(FrameError::BadVersion { seq }) | Err(FrameError::BadCrc { seq }) => {
+                    // Header might be corrupted as well, resync after the magic bytes
+                    self.consume(FRAME_MAGIC.len());
+                    return Some(Received::BadFrame { seq });
+                }
+            }
+        }
+    }
+
+    fn consume(&mut self, count: usize) {
+        let len = self.buf.len();
+        self.buf.copy_within(count..len, 0);
+        self.buf.truncate(len - count);
+    }
+}
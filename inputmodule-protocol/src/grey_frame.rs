@@ -0,0 +1,172 @@
+//! Compressed greyscale frames for the LED Matrix, see [`Command::DrawGreyFrame`]
+//!
+//! Pixels are ordered column by column, in the same order as the columns of
+//! [`Command::StageGreyCol`].
+//!
+//! [`Command::DrawGreyFrame`]: crate::Command::DrawGreyFrame
+//! [`Command::StageGreyCol`]: crate::Command::StageGreyCol
+use heapless::Vec;
+
+use crate::{ErrorCode, HEIGHT, LEDS, WIDTH};
+
+/// Brightness of every LED, column by column
+pub type GreyPixels = [[u8; HEIGHT]; WIDTH];
+
+/// How the pixels of a [`GreyFrame`] are encoded
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum GreyFrameEncoding {
+    /// Brightness of every LED
+    Raw = 0x00,
+    /// Pairs of run length and brightness
+    Rle = 0x01,
+    /// Changes to the currently displayed frame. Segments of the number of
+    /// unchanged pixels and the number of changed pixels, followed by the new
+    /// brightness of the changed pixels. Pixels after the last segment don't change.
+    Delta = 0x02,
+}
+
+/// Encoded greyscale frame
+///
+/// The encoded data is never larger than a raw frame.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GreyFrame {
+    pub encoding: GreyFrameEncoding,
+    pub data: Vec<u8, LEDS>,
+}
+
+impl GreyFrame {
+    /// Encode the frame as small as possible
+    ///
+    /// If the currently displayed frame is known, delta encoding is tried too.
+    pub fn encode(pixels: &GreyPixels, previous: Option<&GreyPixels>) -> Self {
+        let mut best = Self::raw(pixels);
+        let candidates = [
+            Self::rle(pixels),
+            previous.and_then(|previous| Self::delta(pixels, previous)),
+        ];
+        for candidate in candidates.into_iter().flatten() {
+            if candidate.data.len() < best.data.len() {
+                best = candidate;
+            }
+        }
+        best
+    }
+
+    pub fn raw(pixels: &GreyPixels) -> Self {
+        Self {
+            encoding: GreyFrameEncoding::Raw,
+            data: Vec::from_slice(pixels.as_flattened()).unwrap(),
+        }
+    }
+
+    /// Run-length encode the frame
+    ///
+    /// Returns `None` if that's larger than the raw frame.
+    pub fn rle(pixels: &GreyPixels) -> Option<Self> {
+        let pixels = pixels.as_flattened();
+        let mut data = Vec::new();
+        let mut i = 0;
+        while i < LEDS {
+            let value = pixels[i];
+            let run = pixels[i..]
+                .iter()
+                .take(u8::MAX as usize)
+                .take_while(|pixel| **pixel == value)
+                .count();
+            data.push(run as u8).ok()?;
+            data.push(value).ok()?;
+            i += run;
+        }
+        Some(Self {
+            encoding: GreyFrameEncoding::Rle,
+            data,
+        })
+    }
+
+    /// Encode only the pixels that differ from the `previous` frame
+    ///
+    /// Returns `None` if that's larger than the raw frame.
+    pub fn delta(pixels: &GreyPixels, previous: &GreyPixels) -> Option<Self> {
+        let pixels = pixels.as_flattened();
+        let previous = previous.as_flattened();
+        let run = |start: usize, changed: bool| {
+            (start..LEDS)
+                .take(u8::MAX as usize)
+                .take_while(|&i| (pixels[i] != previous[i]) == changed)
+                .count()
+        };
+
+        let mut data = Vec::new();
+        let mut i = 0;
+        while i < LEDS {
+            let unchanged = run(i, false);
+            i += unchanged;
+            if i == LEDS {
+                break;
+            }
+            let changed = run(i, true);
+            data.push(unchanged as u8).ok()?;
+            data.push(changed as u8).ok()?;
+            data.extend_from_slice(&pixels[i..i + changed]).ok()?;
+            i += changed;
+        }
+        Some(Self {
+            encoding: GreyFrameEncoding::Delta,
+            data,
+        })
+    }
+
+    /// Decode the frame into `pixels`
+    ///
+    /// For delta encoding, `pixels` must hold the currently displayed frame.
+    /// If the data is invalid, `pixels` may be partially changed.
+    pub fn decode(&self, pixels: &mut GreyPixels) -> Result<(), ErrorCode> {
+        let pixels = pixels.as_flattened_mut();
+        match self.encoding {
+            GreyFrameEncoding::Raw => {
+                if self.data.len() != LEDS {
+                    return Err(ErrorCode::BadLength);
+                }
+                pixels.copy_from_slice(&self.data);
+            }
+            GreyFrameEncoding::Rle => {
+                let (pairs, remainder) = self.data.as_chunks::<2>();
+                if !remainder.is_empty() {
+                    return Err(ErrorCode::BadLength);
+                }
+                let mut i = 0;
+                for [run, value] in pairs {
+                    let (run, value) = (*run as usize, *value);
+                    pixels
+                        .get_mut(i..i + run)
+                        .ok_or(ErrorCode::BadLength)?
+                        .fill(value);
+                    i += run;
+                }
+                if i != LEDS {
+                    return Err(ErrorCode::BadLength);
+                }
+            }
+            GreyFrameEncoding::Delta => {
+                let mut i = 0;
+                let mut data = &self.data[..];
+                while !data.is_empty() {
+                    let [unchanged, changed, ref rest @ ..] = *data else {
+                        return Err(ErrorCode::BadLength);
+                    };
+                    let changed = changed as usize;
+                    i += unchanged as usize;
+                    let values = rest.get(..changed).ok_or(ErrorCode::BadLength)?;
+                    pixels
+                        .get_mut(i..i + changed)
+                        .ok_or(ErrorCode::BadLength)?
+                        .copy_from_slice(values);
+                    i += changed;
+                    data = &rest[changed..];
+                }
+            }
+        }
+        Ok(())
+    }
+}
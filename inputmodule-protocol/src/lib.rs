@@ -0,0 +1,52 @@
+//! Serial protocol spoken between the input module firmware and the host
+//!
+//! Shared by the firmware (`fl16-inputmodules`) and the host tool
+//! (`inputmodule-control`), so that both sides agree on command IDs and
+//! payload layouts.
+//!
+//! See `commands.md` in the repository root for the documentation of every
+//! command.
+#![no_std]
+
+mod capabilities;
+mod command;
+mod error;
+mod frame;
+mod grey_frame;
+mod response;
+
+pub use capabilities::*;
+pub use command::*;
+pub use error::*;
+pub use frame::*;
+pub use grey_frame::*;
+pub use response::*;
+
+/// Magic bytes at the start of every legacy (unframed) command
+pub const MAGIC: [u8; 2] = [0x32, 0xAC];
+
+/// Maximum size of a single command, including magic and command ID.
+/// Equal to the size of a USB full-speed bulk packet.
+pub const MAX_COMMAND_SIZE: usize = 64;
+
+/// LED Matrix width in pixels
+pub const WIDTH: usize = 9;
+/// LED Matrix height in pixels
+pub const HEIGHT: usize = 34;
+/// Number of LEDs on the LED Matrix
+pub const LEDS: usize = WIDTH * HEIGHT;
+/// Bytes needed to represent all LEDs with a single bit
+/// math.ceil(WIDTH * HEIGHT / 8)
+pub const DRAW_BYTES: usize = 39;
+
+/// Number of entries of a gamma lookup table, one for every brightness value
+pub const GAMMA_LUT_SIZE: usize = 256;
+
+/// Maximum number of frames of a custom animation on the LED Matrix
+pub const MAX_ANIMATION_FRAMES: usize = 32;
+
+/// Maximum length of scrolling text on the LED Matrix, in bytes
+pub const MAX_SCROLL_TEXT_LEN: usize = 128;
+
+/// Bytes needed for one 400 pixel column of the B1 Display (400/8)
+pub const PIXEL_COLUMN_BYTES: usize = 50;
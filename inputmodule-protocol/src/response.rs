@@ -0,0 +1,238 @@
+//! Responses sent from the module back to the host
+use num_traits::FromPrimitive;
+
+use crate::{
+    Capabilities, Command, DisplayMode, GameVal, GammaCurve, Orientation, PwmFreqArg, Side,
+};
+
+/// Size of every response. Unused bytes are zero.
+pub const RESPONSE_SIZE: usize = 32;
+
+pub type ResponseBuf = [u8; RESPONSE_SIZE];
+
+/// State of the embedded game, as reported by [`Command::GameStatus`]
+///
+/// Fields that don't apply to the current game are zero.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GameStatus {
+    /// Game that is running or just ended, `None` if there is no game
+    pub game: Option<GameVal>,
+    pub game_over: bool,
+    pub score: u16,
+    /// Level in Tetris, ball speed in Pong
+    pub level: u16,
+    /// Length of the snake, including its head
+    pub snake_length: u16,
+    /// Points of the upper and lower Pong player
+    pub pong_score: [u8; 2],
+}
+
+/// Game ID in the response when there is no game
+const NO_GAME: u8 = 0xFF;
+
+impl GameStatus {
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = self.game.map_or(NO_GAME, |game| game as u8);
+        buf[1] = self.game_over as u8;
+        buf[2..4].copy_from_slice(&self.score.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.level.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.snake_length.to_le_bytes());
+        buf[8..10].copy_from_slice(&self.pong_score);
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        let game = if buf[0] == NO_GAME {
+            None
+        } else {
+            Some(FromPrimitive::from_u8(buf[0])?)
+        };
+        Some(Self {
+            game,
+            game_over: buf[1] == 1,
+            score: u16::from_le_bytes([buf[2], buf[3]]),
+            level: u16::from_le_bytes([buf[4], buf[5]]),
+            snake_length: u16::from_le_bytes([buf[6], buf[7]]),
+            pong_score: [buf[8], buf[9]],
+        })
+    }
+}
+
+/// Firmware version, as reported by [`Command::Version`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Version {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    pub pre_release: bool,
+}
+
+impl Version {
+    /// Build the version from the USB BCD device release (0xMMmp)
+    pub fn from_bcd(bcd: u16, pre_release: bool) -> Self {
+        let [major, minor_patch] = bcd.to_be_bytes();
+        Self {
+            major,
+            minor: (minor_patch & 0xF0) >> 4,
+            patch: minor_patch & 0x0F,
+            pre_release,
+        }
+    }
+}
+
+/// Response to a getter command
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Response {
+    Brightness(u8),
+    Sleeping(bool),
+    Animate(bool),
+    PlayingCustomAnimation(bool),
+    ScrollingText(bool),
+    /// Animation period in milliseconds
+    AnimationPeriod(u16),
+    PwmFreq(PwmFreqArg),
+    DebugMode(bool),
+    Gamma(GammaCurve),
+    Orientation(Orientation),
+    DisplayOn(bool),
+    InvertScreen(bool),
+    ScreenSaver(bool),
+    Fps(u8),
+    PowerMode(DisplayMode),
+    Color([u8; 3]),
+    Side(Side),
+    Version(Version),
+    Capabilities(Capabilities),
+    GameStatus(GameStatus),
+}
+
+impl Response {
+    /// Serialize the response into the fixed size buffer sent over the wire
+    pub fn encode(&self) -> ResponseBuf {
+        let mut response: ResponseBuf = [0; RESPONSE_SIZE];
+        match self {
+            Response::Brightness(val) | Response::Fps(val) => response[0] = *val,
+            Response::Sleeping(val)
+            | Response::Animate(val)
+            | Response::PlayingCustomAnimation(val)
+            | Response::ScrollingText(val)
+            | Response::DebugMode(val)
+            | Response::DisplayOn(val)
+            | Response::InvertScreen(val)
+            | Response::ScreenSaver(val) => response[0] = *val as u8,
+            Response::AnimationPeriod(period) => {
+                response[0..2].copy_from_slice(&period.to_le_bytes())
+            }
+            Response::PwmFreq(freq) => response[0] = *freq as u8,
+            Response::Gamma(curve) => response[0] = *curve as u8,
+            Response::Orientation(orientation) => response[0] = *orientation as u8,
+            Response::PowerMode(mode) => response[0] = *mode as u8,
+            Response::Color(rgb) => response[0..3].copy_from_slice(rgb),
+            Response::Side(side) => response[0] = side.is_right() as u8,
+            Response::Version(version) => {
+                response[0] = version.major;
+                response[1] = (version.minor << 4) | (version.patch & 0x0F);
+                response[2] = version.pre_release as u8;
+            }
+            Response::Capabilities(capabilities) => capabilities.encode(&mut response),
+            Response::GameStatus(status) => status.encode(&mut response),
+        }
+        response
+    }
+}
+
+impl Command {
+    /// Whether the module sends a response to this command
+    pub fn has_response(&self) -> bool {
+        self.response_kind().is_some()
+    }
+
+    /// Parse the response the module sent back for this command
+    ///
+    /// Returns `None` if the command doesn't have a response or the response
+    /// is invalid.
+    pub fn decode_response(&self, buf: &[u8]) -> Option<Response> {
+        if buf.len() < RESPONSE_SIZE {
+            return None;
+        }
+        let val = buf[0];
+        let response = match self.response_kind()? {
+            ResponseKind::Brightness => Response::Brightness(val),
+            ResponseKind::Sleeping => Response::Sleeping(val == 1),
+            ResponseKind::Animate => Response::Animate(val == 1),
+            ResponseKind::PlayingCustomAnimation => Response::PlayingCustomAnimation(val == 1),
+            ResponseKind::ScrollingText => Response::ScrollingText(val == 1),
+            ResponseKind::AnimationPeriod => {
+                Response::AnimationPeriod(u16::from_le_bytes([buf[0], buf[1]]))
+            }
+            ResponseKind::PwmFreq => Response::PwmFreq(FromPrimitive::from_u8(val)?),
+            ResponseKind::DebugMode => Response::DebugMode(val == 1),
+            ResponseKind::Gamma => Response::Gamma(FromPrimitive::from_u8(val)?),
+            ResponseKind::Orientation => Response::Orientation(FromPrimitive::from_u8(val)?),
+            ResponseKind::DisplayOn => Response::DisplayOn(val == 1),
+            ResponseKind::InvertScreen => Response::InvertScreen(val == 1),
+            ResponseKind::ScreenSaver => Response::ScreenSaver(val == 1),
+            ResponseKind::Fps => Response::Fps(val),
+            ResponseKind::PowerMode => Response::PowerMode(FromPrimitive::from_u8(val)?),
+            ResponseKind::Color => Response::Color([buf[0], buf[1], buf[2]]),
+            ResponseKind::Side => Response::Side(if val == 0 { Side::Left } else { Side::Right }),
+            ResponseKind::Version => Response::Version(Version {
+                major: buf[0],
+                minor: (buf[1] & 0xF0) >> 4,
+                patch: buf[1] & 0x0F,
+                pre_release: buf[2] == 1,
+            }),
+            ResponseKind::Capabilities => Response::Capabilities(Capabilities::decode(buf)?),
+            ResponseKind::GameStatus => Response::GameStatus(GameStatus::decode(buf)?),
+        };
+        Some(response)
+    }
+
+    fn response_kind(&self) -> Option<ResponseKind> {
+        match self {
+            Command::GetBrightness => Some(ResponseKind::Brightness),
+            Command::IsSleeping => Some(ResponseKind::Sleeping),
+            Command::GetAnimate => Some(ResponseKind::Animate),
+            Command::IsPlayingCustomAnimation => Some(ResponseKind::PlayingCustomAnimation),
+            Command::IsScrollingText => Some(ResponseKind::ScrollingText),
+            Command::GetAnimationPeriod => Some(ResponseKind::AnimationPeriod),
+            Command::GetPwmFreq => Some(ResponseKind::PwmFreq),
+            Command::GetDebugMode => Some(ResponseKind::DebugMode),
+            Command::GetGamma => Some(ResponseKind::Gamma),
+            Command::GetOrientation => Some(ResponseKind::Orientation),
+            Command::GetDisplayOn => Some(ResponseKind::DisplayOn),
+            Command::GetInvertScreen => Some(ResponseKind::InvertScreen),
+            Command::GetScreenSaver => Some(ResponseKind::ScreenSaver),
+            Command::GetFps => Some(ResponseKind::Fps),
+            Command::GetPowerMode => Some(ResponseKind::PowerMode),
+            Command::GetColor => Some(ResponseKind::Color),
+            Command::GetSide => Some(ResponseKind::Side),
+            Command::Version => Some(ResponseKind::Version),
+            Command::GetCapabilities => Some(ResponseKind::Capabilities),
+            Command::GameStatus => Some(ResponseKind::GameStatus),
+            _ => None,
+        }
+    }
+}
+
+enum ResponseKind {
+    Brightness,
+    Sleeping,
+    Animate,
+    PlayingCustomAnimation,
+    ScrollingText,
+    AnimationPeriod,
+    PwmFreq,
+    DebugMode,
+    Gamma,
+    Orientation,
+    DisplayOn,
+    InvertScreen,
+    ScreenSaver,
+    Fps,
+    PowerMode,
+    Color,
+    Side,
+    Version,
+    Capabilities,
+    GameStatus,
+}
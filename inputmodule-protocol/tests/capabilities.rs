@@ -0,0 +1,67 @@
+use inputmodule_protocol::*;
+
+const CAPABILITIES: Capabilities = Capabilities {
+    module: ModuleType::LedMatrix,
+    protocol_version: FRAME_VERSION,
+    width: WIDTH as u16,
+    height: HEIGHT as u16,
+    commands: command_bits(&[
+        CommandVals::Brightness,
+        CommandVals::Pattern,
+        CommandVals::StartGame,
+        CommandVals::SetAddonAnimation,
+        CommandVals::GetCapabilities,
+    ]),
+    games: game_bits(&[GameVal::Snake, GameVal::GameOfLife]),
+    patterns: pattern_bits(&[PatternVals::ZigZag]),
+    addon_animations: addon_animation_bits(&[AddonAnimationVals::Helix]),
+};
+
+#[test]
+fn capabilities_roundtrip() {
+    let response = Response::Capabilities(CAPABILITIES);
+    assert_eq!(
+        Command::GetCapabilities.decode_response(&response.encode()),
+        Some(response)
+    );
+}
+
+#[test]
+fn supported_commands() {
+    assert!(CAPABILITIES.supports(&Command::SetBrightness(10)));
+    assert!(CAPABILITIES.supports(&Command::GetBrightness));
+    assert!(!CAPABILITIES.supports(&Command::Draw([0; DRAW_BYTES])));
+
+    // Parameters are checked as well
+    assert!(CAPABILITIES.supports(&Command::Pattern(PatternVals::ZigZag)));
+    assert!(!CAPABILITIES.supports(&Command::Pattern(PatternVals::Gradient)));
+    assert!(!CAPABILITIES.supports(&Command::Percentage(50)));
+    assert!(CAPABILITIES.supports(&Command::StartGame(Game::GameOfLife(
+        GameOfLifeStartParam::Glider.into()
+    ))));
+    assert!(!CAPABILITIES.supports(&Command::StartGame(Game::Pong(PongParam::default()))));
+    assert!(CAPABILITIES.supports(&Command::SetAddonAnimation(AddonAnimationVals::Helix)));
+    assert!(!CAPABILITIES.supports(&Command::SetAddonAnimation(AddonAnimationVals::Spiral)));
+}
+
+#[test]
+fn list_capabilities() {
+    assert_eq!(
+        CAPABILITIES.commands().collect::<Vec<_>>(),
+        [
+            CommandVals::Brightness,
+            CommandVals::Pattern,
+            CommandVals::StartGame,
+            CommandVals::GetCapabilities,
+            CommandVals::SetAddonAnimation,
+        ]
+    );
+    assert_eq!(
+        CAPABILITIES.games().collect::<Vec<_>>(),
+        [GameVal::Snake, GameVal::GameOfLife]
+    );
+    assert_eq!(
+        CAPABILITIES.patterns().collect::<Vec<_>>(),
+        [PatternVals::ZigZag]
+    );
+}
@@ -0,0 +1,210 @@
+use inputmodule_protocol::*;
+
+fn frame(command: &Command, seq: u8) -> Vec<u8> {
+    let mut buf = [0; MAX_FRAME_SIZE];
+    let len = command.encode_frame(seq, &mut buf);
+    buf[..len].to_vec()
+}
+
+fn decode_all(decoder: &mut FrameDecoder) -> Vec<(Reply, Result<Command, ErrorCode>)> {
+    std::iter::from_fn(|| decoder.next_received())
+        .map(Received::into_command)
+        .collect()
+}
+
+#[test]
+fn single_frame() {
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&frame(&Command::SetBrightness(10), 7));
+    assert_eq!(
+        decode_all(&mut decoder),
+        [(Reply::Framed { seq: 7 }, Ok(Command::SetBrightness(10)))]
+    );
+}
+
+#[test]
+fn split_and_packed_frames() {
+    let draw = Command::Draw([0x55; DRAW_BYTES]);
+    let mut data = frame(&draw, 1);
+    data.extend(frame(&Command::GetBrightness, 2));
+    data.extend(frame(&Command::Version, 3));
+
+    // Split at every possible position
+    for split in 0..data.len() {
+        let mut decoder = FrameDecoder::new();
+        decoder.push(&data[..split]);
+        let mut received = decode_all(&mut decoder);
+        decoder.push(&data[split..]);
+        received.extend(decode_all(&mut decoder));
+
+        let commands: Vec<_> = received.into_iter().map(|(_, c)| c.unwrap()).collect();
+        assert_eq!(
+            commands,
+            [draw.clone(), Command::GetBrightness, Command::Version]
+        );
+    }
+}
+
+#[test]
+fn skip_garbage() {
+    let mut data = vec![0x00, 0x32, 0xFF, 0x32];
+    data.extend(frame(&Command::SetAnimate(true), 4));
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&data);
+    assert_eq!(
+        decode_all(&mut decoder),
+        [(Reply::Framed { seq: 4 }, Ok(Command::SetAnimate(true)))]
+    );
+}
+
+#[test]
+fn corrupted_frame() {
+    let mut data = frame(&Command::SetBrightness(10), 5);
+    data[FRAME_HEADER_SIZE + 1] ^= 0x01;
+    data.extend(frame(&Command::SetBrightness(20), 6));
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&data);
+    assert_eq!(
+        decode_all(&mut decoder),
+        [
+            (Reply::Framed { seq: 5 }, Err(ErrorCode::BadFrame)),
+            (Reply::Framed { seq: 6 }, Ok(Command::SetBrightness(20))),
+        ]
+    );
+}
+
+#[test]
+fn legacy_command() {
+    let mut buf = [0; MAX_COMMAND_SIZE];
+    let len = Command::SetBrightness(30).encode(&mut buf);
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&buf[..len]);
+    assert_eq!(
+        decode_all(&mut decoder),
+        [(Reply::Legacy, Ok(Command::SetBrightness(30)))]
+    );
+}
+
+#[test]
+fn replies() {
+    let mut buf = [0; MAX_FRAME_SIZE];
+    let response = Response::Brightness(40).encode();
+
+    let len = Reply::Framed { seq: 9 }.ack(Some(&response), &mut buf);
+    let (frame, size) = decode_frame(&buf[..len]).unwrap();
+    assert_eq!((frame.seq, size), (9, len));
+    assert_eq!(
+        FrameReply::decode(frame.payload),
+        Some(FrameReply::Ack(Some(response)))
+    );
+
+    let len = Reply::Framed { seq: 10 }.ack(None, &mut buf);
+    let (frame, _) = decode_frame(&buf[..len]).unwrap();
+    assert_eq!(
+        FrameReply::decode(frame.payload),
+        Some(FrameReply::Ack(None))
+    );
+
+    let len = Reply::Framed { seq: 11 }.nak(ErrorCode::BadArgument, &mut buf);
+    let (frame, _) = decode_frame(&buf[..len]).unwrap();
+    assert_eq!(
+        FrameReply::decode(frame.payload),
+        Some(FrameReply::Nak(ErrorCode::BadArgument))
+    );
+
+    // Legacy hosts only get the response
+    assert_eq!(Reply::Legacy.ack(Some(&response), &mut buf), RESPONSE_SIZE);
+    assert_eq!(&buf[..RESPONSE_SIZE], &response);
+    assert_eq!(Reply::Legacy.ack(None, &mut buf), 0);
+    assert_eq!(Reply::Legacy.nak(ErrorCode::Unsupported, &mut buf), 0);
+}
+
+#[test]
+fn large_command() {
+    let mut lut = [0; GAMMA_LUT_SIZE];
+    for (i, val) in lut.iter_mut().enumerate() {
+        *val = (i / 2) as u8;
+    }
+    let command = Command::SetGammaLut(lut);
+    assert!(command.needs_frame());
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&frame(&command, 4));
+    assert_eq!(
+        decode_all(&mut decoder),
+        [(Reply::Framed { seq: 4 }, Ok(command))]
+    );
+}
+
+#[test]
+fn long_scroll_text() {
+    let mut text = heapless::String::new();
+    while text.push_str("Hello ").is_ok() {}
+    let command = Command::ScrollText {
+        speed: 8,
+        direction: ScrollDirection::Down,
+        loops: 0,
+        text,
+    };
+    assert!(command.needs_frame());
+
+    let mut decoder = FrameDecoder::new();
+    decoder.push(&frame(&command, 5));
+    assert_eq!(
+        decode_all(&mut decoder),
+        [(Reply::Framed { seq: 5 }, Ok(command))]
+    );
+}
+
+#[test]
+fn cut_off_frame_expires() {
+    let grey = [[0x12; HEIGHT]; WIDTH];
+    let command = Command::DrawGreyFrame(GreyFrame::raw(&grey));
+    let data = frame(&command, 6);
+    assert!(data.len() > 64);
+    let mut buf = [0; MAX_COMMAND_SIZE];
+    let len = Command::SetBrightness(10).encode(&mut buf);
+
+    // Rest of the frame arrives in time
+    let mut decoder = FrameDecoder::new();
+    decoder.push_at(&data[..64], 1_000);
+    decoder.expire(1_000 + FRAME_TIMEOUT);
+    decoder.push_at(&data[64..], 1_000 + FRAME_TIMEOUT);
+    assert_eq!(
+        decode_all(&mut decoder),
+        [(Reply::Framed { seq: 6 }, Ok(command))]
+    );
+
+    // Host gave up on the frame and only sends legacy commands now
+    let mut decoder = FrameDecoder::new();
+    decoder.push_at(&data[..64], 1_000);
+    assert_eq!(decode_all(&mut decoder), []);
+    decoder.expire(2_000 + FRAME_TIMEOUT);
+    decoder.push_at(&buf[..len], 2_000 + FRAME_TIMEOUT);
+    assert_eq!(
+        decode_all(&mut decoder),
+        [(Reply::Legacy, Ok(Command::SetBrightness(10)))]
+    );
+}
+
+#[test]
+fn legacy_magic_inside_frame() {
+    // Second USB packet of the frame starts like a legacy BootloaderReset
+    let mut lut = [0x20; GAMMA_LUT_SIZE];
+    let start = 64 - FRAME_HEADER_SIZE - 1;
+    lut[start..start + 3].copy_from_slice(&[MAGIC[0], MAGIC[1], 0x02]);
+    let command = Command::SetGammaLut(lut);
+    let data = frame(&command, 8);
+    assert_eq!(data[64..67], [0x32, 0xAC, 0x02]);
+
+    let mut decoder = FrameDecoder::new();
+    let mut received = vec![];
+    for packet in data.chunks(64) {
+        decoder.push(packet);
+        received.extend(decode_all(&mut decoder));
+    }
+    assert_eq!(received, [(Reply::Framed { seq: 8 }, Ok(command))]);
+}
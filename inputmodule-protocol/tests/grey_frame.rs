@@ -0,0 +1,100 @@
+use inputmodule_protocol::*;
+
+fn gradient() -> GreyPixels {
+    let mut pixels = [[0; HEIGHT]; WIDTH];
+    for (x, col) in pixels.iter_mut().enumerate() {
+        for (y, pixel) in col.iter_mut().enumerate() {
+            *pixel = (x * HEIGHT + y) as u8;
+        }
+    }
+    pixels
+}
+
+fn decoded(frame: &GreyFrame, mut pixels: GreyPixels) -> GreyPixels {
+    frame.decode(&mut pixels).unwrap();
+    pixels
+}
+
+#[test]
+fn encodings_roundtrip() {
+    let pixels = gradient();
+    let mut previous = pixels;
+    previous[4][10] = 0xFF;
+    previous[8][33] = 0xFF;
+
+    let raw = GreyFrame::raw(&pixels);
+    assert_eq!(raw.data.len(), LEDS);
+    assert_eq!(decoded(&raw, [[0; HEIGHT]; WIDTH]), pixels);
+
+    let delta = GreyFrame::delta(&pixels, &previous).unwrap();
+    assert_eq!(delta.data.len(), 2 * 3);
+    assert_eq!(decoded(&delta, previous), pixels);
+
+    // Every pixel is different, run-length encoding doesn't help
+    assert_eq!(GreyFrame::rle(&pixels), None);
+
+    let blank = [[0; HEIGHT]; WIDTH];
+    let rle = GreyFrame::rle(&blank).unwrap();
+    // Runs are at most 255 long
+    assert_eq!(&rle.data[..], &[255, 0, (LEDS - 255) as u8, 0]);
+    assert_eq!(decoded(&rle, pixels), blank);
+}
+
+#[test]
+fn encode_picks_smallest() {
+    let pixels = gradient();
+    assert_eq!(
+        GreyFrame::encode(&pixels, None).encoding,
+        GreyFrameEncoding::Raw
+    );
+    assert_eq!(
+        GreyFrame::encode(&pixels, Some(&pixels)).encoding,
+        GreyFrameEncoding::Delta
+    );
+    assert_eq!(
+        GreyFrame::encode(&[[7; HEIGHT]; WIDTH], Some(&pixels)).encoding,
+        GreyFrameEncoding::Rle
+    );
+}
+
+#[test]
+fn invalid_frames() {
+    let mut pixels = gradient();
+    let frame = |encoding, data: &[u8]| GreyFrame {
+        encoding,
+        data: heapless::Vec::from_slice(data).unwrap(),
+    };
+    let cases = [
+        frame(GreyFrameEncoding::Raw, &[1, 2, 3]),
+        // Doesn't cover all pixels
+        frame(GreyFrameEncoding::Rle, &[10, 0]),
+        // Covers too many pixels
+        frame(GreyFrameEncoding::Rle, &[255, 0, 255, 0]),
+        frame(GreyFrameEncoding::Rle, &[10]),
+        // Fewer values than changed pixels
+        frame(GreyFrameEncoding::Delta, &[0, 3, 1, 2]),
+        frame(GreyFrameEncoding::Delta, &[255, 255]),
+    ];
+    for case in cases {
+        assert_eq!(case.decode(&mut pixels), Err(ErrorCode::BadLength));
+    }
+}
+
+#[test]
+fn raw_frame_needs_framing() {
+    let raw = Command::DrawGreyFrame(GreyFrame::raw(&gradient()));
+    assert!(raw.needs_frame());
+    let rle = Command::DrawGreyFrame(GreyFrame::rle(&[[0; HEIGHT]; WIDTH]).unwrap());
+    assert!(!rle.needs_frame());
+    assert!(!Command::Draw([0; DRAW_BYTES]).needs_frame());
+
+    let mut buf = [0; MAX_FRAME_SIZE];
+    let len = raw.encode_frame(1, &mut buf);
+    let mut decoder = FrameDecoder::new();
+    // Arrives in multiple USB packets
+    for packet in buf[..len].chunks(64) {
+        decoder.push(packet);
+    }
+    let (_, command) = decoder.next_received().unwrap().into_command();
+    assert_eq!(command, Ok(raw));
+}
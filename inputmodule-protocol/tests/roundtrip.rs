@@ -0,0 +1,287 @@
+use inputmodule_protocol::*;
+
+fn roundtrip(command: Command) {
+    let mut buf = [0; MAX_COMMAND_SIZE];
+    let len = command.encode(&mut buf);
+    assert_eq!(Command::decode(&buf[..len]), Ok(command));
+}
+
+#[test]
+fn commands_roundtrip() {
+    let mut text = heapless::String::new();
+    text.push_str("Hello").unwrap();
+
+    let commands = [
+        Command::Keypress {
+            keycode: 0x1234_5678,
+            pressed: true,
+        },
+        Command::SetAddonAnimation(AddonAnimationVals::Helix),
+        Command::StopAddonAnimation,
+        Command::SetSide(Side::Left),
+        Command::SetSide(Side::Right),
+        Command::GetSide,
+        Command::GetBrightness,
+        Command::SetBrightness(0x42),
+        Command::Pattern(PatternVals::ZigZag),
+        Command::Percentage(33),
+        Command::BootloaderReset,
+        Command::Sleep(true),
+        Command::IsSleeping,
+        Command::SetAnimate(false),
+        Command::GetAnimate,
+        Command::Panic,
+        Command::Draw([0xA5; DRAW_BYTES]),
+        Command::StageGreyCol(8, [7; HEIGHT]),
+        Command::DrawGreyColBuffer,
+        Command::DrawGreyFrame(GreyFrame::rle(&[[3; HEIGHT]; WIDTH]).unwrap()),
+        Command::SetText(text),
+        Command::StartGame(Game::Snake(SnakeParam::default())),
+        Command::StartGame(Game::Snake(SnakeParam {
+            wrap: true,
+            speed_up: false,
+        })),
+        Command::StartGame(Game::Pong(PongParam::default())),
+        Command::StartGame(Game::Pong(PongParam {
+            cpu: Some(PongPaddle::Lower),
+            difficulty: PongDifficulty::Hard,
+        })),
+        Command::StartGame(Game::Tetris),
+        Command::StartGame(Game::GameOfLife(GameOfLifeStartParam::Glider.into())),
+        Command::StartGame(Game::GameOfLife(GameOfLifeParam {
+            start: GameOfLifeStartParam::Upload,
+            edges: LifeEdges::Bounded,
+            rule: LifeRule::DAY_AND_NIGHT,
+            age_brightness: true,
+            reseed: true,
+            seed: [0xA5; DRAW_BYTES],
+        })),
+        Command::GameControl(GameControlArg::SecondRight),
+        Command::GameStatus,
+        Command::Version,
+        Command::GetCapabilities,
+        Command::SaveSettings,
+        Command::LoadSettings,
+        Command::ResetSettings,
+        Command::StoreAnimationFrame {
+            index: 3,
+            delay: 500,
+        },
+        Command::PlayCustomAnimation(true),
+        Command::IsPlayingCustomAnimation,
+        Command::GetColor,
+        Command::SetColor([1, 2, 3]),
+        Command::DisplayOn(true),
+        Command::GetDisplayOn,
+        Command::InvertScreen(true),
+        Command::GetInvertScreen,
+        Command::SetPixelColumn(299, [0x0F; PIXEL_COLUMN_BYTES]),
+        Command::FlushFramebuffer,
+        Command::ClearRam,
+        Command::ScreenSaver(false),
+        Command::GetScreenSaver,
+        Command::SetFps(5),
+        Command::GetFps,
+        Command::SetPowerMode(DisplayMode::Hpm),
+        Command::GetPowerMode,
+        Command::SetAnimationPeriod(1000),
+        Command::GetAnimationPeriod,
+        Command::SetPwmFreq(PwmFreqArg::P900),
+        Command::GetPwmFreq,
+        Command::SetDebugMode(true),
+        Command::GetDebugMode,
+        Command::SetGamma(GammaCurve::Cie),
+        Command::GetGamma,
+        Command::SetOrientation(Orientation::MirrorY),
+        Command::GetOrientation,
+        Command::ScrollText {
+            speed: 16,
+            direction: ScrollDirection::Up,
+            loops: 2,
+            text: heapless::String::try_from("Hi 2 you!").unwrap(),
+        },
+        Command::IsScrollingText,
+    ];
+    for command in commands {
+        roundtrip(command);
+    }
+}
+
+#[test]
+fn keypress_wire_format() {
+    let mut buf = [0; MAX_COMMAND_SIZE];
+    let command = Command::Keypress {
+        keycode: 0x0102_0304,
+        pressed: true,
+    };
+    let len = command.encode(&mut buf);
+    assert_eq!(
+        &buf[..len],
+        &[0x32, 0xAC, 0x30, 0x04, 0x03, 0x02, 0x01, 0x01]
+    );
+}
+
+#[test]
+fn games_without_parameters() {
+    // Older hosts only send the game ID
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x10, 0x00]),
+        Ok(Command::StartGame(Game::Snake(SnakeParam::default())))
+    );
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x10, 0x01]),
+        Ok(Command::StartGame(Game::Pong(PongParam::default())))
+    );
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x10, 0x03, 0x05]),
+        Ok(Command::StartGame(Game::GameOfLife(
+            GameOfLifeStartParam::Glider.into()
+        )))
+    );
+}
+
+#[test]
+fn life_rules() {
+    assert_eq!(LifeRule::parse("B3/S23"), Some(LifeRule::CONWAY));
+    assert_eq!(LifeRule::parse("b36/s23"), Some(LifeRule::HIGHLIFE));
+    assert_eq!(LifeRule::parse("B2/S"), Some(LifeRule::SEEDS));
+    assert_eq!(
+        LifeRule::parse("Day-and-Night"),
+        Some(LifeRule::DAY_AND_NIGHT)
+    );
+    assert_eq!(LifeRule::parse("B9/S23"), None);
+    assert_eq!(LifeRule::parse("S23/B3"), None);
+    assert_eq!(LifeRule::parse("B3S23"), None);
+}
+
+#[test]
+fn text_cut_between_characters() {
+    // 62 bytes, the limit of 60 is in the middle of a character
+    let mut text = heapless::String::new();
+    text.push_str("ab").unwrap();
+    for _ in 0..20 {
+        text.push('€').unwrap();
+    }
+    let mut buf = [0; MAX_COMMAND_SIZE];
+    let len = Command::SetText(text).encode(&mut buf);
+
+    let mut expected = heapless::String::new();
+    expected.push_str("ab").unwrap();
+    for _ in 0..19 {
+        expected.push('€').unwrap();
+    }
+    assert_eq!(Command::decode(&buf[..len]), Ok(Command::SetText(expected)));
+}
+
+#[test]
+fn invalid_commands() {
+    // Wrong magic
+    assert_eq!(
+        Command::decode(&[0x32, 0xAB, 0x00]),
+        Err(ErrorCode::BadFrame)
+    );
+    // Unknown command
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0xFF]),
+        Err(ErrorCode::UnknownCommand)
+    );
+    // Too short
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x30, 0x01]),
+        Err(ErrorCode::BadLength)
+    );
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x06, 0xFF]),
+        Err(ErrorCode::BadLength)
+    );
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x10, 0x03]),
+        Err(ErrorCode::BadLength)
+    );
+    // Invalid argument
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x01, 0xFF]),
+        Err(ErrorCode::BadArgument)
+    );
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x10, 0xFF]),
+        Err(ErrorCode::BadArgument)
+    );
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x10, 0x01, 0x03]),
+        Err(ErrorCode::BadArgument)
+    );
+    // Uploaded Game of Life seed is missing
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x10, 0x03, 0x07, 0x00, 0x08, 0x00, 0x0C, 0x00, 0x00]),
+        Err(ErrorCode::BadLength)
+    );
+    // Text isn't valid UTF-8
+    assert_eq!(
+        Command::decode(&[0x32, 0xAC, 0x2B, 0x10, 0x00, 0x00, 0xFF]),
+        Err(ErrorCode::BadArgument)
+    );
+}
+
+#[test]
+fn responses_roundtrip() {
+    let pairs = [
+        (Command::GetBrightness, Response::Brightness(0x80)),
+        (Command::IsSleeping, Response::Sleeping(true)),
+        (Command::GetAnimate, Response::Animate(true)),
+        (
+            Command::IsPlayingCustomAnimation,
+            Response::PlayingCustomAnimation(true),
+        ),
+        (Command::IsScrollingText, Response::ScrollingText(true)),
+        (Command::GetAnimationPeriod, Response::AnimationPeriod(1234)),
+        (Command::GetPwmFreq, Response::PwmFreq(PwmFreqArg::P3k6)),
+        (Command::GetDebugMode, Response::DebugMode(false)),
+        (Command::GetGamma, Response::Gamma(GammaCurve::Gamma22)),
+        (
+            Command::GetOrientation,
+            Response::Orientation(Orientation::Rotate180),
+        ),
+        (Command::GetDisplayOn, Response::DisplayOn(true)),
+        (Command::GetInvertScreen, Response::InvertScreen(false)),
+        (Command::GetScreenSaver, Response::ScreenSaver(true)),
+        (Command::GetFps, Response::Fps(3)),
+        (Command::GetSide, Response::Side(Side::Right)),
+        (Command::GetPowerMode, Response::PowerMode(DisplayMode::Lpm)),
+        (Command::GetColor, Response::Color([0xFF, 0x80, 0x00])),
+        (
+            Command::GameStatus,
+            Response::GameStatus(GameStatus::default()),
+        ),
+        (
+            Command::GameStatus,
+            Response::GameStatus(GameStatus {
+                game: Some(GameVal::Tetris),
+                game_over: true,
+                score: 1234,
+                level: 5,
+                snake_length: 0,
+                pong_score: [0, 0],
+            }),
+        ),
+        (
+            Command::Version,
+            Response::Version(Version::from_bcd(0x0123, true)),
+        ),
+    ];
+    for (command, response) in pairs {
+        assert!(command.has_response());
+        assert_eq!(command.decode_response(&response.encode()), Some(response));
+    }
+    assert!(!Command::SetBrightness(1).has_response());
+}
+
+#[test]
+fn version_bcd() {
+    let version = Version::from_bcd(0x0123, false);
+    assert_eq!((version.major, version.minor, version.patch), (1, 2, 3));
+    assert_eq!(
+        &Response::Version(version).encode()[..3],
+        &[0x01, 0x23, 0x00]
+    );
+}
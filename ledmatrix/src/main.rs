@@ -114,7 +114,8 @@ use fl16_inputmodules::animations::*;
 use fl16_inputmodules::fl16::EVT_CALC_PIXEL;
 use fl16_inputmodules::games::pong_animation::*;
 use fl16_inputmodules::games::snake_animation::*;
-use fl16_inputmodules::{addon, games::game_of_life, led_hal as bsp};
+use fl16_inputmodules::games::tetris_animation::*;
+use fl16_inputmodules::{games, led_hal as bsp};
 use is31fl3741::devices::LedMatrix;
 #[cfg(not(feature = "evt"))]
 use is31fl3741::devices::CALC_PIXEL;
@@ -143,10 +144,10 @@ use usbd_serial::{SerialPort, USB_CLASS_CDC};
 use core::fmt::Write;
 use fl16_inputmodules::addon::AddonAnimation;
 use fl16_inputmodules::control::*;
-use fl16_inputmodules::games::{pong, snake};
 use fl16_inputmodules::matrix::*;
 use fl16_inputmodules::patterns::*;
 use fl16_inputmodules::serialnum::{device_release, get_serialnum};
+use fl16_inputmodules::settings::*;
 use heapless::{String, Vec};
 
 //                            FRA                - Framwork
@@ -244,22 +245,33 @@ fn main() -> ! {
         visual_keypress_life: 10,
         timer: 0,
         addon_animation: None,
-        side: Side::Left,
+        side: DEFAULT_SIDE,
 
         grid: percentage(0),
         col_buffer: Grid::default(),
         animate: false,
-        brightness: 51, // Default to 51/255 = 20% brightness
+        brightness: DEFAULT_BRIGHTNESS,
+        gamma: DEFAULT_GAMMA,
+        gamma_lut: LINEAR_GAMMA_LUT,
+        custom_gamma_lut: LINEAR_GAMMA_LUT,
+        orientation: DEFAULT_ORIENTATION,
         sleeping: SleepState::Awake,
         game: None,
-        animation_period: 31_250, // 31,250 us = 32 FPS
-        pwm_freq: PwmFreqArg::P29k,
+        animation_period: DEFAULT_ANIMATION_PERIOD,
+        pwm_freq: DEFAULT_PWM_FREQ,
         debug_mode: false,
         upcoming_frames: None,
+        custom_frames: Vec::new(),
     };
-    state.debug_mode = dip1.is_low().unwrap();
+    let mut settings = SettingsStore::new(RomFlash);
+    state.load_settings(&settings);
+    // The DIP switch turns on debug mode, even if it's off in the saved settings
+    let mut last_dip1 = dip1.is_low().unwrap();
+    if last_dip1 {
+        state.debug_mode = true;
+    }
     if show_startup_animation(&state) {
-        state.upcoming_frames = Some(match get_random_byte(&rosc) % 8 {
+        state.upcoming_frames = Some(match get_random_byte(&rosc) % 9 {
             0 => Animation::Percentage(StartupPercentageIterator::default()),
             1 => Animation::ZigZag(ZigZagIterator::default()),
             2 => Animation::Gof(GameOfLifeIterator::new(GameOfLifeStartParam::Pattern1, 200)),
@@ -271,6 +283,7 @@ fn main() -> ! {
             5 => Animation::Breathing(BreathingIterator::default()),
             6 => Animation::Pong(PongIterator::default()),
             7 => Animation::Snake(SnakeIterator::default()),
+            8 => Animation::Tetris(TetrisIterator::default()),
             _ => unreachable!(),
         });
     } else {
@@ -303,7 +316,10 @@ fn main() -> ! {
         .set_scaling(MAX_BRIGHTNESS)
         .expect("failed to set scaling");
 
-    matrix.device.set_pwm_freq(state.pwm_freq.into()).unwrap();
+    matrix
+        .device
+        .set_pwm_freq(pwm_freq(state.pwm_freq))
+        .unwrap();
 
     fill_grid_pixels(&state, &mut matrix);
 
@@ -331,10 +347,17 @@ fn main() -> ! {
     let mut last_sleep_reason: Option<SleepReason>;
     let mut last_host_sleep = sleep.is_low().unwrap();
 
+    let mut frame_decoder = FrameDecoder::new();
+
     loop {
         last_sleep_reason = sleep_reason;
 
-        state.debug_mode = dip1.is_low().unwrap();
+        // Only follow the DIP switch when it's flipped, to keep the saved setting otherwise
+        let dip1_low = dip1.is_low().unwrap();
+        if dip1_low != last_dip1 {
+            state.debug_mode = dip1_low;
+            last_dip1 = dip1_low;
+        }
         if sleep_present {
             // Go to sleep if the host is sleeping
             let host_sleeping = sleep.is_low().unwrap();
@@ -403,39 +426,13 @@ fn main() -> ! {
         // Handle period display updates. Don't do it too often
         let render_again = timer.get_counter().ticks() > animation_timer + state.animation_period;
         if matches!(state.sleeping, SleepState::Awake) && render_again {
-            if let Some(ref mut upcoming) = state.upcoming_frames {
-                if let Some(next_frame) = upcoming.next() {
-                    state.grid = next_frame;
-                } else {
-                    // Animation is over. Clear screen
-                    state.grid = Grid::default();
-                }
-            }
-
-            fill_grid_pixels(&state, &mut matrix);
-            if state.animate {
-                for x in 0..WIDTH {
-                    state.grid.0[x].rotate_right(1);
-                }
-            }
-
-            // manage visual keypresses
-            for keypress in state.visual_keypresses.iter_mut() {
-                if keypress.alive {
-                    continue;
-                }
-                keypress.life -= 1;
-            }
-            state.visual_keypresses.retain(|kp| kp.life > 0);
-
-            if let Some(addon_animation) = &state.addon_animation {
-                state.grid = addon::draw_addon_animation(&state, addon_animation);
-            }
-
+            state.render(&mut matrix);
             animation_timer = timer.get_counter().ticks();
-            state.timer += 1;
         }
 
+        // Drop a frame that was cut off, the host gave up on it
+        frame_decoder.expire(timer.get_counter().ticks());
+
         // Check for new data
         if usb_dev.poll(&mut [&mut serial]) {
             match usb_dev.state() {
@@ -465,15 +462,25 @@ fn main() -> ! {
                     // Do nothing
                 }
                 Ok(count) => {
-                    let random = get_random_byte(&rosc);
-                    match (parse_command(count, &buf), &state.sleeping) {
-                        // Handle bootloader command without any delay
-                        // No need, it'll reset the device anyways
-                        (Some(c @ Command::BootloaderReset), _) => {
-                            handle_command(&c, &mut state, &mut matrix, random);
-                        }
-                        (Some(command), _) => {
-                            if let Command::Sleep(go_sleeping) = command {
+                    frame_decoder.push_at(&buf[..count], timer.get_counter().ticks());
+                    while let Some(received) = frame_decoder.next_received() {
+                        let (reply, command) = received.into_command();
+                        let random = get_random_byte(&rosc);
+                        let mut reply_buf = [0u8; MAX_FRAME_SIZE];
+                        match (command, &state.sleeping) {
+                            // Handle bootloader command without any delay
+                            // No need, it'll reset the device anyways
+                            (Ok(c @ Command::BootloaderReset), _) => {
+                                let mut handler = LedmatrixHandler {
+                                    state: &mut state,
+                                    matrix: &mut matrix,
+                                    settings: &mut settings,
+                                    random,
+                                };
+                                let _ = handler.handle_command(&c);
+                            }
+                            (Ok(command), _) => {
+                                let go_sleeping = state.receive_command(&command);
                                 sleep_reason = assign_sleep_reason(
                                     last_sleep_reason,
                                     sleep_reason,
@@ -481,54 +488,45 @@ fn main() -> ! {
                                     true,
                                     SleepReason::Command,
                                 );
-                            } else {
-                                // If already sleeping, wake up.
-                                // This means every command will wake the device up.
-                                // Much more convenient than having to send the wakeup commmand.
-                                sleep_reason = None;
+                                handle_sleep(
+                                    sleep_reason,
+                                    &mut state,
+                                    &mut matrix,
+                                    &mut delay,
+                                    &mut led_enable,
+                                );
+
+                                // Reset sleep timer when interacting with the device
+                                // Very easy way to keep the device from going to sleep
+                                sleep_timer = timer.get_counter().ticks();
+
+                                let mut handler = LedmatrixHandler {
+                                    state: &mut state,
+                                    matrix: &mut matrix,
+                                    settings: &mut settings,
+                                    random,
+                                };
+                                let result = handler.handle_command(&command);
+                                let len = reply.respond(&result, &mut reply_buf);
+                                let _ = serial.write(&reply_buf[..len]);
+                                // Must write AFTER writing response, otherwise the
+                                // client interprets this debug message as the response
+                                let mut text: String<64> = String::new();
+                                write!(
+                                    &mut text,
+                                    "Handled command {}:{}:{}:{}\r\n",
+                                    buf[0], buf[1], buf[2], buf[3]
+                                )
+                                .unwrap();
+                                // let _ = serial.write(text.as_bytes());
+
+                                fill_grid_pixels(&state, &mut matrix);
                             }
-                            // Make sure sleep animation only goes up to newly set brightness,
-                            // if setting the brightness causes wakeup
-                            if let SleepState::Sleeping((ref grid, _)) = state.sleeping {
-                                if let Command::SetBrightness(new_brightness) = command {
-                                    state.sleeping =
-                                        SleepState::Sleeping((grid.clone(), new_brightness));
-                                }
+                            (Err(code), _) => {
+                                let len = reply.nak(code, &mut reply_buf);
+                                let _ = serial.write(&reply_buf[..len]);
                             }
-                            handle_sleep(
-                                sleep_reason,
-                                &mut state,
-                                &mut matrix,
-                                &mut delay,
-                                &mut led_enable,
-                            );
-
-                            // If there's a very early command, cancel the startup animation
-                            state.upcoming_frames = None;
-
-                            // Reset sleep timer when interacting with the device
-                            // Very easy way to keep the device from going to sleep
-                            sleep_timer = timer.get_counter().ticks();
-
-                            if let Some(response) =
-                                handle_command(&command, &mut state, &mut matrix, random)
-                            {
-                                let _ = serial.write(&response);
-                            };
-                            // Must write AFTER writing response, otherwise the
-                            // client interprets this debug message as the response
-                            let mut text: String<64> = String::new();
-                            write!(
-                                &mut text,
-                                "Handled command {}:{}:{}:{}\r\n",
-                                buf[0], buf[1], buf[2], buf[3]
-                            )
-                            .unwrap();
-                            // let _ = serial.write(text.as_bytes());
-
-                            fill_grid_pixels(&state, &mut matrix);
                         }
-                        (None, _) => {}
                     }
                 }
             }
@@ -550,43 +548,9 @@ fn main() -> ! {
         }
 
         // Handle game state
-        let game_step_diff = match state.game {
-            Some(GameState::Pong(ref pong_state)) => 100_000 - 5_000 * pong_state.speed,
-            Some(GameState::Snake(_)) => 500_000,
-            Some(GameState::GameOfLife(_)) => 500_000,
-            _ => 500_000,
-        };
-        if timer.get_counter().ticks() > game_timer + game_step_diff {
+        if timer.get_counter().ticks() > game_timer + state.game_period() {
             let random = get_random_byte(&rosc);
-            match state.game {
-                Some(GameState::GameOfLife(_)) => {
-                    let _ = serial.write(b"GOL Game step\r\n");
-                    game_of_life::game_step(&mut state, random);
-                }
-                Some(GameState::Pong(_)) => {
-                    let _ = serial.write(b"Pong Game step\r\n");
-                    pong::game_step(&mut state, random);
-                }
-                Some(GameState::Snake(_)) => {
-                    let _ = serial.write(b"Snake Game step\r\n");
-                    let (direction, game_over, points, (x, y)) =
-                        snake::game_step(&mut state, random);
-
-                    if game_over {
-                        // TODO: Show score
-                    } else {
-                        let mut text: String<64> = String::new();
-                        write!(
-                            &mut text,
-                            "Dir: {:?} Status: {}, Points: {}, Head: ({},{})\r\n",
-                            direction, game_over, points, x, y
-                        )
-                        .unwrap();
-                        let _ = serial.write(text.as_bytes());
-                    }
-                }
-                None => {}
-            }
+            games::game_step(&mut state, random);
             game_timer = timer.get_counter().ticks();
         }
     }
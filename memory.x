@@ -1,6 +1,8 @@
 MEMORY {
     BOOT2 : ORIGIN = 0x10000000, LENGTH = 0x100
-    FLASH : ORIGIN = 0x10000100, LENGTH = 2048K - 0x104
+    FLASH : ORIGIN = 0x10000100, LENGTH = 0xFD000 - 0x100
+    /* Persistent settings, see flash_layout.md */
+    SETTINGS : ORIGIN = 0x100FD000, LENGTH = 8K
     /* Serial number - programmed at manufacturing, read-only */
     SERIALNUM : ORIGIN = 0x100FF000, LENGTH = 4K
     RAM   : ORIGIN = 0x20000000, LENGTH = 256K